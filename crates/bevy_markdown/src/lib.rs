@@ -6,6 +6,8 @@ use syntect::highlighting::{FontStyle, ThemeSet};
 use syntect::parsing::SyntaxSet;
 use syntect::util::LinesWithEndings;
 
+pub use markdown;
+
 pub struct BevyMarkdownTheme {
     pub code_theme: String,
     pub code_default_lang: String,
@@ -0,0 +1,11 @@
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(message) = velo::cli::run(&args, &mut std::io::stdout()) {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
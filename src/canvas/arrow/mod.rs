@@ -1,7 +1,7 @@
 pub mod components;
 pub mod events;
 mod systems;
-mod utils;
+pub mod utils;
 use bevy::{
     app::{App, Plugin},
     prelude::PreUpdate,
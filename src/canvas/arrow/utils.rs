@@ -2,7 +2,10 @@ use std::f32::consts::PI;
 
 use bevy::prelude::*;
 use bevy_prototype_lyon::{
-    prelude::{GeometryBuilder, Path, ShapeBundle, Stroke},
+    prelude::{
+        tess::path::{iterator::PathIterator, PathEvent},
+        GeometryBuilder, Path, ShapeBundle, Stroke,
+    },
     shapes,
 };
 
@@ -143,3 +146,25 @@ pub fn build_arrow(start: Vec2, end: Vec2, arrow_meta: ArrowMeta) -> Path {
         }
     }
 }
+
+/// Flattens the arrow geometry into polylines, for rendering without a GPU.
+pub fn arrow_polylines(start: Vec2, end: Vec2, arrow_meta: ArrowMeta) -> Vec<Vec<Vec2>> {
+    let mut lines: Vec<Vec<Vec2>> = vec![];
+    for event in build_arrow(start, end, arrow_meta).0.iter().flattened(0.1) {
+        match event {
+            PathEvent::Begin { at } => lines.push(vec![Vec2::new(at.x, at.y)]),
+            PathEvent::Line { to, .. } => {
+                if let Some(line) = lines.last_mut() {
+                    line.push(Vec2::new(to.x, to.y));
+                }
+            }
+            PathEvent::End { first, close, .. } => {
+                if let (Some(line), true) = (lines.last_mut(), close) {
+                    line.push(Vec2::new(first.x, first.y));
+                }
+            }
+            PathEvent::Quadratic { .. } | PathEvent::Cubic { .. } => {}
+        }
+    }
+    lines
+}
@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use crate::components::{Doc, Tab};
+use crate::export::{checkpoint_to_markdown, checkpoint_to_png, checkpoint_to_svg};
+use crate::format::{Checkpoint, DocFile};
+use crate::store::{import_images, open_store, update_index_entry, DocEntry, DocumentStore};
+use crate::themes::velo_light;
+use crate::ui_plugin::{fuzzy_search, initialize_search_index, search_index_dir};
+use crate::utils::{get_timestamp, ReflectableUuid};
+
+pub const USAGE: &str = "Usage: velo-cli <command> [args]
+
+<doc> is either a path to an exported .json file or the id/name of a document in the app store.
+
+Commands:
+  list [doc]                        List documents and their tabs
+  export-tab <doc> <tab> <output> [scale]
+                                    Write a single tab to a new document file (.json),
+                                    an outline (.md) or an image (.svg, .png at the given scale)
+  merge <doc> <other>               Append the tabs of <other> to <doc>
+  rename <doc> <name>               Rename a document
+  retag <doc> [tag]...              Replace the tags of a document
+  search <query>                    Fuzzy search node text in the app search index";
+
+enum DocSource {
+    File(PathBuf),
+    Store,
+}
+
+#[derive(Default)]
+struct Store {
+    store: Option<Box<dyn DocumentStore>>,
+    /// Images carried by the document files loaded so far.
+    file_images: BTreeMap<String, String>,
+}
+
+impl Store {
+    // opened lazily so that commands working on files never touch the app data
+    fn store(&mut self) -> &mut dyn DocumentStore {
+        self.store.get_or_insert_with(open_store).as_mut()
+    }
+
+    fn index(&mut self) -> HashMap<ReflectableUuid, DocEntry> {
+        self.store().index().unwrap_or_default()
+    }
+
+    fn docs(&mut self) -> Vec<Doc> {
+        let ids: Vec<ReflectableUuid> = self.index().into_keys().collect();
+        let store = self.store();
+        ids.iter().filter_map(|id| store.doc(id).ok()).collect()
+    }
+
+    /// Looks in the loaded files, then in the store if a stored document was loaded.
+    fn image(&self, hash: &str) -> Option<String> {
+        if let Some(data) = self.file_images.get(hash) {
+            return Some(data.clone());
+        }
+        self.store.as_ref().and_then(|store| store.image(hash).ok())
+    }
+
+    fn load(&mut self, doc_ref: &str) -> Result<(DocSource, Doc), String> {
+        let path = Path::new(doc_ref);
+        if path.is_file() {
+            let json = std::fs::read_to_string(path)
+                .map_err(|e| format!("Error reading {}: {}", doc_ref, e))?;
+            let mut doc_file = DocFile::from_json(&json)
+                .map_err(|e| format!("Error parsing {}: {}", doc_ref, e))?;
+            self.file_images.append(&mut doc_file.images);
+            return Ok((DocSource::File(path.to_path_buf()), doc_file.doc));
+        }
+        let index = self.index();
+        if let Ok(id) = Uuid::parse_str(doc_ref) {
+            if index.contains_key(&ReflectableUuid(id)) {
+                return self.load_stored(&ReflectableUuid(id));
+            }
+        }
+        let mut matches = index.iter().filter(|(_, entry)| entry.name == doc_ref);
+        match (matches.next(), matches.next()) {
+            (Some((id, _)), None) => self.load_stored(id),
+            (Some(_), Some(_)) => Err(format!(
+                "Document name {} is ambiguous, use its id instead",
+                doc_ref
+            )),
+            (None, _) => Err(format!("Document not found: {}", doc_ref)),
+        }
+    }
+
+    fn load_stored(&mut self, id: &ReflectableUuid) -> Result<(DocSource, Doc), String> {
+        let doc = self
+            .store()
+            .doc(id)
+            .map_err(|e| format!("Error loading {}: {}", id.0, e))?;
+        Ok((DocSource::Store, doc))
+    }
+
+    fn save(&mut self, source: &DocSource, doc: &Doc) -> Result<(), String> {
+        match source {
+            DocSource::File(path) => {
+                let doc_file = DocFile::new(doc.clone(), |hash| self.image(hash));
+                std::fs::write(path, doc_file.to_json_pretty())
+                    .map_err(|e| format!("Error writing {}: {}", path.display(), e))
+            }
+            DocSource::Store => {
+                let mut index = self.index();
+                update_index_entry(&mut index, doc, Some(get_timestamp()));
+                let file_images = std::mem::take(&mut self.file_images);
+                let store = self.store();
+                import_images(store, &file_images)
+                    .and_then(|_| store.set_doc(doc))
+                    .and_then(|_| store.set_index(&index))
+                    .map_err(|e| format!("Error saving to store: {}", e))
+            }
+        }
+    }
+}
+
+/// Runs a single `velo-cli` command, `args` excludes the binary name.
+pub fn run(args: &[String], out: &mut impl Write) -> Result<(), String> {
+    let mut store = Store::default();
+    let (command, rest) = args.split_first().ok_or(USAGE)?;
+    match (command.as_str(), rest) {
+        ("list", []) => {
+            let mut docs = store.docs();
+            docs.sort_by(|a, b| a.name.cmp(&b.name));
+            for doc in docs.iter() {
+                print_doc(doc, out)?;
+            }
+            Ok(())
+        }
+        ("list", [doc_ref]) => {
+            let (_, doc) = store.load(doc_ref)?;
+            print_doc(&doc, out)
+        }
+        ("export-tab", [doc_ref, tab_ref, output, scale @ ..]) if scale.len() <= 1 => {
+            let (_, doc) = store.load(doc_ref)?;
+            let tab = find_tab(&doc, tab_ref)?;
+            let checkpoint = |tab: &Tab| {
+                last_checkpoint(tab).map(|mut checkpoint| {
+                    checkpoint.inline_images(|hash| store.image(hash));
+                    checkpoint
+                })
+            };
+            let contents = match Path::new(output).extension().and_then(|e| e.to_str()) {
+                Some("svg") => checkpoint_to_svg(&checkpoint(tab)?, &velo_light()).into_bytes(),
+                Some("png") => {
+                    let scale = match scale.first() {
+                        Some(scale) => scale
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|scale| *scale > 0.)
+                            .ok_or_else(|| format!("Invalid scale: {}", scale))?,
+                        None => 1.,
+                    };
+                    checkpoint_to_png(&checkpoint(tab)?, &velo_light(), scale)
+                        .map_err(|e| format!("Error rendering png: {}", e))?
+                }
+                Some("md") => checkpoint_to_markdown(&checkpoint(tab)?).into_bytes(),
+                _ => DocFile::new(export_tab(&doc, tab), |hash| store.image(hash))
+                    .to_json_pretty()
+                    .into_bytes(),
+            };
+            std::fs::write(output, contents).map_err(|e| format!("Error writing {}: {}", output, e))
+        }
+        ("merge", [doc_ref, other_ref]) => {
+            let (source, mut doc) = store.load(doc_ref)?;
+            let (_, other) = store.load(other_ref)?;
+            unlocked(&doc)?;
+            unlocked(&other)?;
+            merge_docs(&mut doc, other);
+            store.save(&source, &doc)
+        }
+        ("rename", [doc_ref, name]) => {
+            let (source, mut doc) = store.load(doc_ref)?;
+            doc.name = name.clone();
+            store.save(&source, &doc)
+        }
+        ("retag", [doc_ref, tags @ ..]) => {
+            let (source, mut doc) = store.load(doc_ref)?;
+            doc.tags = vec![];
+            for tag in tags {
+                if !doc.tags.contains(tag) {
+                    doc.tags.push(tag.clone());
+                }
+            }
+            store.save(&source, &doc)
+        }
+        ("search", query) if !query.is_empty() => {
+            let index = initialize_search_index(search_index_dir());
+            let results = fuzzy_search(&index, &query.join(" "))
+                .map_err(|e| format!("Error searching index: {:?}", e))?;
+            let index = store.index();
+            for location in results {
+                let doc_name = index
+                    .get(&ReflectableUuid(location.doc_id))
+                    .map_or("Unknown", |entry| entry.name.as_str());
+                writeln!(
+                    out,
+                    "{}  {}  tab {}  node {}",
+                    location.doc_id, doc_name, location.tab_id, location.node_id
+                )
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+fn print_doc(doc: &Doc, out: &mut impl Write) -> Result<(), String> {
+    writeln!(
+        out,
+        "{}  {}  [{}]{}",
+        doc.id.0,
+        doc.name,
+        doc.tags.join(", "),
+        if doc.sealed.is_some() { "  locked" } else { "" }
+    )
+    .map_err(|e| e.to_string())?;
+    for tab in doc.tabs.iter() {
+        let nodes = tab
+            .checkpoints
+            .back()
+            .and_then(|json| Checkpoint::from_json(json).ok())
+            .map_or(0, |checkpoint| checkpoint.nodes.len());
+        writeln!(
+            out,
+            "    {}  {}  ({} nodes){}",
+            tab.id.0,
+            tab.name,
+            nodes,
+            if tab.is_active { "  *" } else { "" }
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The tabs of locked documents can only be read in the app.
+fn unlocked(doc: &Doc) -> Result<(), String> {
+    match doc.sealed {
+        Some(_) => Err(format!("Document {} is locked", doc.name)),
+        None => Ok(()),
+    }
+}
+
+fn find_tab<'a>(doc: &'a Doc, tab_ref: &str) -> Result<&'a Tab, String> {
+    unlocked(doc)?;
+    doc.tabs
+        .iter()
+        .find(|tab| tab.id.0.to_string() == tab_ref || tab.name == tab_ref)
+        .ok_or_else(|| format!("Tab not found in {}: {}", doc.name, tab_ref))
+}
+
+fn last_checkpoint(tab: &Tab) -> Result<Checkpoint, String> {
+    match tab.checkpoints.back() {
+        Some(json) => Checkpoint::from_json(json)
+            .map_err(|e| format!("Error parsing tab {}: {}", tab.name, e)),
+        None => Ok(Checkpoint::default()),
+    }
+}
+
+fn export_tab(doc: &Doc, tab: &Tab) -> Doc {
+    Doc {
+        id: ReflectableUuid::generate(),
+        name: format!("{} - {}", doc.name, tab.name),
+        tabs: vec![Tab {
+            is_active: true,
+            ..tab.clone()
+        }],
+        tags: doc.tags.clone(),
+        sealed: None,
+    }
+}
+
+fn merge_docs(doc: &mut Doc, other: Doc) {
+    let tab_ids: HashSet<ReflectableUuid> = doc.tabs.iter().map(|tab| tab.id).collect();
+    for mut tab in other.tabs {
+        if tab_ids.contains(&tab.id) {
+            tab.id = ReflectableUuid::generate();
+        }
+        tab.is_active = false;
+        doc.tabs.push(tab);
+    }
+    for tag in other.tags {
+        if !doc.tags.contains(&tag) {
+            doc.tags.push(tag);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use tempfile::tempdir;
+
+    fn write_doc(dir: &Path, file_name: &str, name: &str, tags: &[&str]) -> String {
+        let mut checkpoints = VecDeque::new();
+        checkpoints.push_back(Checkpoint::default().to_json());
+        let doc = Doc {
+            id: ReflectableUuid::generate(),
+            name: name.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            tabs: vec![Tab {
+                id: ReflectableUuid::generate(),
+                is_active: true,
+                name: "Tab 1".to_string(),
+                checkpoints,
+                z_index: 1.,
+                history: vec![],
+            }],
+            sealed: None,
+        };
+        let path = dir.join(file_name);
+        std::fs::write(&path, DocFile::new(doc, |_| None).to_json_pretty()).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn read_doc(path: &str) -> Doc {
+        DocFile::from_json(&std::fs::read_to_string(path).unwrap())
+            .unwrap()
+            .doc
+    }
+
+    fn run_args(args: &[&str]) -> Result<String, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let mut out = Vec::new();
+        run(&args, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn test_rename_and_retag_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = write_doc(temp_dir.path(), "a.json", "Retro", &["team"]);
+
+        run_args(&["rename", &path, "Retro 2023"]).unwrap();
+        run_args(&["retag", &path, "retro", "team", "retro"]).unwrap();
+
+        let doc = read_doc(&path);
+        assert_eq!(doc.name, "Retro 2023");
+        assert_eq!(doc.tags, vec!["retro".to_string(), "team".to_string()]);
+    }
+
+    #[test]
+    fn test_merge_files() {
+        let temp_dir = tempdir().unwrap();
+        let path_a = write_doc(temp_dir.path(), "a.json", "A", &["x"]);
+        let path_b = write_doc(temp_dir.path(), "b.json", "B", &["x", "y"]);
+
+        run_args(&["merge", &path_a, &path_b]).unwrap();
+
+        let doc = read_doc(&path_a);
+        assert_eq!(doc.tabs.len(), 2);
+        assert_eq!(doc.tabs.iter().filter(|tab| tab.is_active).count(), 1);
+        assert_eq!(doc.tags, vec!["x".to_string(), "y".to_string()]);
+    }
+
+    #[test]
+    fn test_list_and_export_tab() {
+        let temp_dir = tempdir().unwrap();
+        let path = write_doc(temp_dir.path(), "a.json", "Board", &[]);
+        let output = temp_dir.path().join("tab.json");
+
+        let listing = run_args(&["list", &path]).unwrap();
+        assert!(listing.contains("Board"));
+        assert!(listing.contains("Tab 1  (0 nodes)  *"));
+
+        run_args(&["export-tab", &path, "Tab 1", output.to_str().unwrap()]).unwrap();
+        let exported = read_doc(output.to_str().unwrap());
+        assert_eq!(exported.name, "Board - Tab 1");
+        assert_eq!(exported.tabs.len(), 1);
+
+        let svg = temp_dir.path().join("tab.svg");
+        run_args(&["export-tab", &path, "Tab 1", svg.to_str().unwrap()]).unwrap();
+        assert!(std::fs::read_to_string(svg).unwrap().starts_with("<svg"));
+
+        let png = temp_dir.path().join("tab.png");
+        run_args(&["export-tab", &path, "Tab 1", png.to_str().unwrap(), "2"]).unwrap();
+        assert!(std::fs::read(png).unwrap().starts_with(b"\x89PNG"));
+        assert!(run_args(&["export-tab", &path, "Tab 1", "tab.png", "-1"]).is_err());
+
+        let md = temp_dir.path().join("tab.md");
+        run_args(&["export-tab", &path, "Tab 1", md.to_str().unwrap()]).unwrap();
+        assert_eq!(std::fs::read_to_string(md).unwrap(), "");
+    }
+
+    #[test]
+    fn test_locked_file() {
+        let temp_dir = tempdir().unwrap();
+        let path = write_doc(temp_dir.path(), "a.json", "Secrets", &[]);
+        let doc = crate::crypto::seal_doc(&read_doc(&path), "passphrase", |_| None).unwrap();
+        std::fs::write(&path, DocFile::new(doc, |_| None).to_json_pretty()).unwrap();
+
+        assert!(run_args(&["list", &path])
+            .unwrap()
+            .contains("Secrets  []  locked"));
+        let output = temp_dir.path().join("tab.json");
+        let result = run_args(&["export-tab", &path, "Tab 1", output.to_str().unwrap()]);
+        assert_eq!(result, Err("Document Secrets is locked".to_string()));
+        run_args(&["rename", &path, "More secrets"]).unwrap();
+        assert!(read_doc(&path).sealed.is_some());
+    }
+
+    #[test]
+    fn test_unknown_command() {
+        assert_eq!(run_args(&["frobnicate"]), Err(USAGE.to_string()));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::crypto::Sealed;
 use crate::utils::ReflectableUuid;
 use bevy::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -8,19 +9,66 @@ pub struct MainCamera;
 #[derive(Component)]
 pub struct EffectsCamera;
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Tab {
     pub is_active: bool,
     pub id: ReflectableUuid,
     pub name: String,
     pub checkpoints: VecDeque<String>,
     pub z_index: f32,
+    /// States older than `checkpoints` and named snapshots, oldest first.
+    #[serde(default)]
+    pub history: Vec<Snapshot>,
 }
 
-#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+/// A past state of a tab. Unnamed snapshots are compacted as they age, named ones are
+/// kept as they are.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub name: Option<String>,
+    /// Milliseconds since the epoch, as returned by `get_timestamp`.
+    pub timestamp: f64,
+    pub checkpoint: String,
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Doc {
     pub tabs: Vec<Tab>,
     pub id: ReflectableUuid,
     pub name: String,
     pub tags: Vec<String>,
+    /// Tabs and images of a locked document, encrypted with its passphrase. `tabs` stays
+    /// empty while the document is sealed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed: Option<Sealed>,
+}
+
+#[cfg(test)]
+impl Doc {
+    /// A document with one active tab, "Tab 1", holding `checkpoints` oldest first.
+    pub fn test(name: &str, checkpoints: &[&str]) -> Self {
+        Self {
+            id: ReflectableUuid::generate(),
+            name: name.to_string(),
+            tabs: vec![Tab {
+                is_active: true,
+                id: ReflectableUuid::generate(),
+                name: "Tab 1".to_string(),
+                checkpoints: checkpoints.iter().map(|c| c.to_string()).collect(),
+                z_index: 1.,
+                history: vec![],
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// The same document under `id`, its tab too, so that documents built apart can
+    /// stand for the same one.
+    pub fn with_id(mut self, id: ReflectableUuid) -> Self {
+        self.id = id;
+        for tab in self.tabs.iter_mut() {
+            tab.id = id;
+        }
+        self
+    }
 }
@@ -0,0 +1,184 @@
+//! Encryption of locked documents at rest. The key is derived from the passphrase with
+//! Argon2 and the content is sealed with XChaCha20-Poly1305.
+
+use std::collections::{BTreeMap, HashMap};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::components::{Doc, Tab};
+use crate::error::VeloError;
+use crate::format::DocFile;
+use crate::utils::ReflectableUuid;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Encrypted bytes and what it takes besides the passphrase to decrypt them, as base64.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Sealed {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// What a locked document keeps encrypted. Its images go along, they are kept out of
+/// the image store, which has them in the clear, while the document is locked.
+#[derive(Serialize, Deserialize)]
+struct SealedContent {
+    tabs: Vec<Tab>,
+    #[serde(default)]
+    images: BTreeMap<String, String>,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32], VeloError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| VeloError::Crypto(e.to_string()))?;
+    Ok(key)
+}
+
+pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<Sealed, VeloError> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(passphrase, &salt)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(&nonce, plaintext)
+        .map_err(|e| VeloError::Crypto(e.to_string()))?;
+    Ok(Sealed {
+        salt: general_purpose::STANDARD.encode(salt),
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(ciphertext),
+    })
+}
+
+pub fn unseal(sealed: &Sealed, passphrase: &str) -> Result<Vec<u8>, VeloError> {
+    let decode = |value: &str| {
+        general_purpose::STANDARD
+            .decode(value)
+            .map_err(|e| VeloError::InvalidDocument(e.to_string()))
+    };
+    let nonce = decode(&sealed.nonce)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(VeloError::InvalidDocument("invalid nonce".to_string()));
+    }
+    let key = derive_key(passphrase, &decode(&sealed.salt)?)?;
+    XChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(
+            XNonce::from_slice(&nonce),
+            decode(&sealed.ciphertext)?.as_ref(),
+        )
+        .map_err(|_| VeloError::Crypto("wrong passphrase".to_string()))
+}
+
+/// The document with its tabs and their images sealed, only the name and tags stay in
+/// the clear for the document list. `lookup` finds images, usually in the image store.
+pub fn seal_doc(
+    doc: &Doc,
+    passphrase: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Doc, VeloError> {
+    let doc_file = DocFile::new(doc.clone(), lookup);
+    let content = SealedContent {
+        tabs: doc_file.doc.tabs,
+        images: doc_file.images,
+    };
+    Ok(Doc {
+        id: doc.id,
+        name: doc.name.clone(),
+        tags: doc.tags.clone(),
+        tabs: vec![],
+        sealed: Some(seal(&serde_json::to_vec(&content)?, passphrase)?),
+    })
+}
+
+/// Opens a sealed document, returns it along with the images it carries.
+pub fn unseal_doc(
+    doc: &Doc,
+    passphrase: &str,
+) -> Result<(Doc, BTreeMap<String, String>), VeloError> {
+    let Some(sealed) = &doc.sealed else {
+        return Ok((doc.clone(), BTreeMap::new()));
+    };
+    let content: SealedContent = serde_json::from_slice(&unseal(sealed, passphrase)?)?;
+    let doc = Doc {
+        id: doc.id,
+        name: doc.name.clone(),
+        tags: doc.tags.clone(),
+        tabs: content.tabs,
+        sealed: None,
+    };
+    Ok((doc, content.images))
+}
+
+/// The document as it goes to the store, the journal, the trash or a file: sealed if it
+/// has a passphrase.
+pub fn doc_at_rest(
+    doc: &Doc,
+    passphrases: &HashMap<ReflectableUuid, String>,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> Result<Doc, VeloError> {
+    match passphrases.get(&doc.id) {
+        Some(passphrase) => seal_doc(doc, passphrase, lookup),
+        None => Ok(doc.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::Checkpoint;
+
+    fn doc() -> Doc {
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.nodes.push(crate::JsonNode {
+            text: crate::JsonNodeText {
+                text: "root password".to_string(),
+                pos: crate::TextPos::Center,
+            },
+            ..Default::default()
+        });
+        checkpoint.insert_image("node".to_string(), "aW1n".to_string());
+        checkpoint.blobs.clear();
+        Doc {
+            tags: vec!["ops".to_string()],
+            ..Doc::test("Incident", &[checkpoint.to_json().as_str()])
+        }
+    }
+
+    #[test]
+    fn test_seal_and_unseal_doc() {
+        let doc = doc();
+        let hash = crate::utils::image_hash("aW1n");
+        let lookup = |h: &str| (h == hash).then(|| "aW1n".to_string());
+        let sealed = seal_doc(&doc, "correct horse", lookup).unwrap();
+        assert!(sealed.tabs.is_empty());
+        assert_eq!(sealed.name, "Incident");
+        let json = serde_json::to_string(&sealed).unwrap();
+        assert!(!json.contains("root password"));
+        assert!(!json.contains(&hash));
+
+        assert!(unseal_doc(&sealed, "wrong horse").is_err());
+        let (unsealed, images) = unseal_doc(&sealed, "correct horse").unwrap();
+        assert_eq!(unsealed, doc);
+        assert_eq!(images, BTreeMap::from([(hash, "aW1n".to_string())]));
+    }
+
+    #[test]
+    fn test_doc_at_rest() {
+        let doc = doc();
+        let mut passphrases = HashMap::new();
+        assert_eq!(doc_at_rest(&doc, &passphrases, |_| None).unwrap(), doc);
+        passphrases.insert(doc.id, "passphrase".to_string());
+        let first = doc_at_rest(&doc, &passphrases, |_| None).unwrap();
+        let second = doc_at_rest(&doc, &passphrases, |_| None).unwrap();
+        // a fresh salt and nonce every time
+        assert_ne!(first.sealed, second.sealed);
+    }
+}
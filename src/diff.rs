@@ -0,0 +1,358 @@
+//! What changed between two checkpoints of a tab, element by element.
+
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+
+use crate::canvas::arrow::components::{ArrowConnect, ArrowMeta};
+use crate::export::connector_position;
+use crate::format::Checkpoint;
+use crate::utils::ReflectableUuid;
+use crate::{DrawingJsonNode, JsonNode};
+
+/// Arrows have no id of their own, they are known by the connectors they join, so
+/// reattaching an arrow to another side of a node removes it and adds a new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ElementId {
+    Node(ReflectableUuid),
+    Arrow {
+        start: ArrowConnect,
+        end: ArrowConnect,
+    },
+    Drawing(ReflectableUuid),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Moved,
+    Resized,
+    Recolored,
+    TextEdited,
+    /// The image shown on a node was replaced.
+    ImageChanged,
+    /// An arrow got another type, e.g. a line became a double arrow.
+    Restyled,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub element: ElementId,
+    pub kind: ChangeKind,
+    /// World space box around the element, where it was for `Removed`, where it is otherwise.
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+/// Changes from `old` to `new`, in the order elements appear in `new` followed by the
+/// removed ones. An element can have several changes, e.g. moved and resized.
+pub fn diff_checkpoints(old: &Checkpoint, new: &Checkpoint) -> Vec<Change> {
+    let mut changes = vec![];
+
+    let old_nodes: HashMap<_, _> = old.nodes.iter().map(|node| (node.id, node)).collect();
+    for node in new.nodes.iter() {
+        let (min, max) = node_bounds(node);
+        let mut push = |kind| {
+            changes.push(Change {
+                element: ElementId::Node(ReflectableUuid(node.id)),
+                kind,
+                min,
+                max,
+            })
+        };
+        let Some(old_node) = old_nodes.get(&node.id) else {
+            push(ChangeKind::Added);
+            continue;
+        };
+        if old_node.x != node.x || old_node.y != node.y {
+            push(ChangeKind::Moved);
+        }
+        if old_node.width != node.width || old_node.height != node.height {
+            push(ChangeKind::Resized);
+        }
+        if old_node.bg_color != node.bg_color {
+            push(ChangeKind::Recolored);
+        }
+        if old_node.text.text != node.text.text || old_node.text.pos != node.text.pos {
+            push(ChangeKind::TextEdited);
+        }
+        if old.images.get(&node.id.to_string()) != new.images.get(&node.id.to_string()) {
+            push(ChangeKind::ImageChanged);
+        }
+    }
+
+    let old_arrows: HashMap<_, _> = old.arrows.iter().map(|a| (arrow_id(a), a)).collect();
+    for arrow in new.arrows.iter() {
+        let Some((min, max)) = arrow_bounds(new, arrow) else {
+            continue;
+        };
+        let kind = match old_arrows.get(&arrow_id(arrow)) {
+            None => ChangeKind::Added,
+            Some(old_arrow) if *old_arrow != arrow => ChangeKind::Restyled,
+            Some(_) => continue,
+        };
+        changes.push(Change {
+            element: arrow_id(arrow),
+            kind,
+            min,
+            max,
+        });
+    }
+
+    let old_drawings: HashMap<_, _> = old.drawings.iter().map(|d| (d.id, d)).collect();
+    for drawing in new.drawings.iter() {
+        let (min, max) = drawing_bounds(drawing);
+        let mut push = |kind| {
+            changes.push(Change {
+                element: ElementId::Drawing(drawing.id),
+                kind,
+                min,
+                max,
+            })
+        };
+        let Some(old_drawing) = old_drawings.get(&drawing.id) else {
+            push(ChangeKind::Added);
+            continue;
+        };
+        if old_drawing.x != drawing.x || old_drawing.y != drawing.y {
+            push(ChangeKind::Moved);
+        }
+        if old_drawing.points != drawing.points || old_drawing.width != drawing.width {
+            push(ChangeKind::Resized);
+        }
+        if old_drawing.drawing_color != drawing.drawing_color {
+            push(ChangeKind::Recolored);
+        }
+    }
+
+    let new_nodes: HashMap<_, _> = new.nodes.iter().map(|node| (node.id, node)).collect();
+    for node in old.nodes.iter().filter(|n| !new_nodes.contains_key(&n.id)) {
+        let (min, max) = node_bounds(node);
+        changes.push(Change {
+            element: ElementId::Node(ReflectableUuid(node.id)),
+            kind: ChangeKind::Removed,
+            min,
+            max,
+        });
+    }
+    let new_arrows: HashMap<_, _> = new.arrows.iter().map(|a| (arrow_id(a), a)).collect();
+    for arrow in old.arrows.iter() {
+        if new_arrows.contains_key(&arrow_id(arrow)) {
+            continue;
+        }
+        if let Some((min, max)) = arrow_bounds(old, arrow) {
+            changes.push(Change {
+                element: arrow_id(arrow),
+                kind: ChangeKind::Removed,
+                min,
+                max,
+            });
+        }
+    }
+    let new_drawings: HashMap<_, _> = new.drawings.iter().map(|d| (d.id, d)).collect();
+    for drawing in old.drawings.iter() {
+        if new_drawings.contains_key(&drawing.id) {
+            continue;
+        }
+        let (min, max) = drawing_bounds(drawing);
+        changes.push(Change {
+            element: ElementId::Drawing(drawing.id),
+            kind: ChangeKind::Removed,
+            min,
+            max,
+        });
+    }
+    changes
+}
+
+fn arrow_id(arrow: &ArrowMeta) -> ElementId {
+    ElementId::Arrow {
+        start: arrow.start,
+        end: arrow.end,
+    }
+}
+
+fn node_bounds(node: &JsonNode<String>) -> (Vec2, Vec2) {
+    let center = Vec2::new(node.x, node.y);
+    let half = Vec2::new(node.width, node.height) / 2.;
+    (center - half, center + half)
+}
+
+/// Box between the connectors, `None` if the checkpoint lacks one of the nodes.
+fn arrow_bounds(checkpoint: &Checkpoint, arrow: &ArrowMeta) -> Option<(Vec2, Vec2)> {
+    let node = |id: ReflectableUuid| checkpoint.nodes.iter().find(|node| node.id == id.0);
+    let start = connector_position(node(arrow.start.id)?, arrow.start.pos);
+    let end = connector_position(node(arrow.end.id)?, arrow.end.pos);
+    Some((start.min(end), start.max(end)))
+}
+
+fn drawing_bounds(drawing: &DrawingJsonNode<String>) -> (Vec2, Vec2) {
+    let offset = Vec2::new(drawing.x, drawing.y);
+    if drawing.points.is_empty() {
+        return (offset, offset);
+    }
+    drawing.points.iter().map(|point| *point + offset).fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), point| (min.min(point), max.max(point)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::arrow::components::{ArrowConnectPos, ArrowType};
+    use crate::{JsonNodeText, TextPos};
+
+    fn node(id: u128, x: f32, text: &str) -> JsonNode<String> {
+        JsonNode {
+            id: uuid::Uuid::from_u128(id),
+            x,
+            width: 100.,
+            height: 50.,
+            bg_color: "node_bg".to_string(),
+            text: JsonNodeText {
+                text: text.to_string(),
+                pos: TextPos::Center,
+            },
+            ..default()
+        }
+    }
+
+    fn arrow(start: u128, end: u128, pos: ArrowConnectPos) -> ArrowMeta {
+        ArrowMeta {
+            start: ArrowConnect {
+                id: ReflectableUuid(uuid::Uuid::from_u128(start)),
+                pos,
+            },
+            end: ArrowConnect {
+                id: ReflectableUuid(uuid::Uuid::from_u128(end)),
+                pos: ArrowConnectPos::Left,
+            },
+            ..default()
+        }
+    }
+
+    fn arrow_id(start: u128, end: u128, pos: ArrowConnectPos) -> ElementId {
+        let arrow = arrow(start, end, pos);
+        ElementId::Arrow {
+            start: arrow.start,
+            end: arrow.end,
+        }
+    }
+
+    fn kinds(changes: &[Change], element: ElementId) -> Vec<ChangeKind> {
+        changes
+            .iter()
+            .filter(|change| change.element == element)
+            .map(|change| change.kind)
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_nodes() {
+        let image_key = uuid::Uuid::from_u128(2).to_string();
+        let old = Checkpoint {
+            nodes: vec![node(1, 0., "a"), node(2, 0., "b"), node(3, 0., "c")],
+            images: [(image_key.clone(), "hash_1".to_string())].into(),
+            ..default()
+        };
+        let mut moved = node(1, 200., "a");
+        moved.width = 120.;
+        let mut recolored = node(2, 0., "b!");
+        recolored.bg_color = "color_change_1".to_string();
+        let new = Checkpoint {
+            nodes: vec![moved, recolored, node(4, 0., "d")],
+            images: [(image_key, "hash_2".to_string())].into(),
+            ..default()
+        };
+        let changes = diff_checkpoints(&old, &new);
+        let id = |id| ElementId::Node(ReflectableUuid(uuid::Uuid::from_u128(id)));
+        assert_eq!(
+            kinds(&changes, id(1)),
+            vec![ChangeKind::Moved, ChangeKind::Resized]
+        );
+        assert_eq!(
+            kinds(&changes, id(2)),
+            vec![
+                ChangeKind::Recolored,
+                ChangeKind::TextEdited,
+                ChangeKind::ImageChanged
+            ]
+        );
+        assert_eq!(kinds(&changes, id(3)), vec![ChangeKind::Removed]);
+        assert_eq!(kinds(&changes, id(4)), vec![ChangeKind::Added]);
+        let moved = &changes[0];
+        assert_eq!(moved.min, Vec2::new(140., -25.));
+        assert_eq!(moved.max, Vec2::new(260., 25.));
+        assert!(diff_checkpoints(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_diff_arrows_and_drawings() {
+        let drawing = DrawingJsonNode {
+            x: 0.,
+            y: 0.,
+            z: 0.,
+            id: ReflectableUuid(uuid::Uuid::from_u128(10)),
+            points: vec![Vec2::ZERO, Vec2::new(10., 10.)],
+            drawing_color: "font".to_string(),
+            width: 2.,
+        };
+        let old = Checkpoint {
+            nodes: vec![node(1, 0., ""), node(2, 300., ""), node(3, 600., "")],
+            arrows: vec![
+                arrow(1, 2, ArrowConnectPos::Right),
+                arrow(1, 2, ArrowConnectPos::Bottom),
+                arrow(2, 3, ArrowConnectPos::Right),
+            ],
+            drawings: vec![drawing.clone()],
+            ..default()
+        };
+        let new = Checkpoint {
+            nodes: old.nodes.clone(),
+            arrows: vec![
+                arrow(1, 2, ArrowConnectPos::Top),
+                ArrowMeta {
+                    arrow_type: ArrowType::Line,
+                    ..arrow(1, 2, ArrowConnectPos::Bottom)
+                },
+                arrow(1, 3, ArrowConnectPos::Right),
+            ],
+            drawings: vec![DrawingJsonNode {
+                x: 5.,
+                drawing_color: "color_change_2".to_string(),
+                ..drawing
+            }],
+            ..default()
+        };
+        let changes = diff_checkpoints(&old, &new);
+        // arrows between the same nodes are told apart by their connectors
+        assert_eq!(
+            kinds(&changes, arrow_id(1, 2, ArrowConnectPos::Right)),
+            vec![ChangeKind::Removed]
+        );
+        assert_eq!(
+            kinds(&changes, arrow_id(1, 2, ArrowConnectPos::Top)),
+            vec![ChangeKind::Added]
+        );
+        assert_eq!(
+            kinds(&changes, arrow_id(1, 2, ArrowConnectPos::Bottom)),
+            vec![ChangeKind::Restyled]
+        );
+        assert_eq!(
+            kinds(&changes, arrow_id(1, 3, ArrowConnectPos::Right)),
+            vec![ChangeKind::Added]
+        );
+        assert_eq!(
+            kinds(&changes, arrow_id(2, 3, ArrowConnectPos::Right)),
+            vec![ChangeKind::Removed]
+        );
+        let drawing_id = ElementId::Drawing(ReflectableUuid(uuid::Uuid::from_u128(10)));
+        assert_eq!(
+            kinds(&changes, drawing_id),
+            vec![ChangeKind::Moved, ChangeKind::Recolored]
+        );
+        assert_eq!(changes.len(), 7);
+    }
+}
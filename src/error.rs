@@ -0,0 +1,48 @@
+//! Failures the user should hear about. Systems report them as toasts instead of panicking.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::store::StoreError;
+use crate::utils::ReflectableUuid;
+
+#[derive(Debug)]
+pub enum VeloError {
+    DocumentNotFound(ReflectableUuid),
+    Store(StoreError),
+    Io(PathBuf, std::io::Error),
+    /// The json or markdown being opened is not something velo can read.
+    InvalidDocument(String),
+    Network(String),
+    Share(String),
+    /// Sealing or opening a locked document failed, e.g. on a wrong passphrase.
+    Crypto(String),
+}
+
+impl fmt::Display for VeloError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VeloError::DocumentNotFound(id) => write!(f, "document {} is not stored", id.0),
+            VeloError::Store(e) => write!(f, "{}", e),
+            VeloError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            VeloError::InvalidDocument(e) => write!(f, "invalid document: {}", e),
+            VeloError::Network(e) => write!(f, "request failed: {}", e),
+            VeloError::Share(e) => write!(f, "{}", e),
+            VeloError::Crypto(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for VeloError {}
+
+impl From<StoreError> for VeloError {
+    fn from(e: StoreError) -> Self {
+        VeloError::Store(e)
+    }
+}
+
+impl From<serde_json::Error> for VeloError {
+    fn from(e: serde_json::Error) -> Self {
+        VeloError::InvalidDocument(e.to_string())
+    }
+}
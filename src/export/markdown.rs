@@ -0,0 +1,211 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use uuid::Uuid;
+
+use crate::format::Checkpoint;
+use crate::JsonNode;
+
+/// Title of the section listing nodes without any arrow.
+const UNCONNECTED_TITLE: &str = "## Unconnected";
+
+/// Renders a tab checkpoint as a markdown outline. Nodes connected by arrows become
+/// nested lists following the arrow direction, siblings are ordered top to bottom
+/// and left to right so the output stays stable between exports.
+pub fn checkpoint_to_markdown(checkpoint: &Checkpoint) -> String {
+    let mut nodes: Vec<&JsonNode<String>> = checkpoint.nodes.iter().collect();
+    nodes.sort_by(|a, b| reading_order(a, b));
+    let known: HashSet<Uuid> = nodes.iter().map(|node| node.id).collect();
+
+    let mut children: HashMap<Uuid, BTreeSet<usize>> = HashMap::new();
+    let mut has_parent: HashSet<Uuid> = HashSet::new();
+    let mut connected: HashSet<Uuid> = HashSet::new();
+    let position: HashMap<Uuid, usize> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| (node.id, i))
+        .collect();
+    for arrow in checkpoint.arrows.iter() {
+        let (start, end) = (arrow.start.id.0, arrow.end.id.0);
+        if start == end || !known.contains(&start) || !known.contains(&end) {
+            continue;
+        }
+        children.entry(start).or_default().insert(position[&end]);
+        has_parent.insert(end);
+        connected.insert(start);
+        connected.insert(end);
+    }
+
+    let mut markdown = String::new();
+    let mut visited: HashSet<Uuid> = HashSet::new();
+    // roots first, then whatever is left of cycles without a root
+    let roots = nodes
+        .iter()
+        .filter(|node| connected.contains(&node.id) && !has_parent.contains(&node.id))
+        .chain(nodes.iter().filter(|node| connected.contains(&node.id)));
+    for root in roots {
+        if visited.contains(&root.id) {
+            continue;
+        }
+        if !markdown.is_empty() {
+            markdown.push('\n');
+        }
+        write_tree(&mut markdown, &nodes, &children, &mut visited, root, 0);
+    }
+
+    let unconnected: Vec<_> = nodes
+        .iter()
+        .filter(|node| !connected.contains(&node.id))
+        .collect();
+    if !unconnected.is_empty() {
+        if !markdown.is_empty() {
+            markdown.push('\n');
+        }
+        markdown.push_str(UNCONNECTED_TITLE);
+        markdown.push_str("\n\n");
+        for node in unconnected {
+            write_item(&mut markdown, node, 0);
+        }
+    }
+    markdown
+}
+
+fn write_tree(
+    markdown: &mut String,
+    nodes: &[&JsonNode<String>],
+    children: &HashMap<Uuid, BTreeSet<usize>>,
+    visited: &mut HashSet<Uuid>,
+    node: &JsonNode<String>,
+    depth: usize,
+) {
+    visited.insert(node.id);
+    write_item(markdown, node, depth);
+    for child in children.get(&node.id).into_iter().flatten() {
+        let child = nodes[*child];
+        if !visited.contains(&child.id) {
+            write_tree(markdown, nodes, children, visited, child, depth + 1);
+        }
+    }
+}
+
+/// Writes the node text as a list item, continuation lines are indented to stay in the item.
+fn write_item(markdown: &mut String, node: &JsonNode<String>, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let mut lines = node.text.text.trim_end().lines();
+    markdown.push_str(&indent);
+    markdown.push('-');
+    if let Some(first) = lines.next() {
+        if !first.is_empty() {
+            markdown.push(' ');
+            markdown.push_str(first);
+        }
+    }
+    markdown.push('\n');
+    for line in lines {
+        if !line.is_empty() {
+            markdown.push_str(&indent);
+            markdown.push_str("  ");
+            markdown.push_str(line);
+        }
+        markdown.push('\n');
+    }
+}
+
+/// Top to bottom, then left to right, the id breaks ties between overlapping nodes.
+fn reading_order(a: &JsonNode<String>, b: &JsonNode<String>) -> Ordering {
+    b.y.total_cmp(&a.y)
+        .then(a.x.total_cmp(&b.x))
+        .then(a.id.cmp(&b.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::arrow::components::{ArrowConnect, ArrowMeta};
+    use crate::import::markdown_to_checkpoint;
+    use crate::utils::ReflectableUuid;
+    use crate::JsonNodeText;
+    use bevy::prelude::Vec2;
+
+    fn node(id: u128, x: f32, y: f32, text: &str) -> JsonNode<String> {
+        JsonNode {
+            id: Uuid::from_u128(id),
+            x,
+            y,
+            text: JsonNodeText {
+                text: text.to_string(),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn arrow(start: u128, end: u128) -> ArrowMeta {
+        ArrowMeta {
+            start: ArrowConnect {
+                id: ReflectableUuid(Uuid::from_u128(start)),
+                ..Default::default()
+            },
+            end: ArrowConnect {
+                id: ReflectableUuid(Uuid::from_u128(end)),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_markdown_components_cycles_and_unconnected() {
+        let checkpoint = Checkpoint {
+            nodes: vec![
+                node(1, 0., 0., "# Plan"),
+                node(2, 200., -100., "**later**"),
+                node(3, 200., 100., "first\n\n```rust\nfn main() {}\n```"),
+                node(4, 0., -300., "loop a"),
+                node(5, 200., -300., "loop b"),
+                node(6, 0., -500., "stray"),
+                node(7, -100., -500., ""),
+            ],
+            arrows: vec![
+                arrow(1, 2),
+                arrow(1, 3),
+                arrow(4, 5),
+                arrow(5, 4),
+                arrow(3, 9),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            checkpoint_to_markdown(&checkpoint),
+            "- # Plan
+  - first
+
+    ```rust
+    fn main() {}
+    ```
+  - **later**
+
+- loop a
+  - loop b
+
+## Unconnected
+
+-
+- stray
+"
+        );
+    }
+
+    #[test]
+    fn test_markdown_is_stable_across_arrow_order() {
+        let mut checkpoint =
+            markdown_to_checkpoint("# A\n\n- b\n- c\n  - d\n", Vec2::new(100., 50.)).unwrap();
+        let markdown = checkpoint_to_markdown(&checkpoint);
+        checkpoint.arrows.reverse();
+        checkpoint.nodes.reverse();
+
+        assert_eq!(markdown, "- # A\n  - b\n  - c\n    - d\n");
+        assert_eq!(checkpoint_to_markdown(&checkpoint), markdown);
+    }
+}
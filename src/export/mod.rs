@@ -0,0 +1,201 @@
+//! Headless rendering of tab checkpoints, shared by all exporters.
+
+use bevy::prelude::*;
+use serde_json::{Map, Value};
+
+use crate::canvas::arrow::components::ArrowConnectPos;
+use crate::canvas::arrow::utils::arrow_polylines;
+use crate::format::Checkpoint;
+use crate::themes::Theme;
+use crate::{JsonNode, NodeType};
+
+mod markdown;
+pub use markdown::*;
+mod png;
+pub use png::*;
+mod svg;
+pub use svg::*;
+
+/// Empty space around the content of an exported tab, in world units.
+const PADDING: f32 = 20.;
+/// Stroke width of arrows, matches `create_arrow`.
+const ARROW_WIDTH: f32 = 1.5;
+/// Stroke width of freehand drawings, matches `load_tab`.
+const DRAWING_WIDTH: f32 = 2.;
+/// Radius of the rounded corners of `NodeType::Rect`, matches `spawn_sprite_node`.
+pub const RECT_CORNER_RADIUS: f32 = 10.;
+/// Distance between node border and text for `TextPos::TopLeft`.
+const TEXT_INSET: f32 = 10.;
+
+pub enum SceneItem {
+    Node {
+        node: JsonNode<String>,
+        fill: Color,
+        /// Base64 encoded png, carried along in the checkpoint `blobs`.
+        image: Option<String>,
+    },
+    Stroke {
+        lines: Vec<Vec<Vec2>>,
+        color: Color,
+        width: f32,
+    },
+}
+
+/// Checkpoint with theme colors resolved and every item in paint order.
+pub struct Scene {
+    pub min: Vec2,
+    pub max: Vec2,
+    pub background: Option<Color>,
+    pub node_border: Color,
+    pub font: Color,
+    pub font_name: String,
+    pub font_size: f32,
+    pub line_height: f32,
+    pub items: Vec<SceneItem>,
+}
+
+impl Scene {
+    pub fn new(checkpoint: &Checkpoint, theme: &Theme) -> Self {
+        let theme_colors = serde_json::to_value(theme)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .clone();
+        let mut items: Vec<(f32, SceneItem)> = vec![];
+
+        for node in checkpoint.nodes.iter() {
+            items.push((
+                // node shapes are spawned slightly above their parent
+                node.z + 0.001,
+                SceneItem::Node {
+                    node: node.clone(),
+                    fill: resolve_color(&theme_colors, &node.bg_color, theme.node_bg),
+                    image: checkpoint.image_data(&node.id.to_string()).cloned(),
+                },
+            ));
+        }
+        for arrow in checkpoint.arrows.iter() {
+            let start = checkpoint.nodes.iter().find(|n| n.id == arrow.start.id.0);
+            let end = checkpoint.nodes.iter().find(|n| n.id == arrow.end.id.0);
+            if let (Some(start), Some(end)) = (start, end) {
+                items.push((
+                    f32::max(0.1, f32::max(start.z, end.z)),
+                    SceneItem::Stroke {
+                        lines: arrow_polylines(
+                            connector_position(start, arrow.start.pos),
+                            connector_position(end, arrow.end.pos),
+                            *arrow,
+                        ),
+                        color: theme.arrow,
+                        width: ARROW_WIDTH,
+                    },
+                ));
+            }
+        }
+        for drawing in checkpoint.drawings.iter() {
+            let offset = Vec2::new(drawing.x, drawing.y);
+            items.push((
+                drawing.z,
+                SceneItem::Stroke {
+                    lines: vec![drawing.points.iter().map(|p| *p + offset).collect()],
+                    color: resolve_color(&theme_colors, &drawing.drawing_color, theme.font),
+                    width: DRAWING_WIDTH,
+                },
+            ));
+        }
+        items.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let items: Vec<SceneItem> = items.into_iter().map(|(_, item)| item).collect();
+
+        let mut min = Vec2::splat(f32::MAX);
+        let mut max = Vec2::splat(f32::MIN);
+        for item in items.iter() {
+            match item {
+                SceneItem::Node { node, .. } => {
+                    let half = Vec2::new(node.width, node.height) / 2.;
+                    min = min.min(Vec2::new(node.x, node.y) - half);
+                    max = max.max(Vec2::new(node.x, node.y) + half);
+                }
+                SceneItem::Stroke { lines, .. } => {
+                    for point in lines.iter().flatten() {
+                        min = min.min(*point);
+                        max = max.max(*point);
+                    }
+                }
+            }
+        }
+        if min.x > max.x {
+            min = Vec2::ZERO;
+            max = Vec2::ZERO;
+        }
+
+        Self {
+            min: min - PADDING,
+            max: max + PADDING,
+            background: theme.canvas_bg_color,
+            node_border: theme.node_border,
+            font: theme.font,
+            font_name: theme.font_name.clone(),
+            font_size: theme.font_size,
+            line_height: theme.line_height,
+            items,
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        self.max - self.min
+    }
+
+    /// Converts a world position (y up) to image coordinates (y down, origin top left).
+    pub fn to_image(&self, point: Vec2) -> Vec2 {
+        Vec2::new(point.x - self.min.x, self.max.y - point.y)
+    }
+
+    /// Image coordinates of the first text line and the text anchor of a node.
+    pub fn text_origin(&self, node: &JsonNode<String>) -> (Vec2, TextAnchor) {
+        let lines = node.text.text.lines().count().max(1) as f32;
+        match node.text.pos {
+            crate::TextPos::Center => (
+                self.to_image(Vec2::new(
+                    node.x,
+                    node.y + (lines - 1.) * self.line_height / 2.,
+                )),
+                TextAnchor::Middle,
+            ),
+            crate::TextPos::TopLeft => (
+                self.to_image(Vec2::new(
+                    node.x - node.width / 2. + TEXT_INSET,
+                    node.y + node.height / 2. - TEXT_INSET - self.line_height / 2.,
+                )),
+                TextAnchor::Start,
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TextAnchor {
+    Start,
+    Middle,
+}
+
+/// Node shapes have a border unless they are paper notes, matches `spawn_sprite_node`.
+pub fn has_border(node_type: &NodeType) -> bool {
+    *node_type != NodeType::Paper
+}
+
+pub fn connector_position(node: &JsonNode<String>, pos: ArrowConnectPos) -> Vec2 {
+    let offset = match pos {
+        ArrowConnectPos::Left => Vec2::new(-node.width / 2., 0.),
+        ArrowConnectPos::Bottom => Vec2::new(0., -node.height / 2.),
+        ArrowConnectPos::Top => Vec2::new(0., node.height / 2.),
+        ArrowConnectPos::Right => Vec2::new(node.width / 2., 0.),
+    };
+    Vec2::new(node.x, node.y) + offset
+}
+
+fn resolve_color(theme_colors: &Map<String, Value>, key: &str, default: Color) -> Color {
+    theme_colors
+        .get(key)
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,278 @@
+use std::io::Cursor;
+
+use base64::{engine::general_purpose, Engine};
+use bevy::prelude::*;
+use image::{
+    imageops::{self, FilterType},
+    load_from_memory_with_format, ImageError, ImageFormat, Rgba, RgbaImage,
+};
+
+use super::{has_border, Scene, SceneItem, RECT_CORNER_RADIUS};
+use crate::format::Checkpoint;
+use crate::themes::Theme;
+use crate::NodeType;
+
+/// Renders a tab checkpoint to PNG bytes on the CPU, `scale` is pixels per world unit.
+/// Node text is not rendered, it needs the font pipeline of the app.
+pub fn checkpoint_to_png(
+    checkpoint: &Checkpoint,
+    theme: &Theme,
+    scale: f32,
+) -> Result<Vec<u8>, ImageError> {
+    let image = scene_to_image(&Scene::new(checkpoint, theme), scale);
+    let mut bytes = Cursor::new(vec![]);
+    image.write_to(&mut bytes, ImageFormat::Png)?;
+    Ok(bytes.into_inner())
+}
+
+pub fn scene_to_image(scene: &Scene, scale: f32) -> RgbaImage {
+    let size = (scene.size() * scale).ceil();
+    let mut canvas = Canvas::new(size.x as u32, size.y as u32, scale);
+    canvas.fill_all(scene.background.unwrap_or(Color::WHITE));
+    for item in scene.items.iter() {
+        match item {
+            SceneItem::Node { node, fill, image } => {
+                let center = scene.to_image(Vec2::new(node.x, node.y));
+                let half = Vec2::new(node.width, node.height) / 2.;
+                let shape = |p: Vec2| match node.node_type {
+                    NodeType::Rect => rounded_rect_distance(p, center, half, RECT_CORNER_RADIUS),
+                    NodeType::Paper | NodeType::Frame => rounded_rect_distance(p, center, half, 0.),
+                    NodeType::Circle => circle_distance(p, center, node.width / 2.),
+                };
+                canvas.fill(center - half, center + half, *fill, shape);
+                if let Some(image) = image.as_deref().and_then(decode_image) {
+                    canvas.draw_image(&image, center - half, center + half, shape);
+                }
+                if has_border(&node.node_type) {
+                    canvas.fill(center - half, center + half, scene.node_border, |p| {
+                        shape(p).abs() - 0.5
+                    });
+                }
+            }
+            SceneItem::Stroke {
+                lines,
+                color,
+                width,
+            } => {
+                let lines: Vec<Vec<Vec2>> = lines
+                    .iter()
+                    .map(|line| line.iter().map(|p| scene.to_image(*p)).collect())
+                    .collect();
+                let mut min = Vec2::splat(f32::MAX);
+                let mut max = Vec2::splat(f32::MIN);
+                for point in lines.iter().flatten() {
+                    min = min.min(*point);
+                    max = max.max(*point);
+                }
+                canvas.fill(min - *width, max + *width, *color, |p| {
+                    polyline_distance(p, &lines) - width / 2.
+                });
+            }
+        }
+    }
+    canvas.into_image()
+}
+
+/// Straight (not premultiplied) RGBA pixels in sRGB space.
+struct Canvas {
+    width: u32,
+    height: u32,
+    scale: f32,
+    pixels: Vec<Vec4>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, scale: f32) -> Self {
+        Self {
+            width,
+            height,
+            scale,
+            pixels: vec![Vec4::ZERO; (width * height) as usize],
+        }
+    }
+
+    fn fill_all(&mut self, color: Color) {
+        let color = Vec4::from(color.as_rgba_f32());
+        for pixel in self.pixels.iter_mut() {
+            *pixel = color;
+        }
+    }
+
+    /// Pixel range covering the given image space bounds, with a margin for anti-aliasing.
+    fn pixel_range(&self, min: Vec2, max: Vec2) -> (UVec2, UVec2) {
+        let min = (min * self.scale - 1.).floor().max(Vec2::ZERO);
+        let max = (max * self.scale + 1.)
+            .ceil()
+            .min(Vec2::new(self.width as f32, self.height as f32));
+        (min.as_uvec2(), max.as_uvec2())
+    }
+
+    /// Anti-aliased fill of the shape given by a signed distance function in image space.
+    fn fill(&mut self, min: Vec2, max: Vec2, color: Color, distance: impl Fn(Vec2) -> f32) {
+        let color = Vec4::from(color.as_rgba_f32());
+        let (min, max) = self.pixel_range(min, max);
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let coverage = self.coverage(x, y, &distance);
+                self.blend(x, y, color, coverage);
+            }
+        }
+    }
+
+    /// Draws an image stretched to the given bounds and clipped to the shape.
+    fn draw_image(
+        &mut self,
+        image: &RgbaImage,
+        min: Vec2,
+        max: Vec2,
+        distance: impl Fn(Vec2) -> f32,
+    ) {
+        let origin = (min * self.scale).round();
+        let size = ((max - min) * self.scale).round().max(Vec2::ONE);
+        let image = imageops::resize(image, size.x as u32, size.y as u32, FilterType::Triangle);
+        for (image_x, image_y, pixel) in image.enumerate_pixels() {
+            let (x, y) = (
+                origin.x as i64 + image_x as i64,
+                origin.y as i64 + image_y as i64,
+            );
+            if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+                continue;
+            }
+            let (x, y) = (x as u32, y as u32);
+            let coverage = self.coverage(x, y, &distance);
+            self.blend(x, y, Vec4::from(pixel.0.map(|c| c as f32 / 255.)), coverage);
+        }
+    }
+
+    fn coverage(&self, x: u32, y: u32, distance: &impl Fn(Vec2) -> f32) -> f32 {
+        let center = (Vec2::new(x as f32, y as f32) + 0.5) / self.scale;
+        (0.5 - distance(center) * self.scale).clamp(0., 1.)
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: Vec4, coverage: f32) {
+        let alpha = color.w * coverage;
+        if alpha <= 0. {
+            return;
+        }
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        let out_alpha = alpha + pixel.w * (1. - alpha);
+        let rgb =
+            (color.truncate() * alpha + pixel.truncate() * pixel.w * (1. - alpha)) / out_alpha;
+        *pixel = rgb.extend(out_alpha);
+    }
+
+    fn into_image(self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (pixel, color) in image.pixels_mut().zip(self.pixels) {
+            *pixel = Rgba(color.to_array().map(|c| (c * 255.).round() as u8));
+        }
+        image
+    }
+}
+
+fn decode_image(base64: &str) -> Option<RgbaImage> {
+    let bytes = general_purpose::STANDARD.decode(base64.as_bytes()).ok()?;
+    let image = load_from_memory_with_format(&bytes, ImageFormat::Png).ok()?;
+    Some(image.into_rgba8())
+}
+
+fn rounded_rect_distance(p: Vec2, center: Vec2, half: Vec2, radius: f32) -> f32 {
+    let radius = radius.min(half.x).min(half.y);
+    let q = (p - center).abs() - half + radius;
+    q.max(Vec2::ZERO).length() + q.x.max(q.y).min(0.) - radius
+}
+
+fn circle_distance(p: Vec2, center: Vec2, radius: f32) -> f32 {
+    p.distance(center) - radius
+}
+
+fn polyline_distance(p: Vec2, lines: &[Vec<Vec2>]) -> f32 {
+    let mut distance = f32::MAX;
+    for line in lines.iter() {
+        if line.len() == 1 {
+            distance = distance.min(p.distance(line[0]));
+        }
+        for segment in line.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            let ab = b - a;
+            let t = if ab.length_squared() > 0. {
+                ((p - a).dot(ab) / ab.length_squared()).clamp(0., 1.)
+            } else {
+                0.
+            };
+            distance = distance.min(p.distance(a + ab * t));
+        }
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::DocFile;
+    use crate::themes::velo_light;
+    use crate::{DrawingJsonNode, JsonNode};
+    use uuid::Uuid;
+
+    const DOC_V1: &str = include_str!("../../tests/fixtures/doc_v1.json");
+
+    fn render(checkpoint: &Checkpoint, scale: f32) -> RgbaImage {
+        let png = checkpoint_to_png(checkpoint, &velo_light(), scale).unwrap();
+        load_from_memory_with_format(&png, ImageFormat::Png)
+            .unwrap()
+            .into_rgba8()
+    }
+
+    #[test]
+    fn test_png_nodes_and_arrows() {
+        let doc = DocFile::from_json(DOC_V1).unwrap().doc;
+        let checkpoint = Checkpoint::from_json(&doc.tabs[0].checkpoints[0]).unwrap();
+
+        let image = render(&checkpoint, 1.);
+        assert_eq!(image.dimensions(), (390, 115));
+        assert_eq!(image.get_pixel(2, 2), &Rgba([255, 255, 255, 255]));
+        assert_eq!(image.get_pixel(95, 35), &Rgba([250, 250, 250, 255]));
+        assert_eq!(image.get_pixel(195, 57), &Rgba([63, 81, 181, 255]));
+
+        let image = render(&checkpoint, 2.);
+        assert_eq!(image.dimensions(), (780, 230));
+        assert_eq!(image.get_pixel(390, 115), &Rgba([63, 81, 181, 255]));
+    }
+
+    #[test]
+    fn test_png_images_and_drawings() {
+        let mut red = vec![];
+        RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]))
+            .write_to(&mut Cursor::new(&mut red), ImageFormat::Png)
+            .unwrap();
+        let id = Uuid::new_v4();
+        let mut checkpoint = Checkpoint {
+            nodes: vec![JsonNode {
+                id,
+                node_type: NodeType::Paper,
+                width: 40.,
+                height: 40.,
+                bg_color: "paper_node_bg".to_string(),
+                ..Default::default()
+            }],
+            drawings: vec![DrawingJsonNode {
+                x: 0.,
+                y: -40.,
+                z: 0.,
+                id: crate::utils::ReflectableUuid(Uuid::new_v4()),
+                points: vec![Vec2::new(-20., 0.), Vec2::new(20., 0.)],
+                drawing_color: "font".to_string(),
+                width: 2.,
+            }],
+            ..Default::default()
+        };
+        checkpoint.insert_image(id.to_string(), general_purpose::STANDARD.encode(red));
+
+        let image = render(&checkpoint, 1.);
+        // content spans from (-20, -40) to (20, 20) plus padding
+        assert_eq!(image.dimensions(), (80, 100));
+        assert_eq!(image.get_pixel(40, 40), &Rgba([255, 0, 0, 255]));
+        assert_eq!(image.get_pixel(40, 80), &Rgba([0, 0, 0, 255]));
+        assert_eq!(image.get_pixel(40, 70), &Rgba([255, 255, 255, 255]));
+    }
+}
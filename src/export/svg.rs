@@ -0,0 +1,266 @@
+use std::fmt::Write;
+
+use bevy::prelude::*;
+
+use super::{has_border, Scene, SceneItem, TextAnchor, RECT_CORNER_RADIUS};
+use crate::format::Checkpoint;
+use crate::themes::Theme;
+use crate::{JsonNode, NodeType};
+
+/// Renders a tab checkpoint as a standalone SVG document.
+pub fn checkpoint_to_svg(checkpoint: &Checkpoint, theme: &Theme) -> String {
+    scene_to_svg(&Scene::new(checkpoint, theme))
+}
+
+pub fn scene_to_svg(scene: &Scene) -> String {
+    let size = scene.size();
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = num(size.x),
+        h = num(size.y)
+    )
+    .unwrap();
+    if let Some(background) = scene.background {
+        writeln!(
+            svg,
+            r#"<rect width="100%" height="100%" {}/>"#,
+            paint("fill", background)
+        )
+        .unwrap();
+    }
+    for item in scene.items.iter() {
+        match item {
+            SceneItem::Node { node, fill, image } => {
+                write_node(&mut svg, scene, node, *fill, image.as_deref())
+            }
+            SceneItem::Stroke {
+                lines,
+                color,
+                width,
+            } => {
+                let mut d = vec![];
+                for line in lines.iter() {
+                    for (i, point) in line.iter().enumerate() {
+                        let point = scene.to_image(*point);
+                        let command = if i == 0 { "M" } else { "L" };
+                        d.push(format!("{}{} {}", command, num(point.x), num(point.y)));
+                    }
+                }
+                writeln!(
+                    svg,
+                    r#"<path d="{}" fill="none" {} stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                    d.join(" "),
+                    paint("stroke", *color),
+                    num(*width)
+                )
+                .unwrap();
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn write_node(
+    svg: &mut String,
+    scene: &Scene,
+    node: &JsonNode<String>,
+    fill: Color,
+    image: Option<&str>,
+) {
+    let top_left = scene.to_image(Vec2::new(
+        node.x - node.width / 2.,
+        node.y + node.height / 2.,
+    ));
+    let stroke = if has_border(&node.node_type) {
+        format!(r#"{} stroke-width="1""#, paint("stroke", scene.node_border))
+    } else {
+        r#"stroke="none""#.to_string()
+    };
+    match node.node_type {
+        NodeType::Circle => {
+            let center = scene.to_image(Vec2::new(node.x, node.y));
+            writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" {} {}/>"#,
+                num(center.x),
+                num(center.y),
+                num(node.width / 2.),
+                paint("fill", fill),
+                stroke
+            )
+            .unwrap();
+        }
+        NodeType::Rect | NodeType::Paper | NodeType::Frame => {
+            let corners = if node.node_type == NodeType::Rect {
+                format!(r#" rx="{}""#, num(RECT_CORNER_RADIUS))
+            } else {
+                String::new()
+            };
+            writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}"{} {} {}/>"#,
+                num(top_left.x),
+                num(top_left.y),
+                num(node.width),
+                num(node.height),
+                corners,
+                paint("fill", fill),
+                stroke
+            )
+            .unwrap();
+        }
+    }
+    if let Some(image) = image {
+        writeln!(
+            svg,
+            r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none" href="data:image/png;base64,{}"/>"#,
+            num(top_left.x),
+            num(top_left.y),
+            num(node.width),
+            num(node.height),
+            image
+        )
+        .unwrap();
+    }
+    if node.text.text.trim().is_empty() {
+        return;
+    }
+    let (origin, anchor) = scene.text_origin(node);
+    writeln!(
+        svg,
+        r#"<text font-family="{}" font-size="{}" {} text-anchor="{}" dominant-baseline="middle">"#,
+        escape(&scene.font_name),
+        num(scene.font_size),
+        paint("fill", scene.font),
+        match anchor {
+            TextAnchor::Start => "start",
+            TextAnchor::Middle => "middle",
+        }
+    )
+    .unwrap();
+    for (i, line) in node.text.text.lines().enumerate() {
+        writeln!(
+            svg,
+            r#"<tspan x="{}" y="{}">{}</tspan>"#,
+            num(origin.x),
+            num(origin.y + i as f32 * scene.line_height),
+            escape(line)
+        )
+        .unwrap();
+    }
+    svg.push_str("</text>\n");
+}
+
+/// `fill`/`stroke` attributes, with opacity only when the color is translucent.
+fn paint(attribute: &str, color: Color) -> String {
+    let [r, g, b, a] = color.as_rgba_f32();
+    if a <= 0. {
+        return format!(r#"{}="none""#, attribute);
+    }
+    let hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        (r * 255.).round() as u8,
+        (g * 255.).round() as u8,
+        (b * 255.).round() as u8
+    );
+    if a < 1. {
+        format!(
+            r#"{attribute}="{}" {attribute}-opacity="{}""#,
+            hex,
+            num(a),
+            attribute = attribute
+        )
+    } else {
+        format!(r#"{}="{}""#, attribute, hex)
+    }
+}
+
+/// Formats a coordinate with at most two decimals, so output is stable across platforms.
+fn num(value: f32) -> String {
+    let value = format!("{:.2}", value);
+    let value = value.trim_end_matches('0').trim_end_matches('.');
+    match value {
+        "-0" => "0".to_string(),
+        value => value.to_string(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::DocFile;
+    use crate::themes::velo_light;
+    use crate::{DrawingJsonNode, JsonNodeText, TextPos};
+    use uuid::Uuid;
+
+    const DOC_V1: &str = include_str!("../../tests/fixtures/doc_v1.json");
+    const DOC_V1_SVG: &str = include_str!("../../tests/fixtures/doc_v1.svg");
+
+    #[test]
+    fn test_svg_snapshot() {
+        let doc = DocFile::from_json(DOC_V1).unwrap().doc;
+        let checkpoint = Checkpoint::from_json(&doc.tabs[0].checkpoints[0]).unwrap();
+
+        assert_eq!(checkpoint_to_svg(&checkpoint, &velo_light()), DOC_V1_SVG);
+    }
+
+    #[test]
+    fn test_svg_shapes_images_and_drawings() {
+        let id = Uuid::new_v4();
+        let node = |node_type, text: &str| JsonNode {
+            id,
+            node_type,
+            x: 0.,
+            y: 0.,
+            z: 0.,
+            width: 100.,
+            height: 100.,
+            text: JsonNodeText {
+                text: text.to_string(),
+                pos: TextPos::TopLeft,
+            },
+            bg_color: "paper_node_bg".to_string(),
+        };
+        let mut checkpoint = Checkpoint {
+            nodes: vec![
+                node(NodeType::Paper, "a < b\nc"),
+                node(NodeType::Circle, ""),
+            ],
+            drawings: vec![DrawingJsonNode {
+                x: 10.,
+                y: 10.,
+                z: 1.,
+                id: crate::utils::ReflectableUuid(Uuid::new_v4()),
+                points: vec![Vec2::ZERO, Vec2::new(10., 0.)],
+                drawing_color: "unknown_key".to_string(),
+                width: 2.,
+            }],
+            ..Default::default()
+        };
+        checkpoint.insert_image(id.to_string(), "aW1n".to_string());
+
+        let svg = checkpoint_to_svg(&checkpoint, &velo_light());
+
+        assert!(svg.contains(
+            r##"<rect x="20" y="20" width="100" height="100" fill="#ffecac" stroke="none"/>"##
+        ));
+        assert!(svg.contains(r#"<circle cx="70" cy="70" r="50""#));
+        assert_eq!(svg.matches("data:image/png;base64,aW1n").count(), 2);
+        assert!(svg.contains(r#"<tspan x="30" y="39">a &lt; b</tspan>"#));
+        assert!(svg.contains(r#"<tspan x="30" y="57">c</tspan>"#));
+        assert!(svg.contains(
+            r##"<path d="M80 60 L90 60" fill="none" stroke="#000000" stroke-width="2""##
+        ));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
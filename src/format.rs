@@ -0,0 +1,342 @@
+use std::collections::BTreeMap;
+
+use serde::{de::Error as _, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::components::Doc;
+use crate::utils::image_hash;
+use crate::{DrawingJsonNode, JsonNode};
+
+/// Version of the document and checkpoint format written by this build.
+/// Bump it together with a new entry in both migration chains below.
+pub const FORMAT_VERSION: u32 = 2;
+
+type Migration = fn(&mut Value);
+
+/// `CHECKPOINT_MIGRATIONS[n]` upgrades a checkpoint from version `n` to `n + 1`.
+const CHECKPOINT_MIGRATIONS: [Migration; FORMAT_VERSION as usize] =
+    [checkpoint_v0_to_v1, checkpoint_v1_to_v2];
+
+/// `DOC_MIGRATIONS[n]` upgrades an exported document from version `n` to `n + 1`.
+const DOC_MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [doc_v0_to_v1, doc_v1_to_v2];
+
+/// Snapshot of a single tab as stored in `Tab.checkpoints`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Checkpoint {
+    pub format_version: u32,
+    /// Image hash of each node with a background image, see `image_hash`.
+    pub images: BTreeMap<String, String>,
+    pub nodes: Vec<JsonNode<String>>,
+    pub arrows: Vec<ArrowMeta>,
+    pub drawings: Vec<DrawingJsonNode<String>>,
+    /// Base64 png by hash, for checkpoints that travel without the image store:
+    /// clipboard fragments and checkpoints written before images were stored once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub blobs: BTreeMap<String, String>,
+}
+
+impl Default for Checkpoint {
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            images: BTreeMap::new(),
+            nodes: vec![],
+            arrows: vec![],
+            drawings: vec![],
+            blobs: BTreeMap::new(),
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Parses a checkpoint of any known version, upgrading it to `FORMAT_VERSION`.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let mut value: Value = serde_json::from_str(json)?;
+        migrate(&mut value, &CHECKPOINT_MIGRATIONS)?;
+        serde_json::from_value(value)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("checkpoint should serialize to json")
+    }
+
+    /// Sets the background of a node and carries the image along in `blobs`.
+    pub fn insert_image(&mut self, node_id: String, data: String) {
+        let hash = image_hash(&data);
+        self.images.insert(node_id, hash.clone());
+        self.blobs.insert(hash, data);
+    }
+
+    /// Base64 png background of a node, if it is carried along.
+    pub fn image_data(&self, node_id: &str) -> Option<&String> {
+        self.blobs.get(self.images.get(node_id)?)
+    }
+
+    /// Carries along every image of the checkpoint that `lookup` finds, e.g. before
+    /// rendering it.
+    pub fn inline_images(&mut self, lookup: impl Fn(&str) -> Option<String>) {
+        for hash in self.images.values() {
+            if !self.blobs.contains_key(hash) {
+                if let Some(data) = lookup(hash) {
+                    self.blobs.insert(hash.clone(), data);
+                }
+            }
+        }
+    }
+}
+
+/// Top-level structure of an exported document (`velo.json`, gists, `window.velo`).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DocFile {
+    pub format_version: u32,
+    #[serde(flatten)]
+    pub doc: Doc,
+    /// Base64 png by hash, every image the checkpoints of the document refer to.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub images: BTreeMap<String, String>,
+}
+
+impl DocFile {
+    /// Looks up the images of the document with `lookup`, usually in the image store.
+    pub fn new(doc: Doc, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let mut images = BTreeMap::new();
+        for json in checkpoint_jsons(&doc) {
+            let Ok(checkpoint) = Checkpoint::from_json(json) else {
+                continue;
+            };
+            for hash in checkpoint.images.values() {
+                if images.contains_key(hash) {
+                    continue;
+                }
+                if let Some(data) = checkpoint.blobs.get(hash).cloned().or_else(|| lookup(hash)) {
+                    images.insert(hash.clone(), data);
+                }
+            }
+        }
+        Self {
+            format_version: FORMAT_VERSION,
+            doc,
+            images,
+        }
+    }
+
+    /// Parses an exported document of any known version. Every checkpoint of
+    /// every tab is upgraded as well, so the result only holds current data.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        let mut value: Value = serde_json::from_str(json)?;
+        migrate(&mut value, &DOC_MIGRATIONS)?;
+        let mut doc_file: DocFile = serde_json::from_value(value)?;
+        let images = &mut doc_file.images;
+        // images of older files are in their checkpoints, they move to `images`
+        let mut upgrade = |json: &mut String| -> Result<(), serde_json::Error> {
+            let mut checkpoint = Checkpoint::from_json(json)?;
+            images.append(&mut checkpoint.blobs);
+            *json = checkpoint.to_json();
+            Ok(())
+        };
+        for tab in doc_file.doc.tabs.iter_mut() {
+            for checkpoint in tab.checkpoints.iter_mut() {
+                upgrade(checkpoint)?;
+            }
+            for snapshot in tab.history.iter_mut() {
+                upgrade(&mut snapshot.checkpoint)?;
+            }
+        }
+        Ok(doc_file)
+    }
+
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(self).expect("document should serialize to json")
+    }
+}
+
+/// Every checkpoint of the document, history included.
+pub fn checkpoint_jsons(doc: &Doc) -> impl Iterator<Item = &String> {
+    doc.tabs.iter().flat_map(|tab| {
+        tab.checkpoints
+            .iter()
+            .chain(tab.history.iter().map(|snapshot| &snapshot.checkpoint))
+    })
+}
+
+fn migrate(value: &mut Value, migrations: &[Migration]) -> Result<(), serde_json::Error> {
+    if !value.is_object() {
+        return Err(serde_json::Error::custom("expected a json object"));
+    }
+    // files written before versioning was introduced carry no version field
+    let version = value
+        .get("format_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as usize;
+    if version > migrations.len() {
+        return Err(serde_json::Error::custom(format!(
+            "format version {} is newer than supported version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    for (from, migration) in migrations.iter().enumerate().skip(version) {
+        migration(value);
+        value["format_version"] = Value::from(from as u32 + 1);
+    }
+    Ok(())
+}
+
+fn insert_missing(value: &mut Value, key: &str, default: Value) {
+    if let Some(object) = value.as_object_mut() {
+        object.entry(key).or_insert(default);
+    }
+}
+
+/// Unversioned checkpoints could lack `images`/`arrows`/`drawings`,
+/// node depth and text position.
+fn checkpoint_v0_to_v1(value: &mut Value) {
+    insert_missing(value, "images", Value::Object(Default::default()));
+    insert_missing(value, "nodes", Value::Array(vec![]));
+    insert_missing(value, "arrows", Value::Array(vec![]));
+    insert_missing(value, "drawings", Value::Array(vec![]));
+    if let Some(nodes) = value["nodes"].as_array_mut() {
+        for node in nodes.iter_mut() {
+            insert_missing(node, "z", Value::from(0.));
+            insert_missing(&mut node["text"], "pos", Value::from("Center"));
+        }
+    }
+}
+
+/// Images were embedded as base64 in every checkpoint, they are referenced by hash now
+/// and carried along in `blobs` until they reach the image store.
+fn checkpoint_v1_to_v2(value: &mut Value) {
+    let Some(images) = value["images"].as_object_mut() else {
+        return;
+    };
+    let mut blobs = serde_json::Map::new();
+    for image in images.values_mut() {
+        let Some(data) = image.as_str().map(str::to_string) else {
+            continue;
+        };
+        let hash = image_hash(&data);
+        *image = Value::from(hash.clone());
+        blobs.insert(hash, Value::from(data));
+    }
+    value["blobs"] = Value::Object(blobs);
+}
+
+/// Unversioned documents could lack tags and per-tab depth.
+fn doc_v0_to_v1(value: &mut Value) {
+    insert_missing(value, "tags", Value::Array(vec![]));
+    if let Some(tabs) = value["tabs"].as_array_mut() {
+        for tab in tabs.iter_mut() {
+            insert_missing(tab, "z_index", Value::from(1.));
+        }
+    }
+}
+
+/// Images of the document come along in `images`, older checkpoints carry their own.
+fn doc_v1_to_v2(value: &mut Value) {
+    insert_missing(value, "images", Value::Object(Default::default()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC_V0: &str = include_str!("../tests/fixtures/doc_v0.json");
+    const DOC_V1: &str = include_str!("../tests/fixtures/doc_v1.json");
+
+    /// Replaces checkpoint strings with parsed values so documents compare structurally.
+    fn normalize(doc_file: &DocFile) -> Value {
+        let mut value = serde_json::to_value(doc_file).unwrap();
+        for tab in value["tabs"].as_array_mut().unwrap() {
+            for checkpoint in tab["checkpoints"].as_array_mut().unwrap() {
+                *checkpoint = serde_json::from_str(checkpoint.as_str().unwrap()).unwrap();
+            }
+        }
+        value
+    }
+
+    #[test]
+    fn test_upgrade_v0_doc() {
+        let upgraded = DocFile::from_json(DOC_V0).unwrap();
+        let current = DocFile::from_json(DOC_V1).unwrap();
+
+        assert_eq!(upgraded.format_version, FORMAT_VERSION);
+        assert_eq!(normalize(&upgraded), normalize(&current));
+    }
+
+    #[test]
+    fn test_upgrade_v0_checkpoint() {
+        let doc: Value = serde_json::from_str(DOC_V0).unwrap();
+        let json = doc["tabs"][0]["checkpoints"][0].as_str().unwrap();
+        let checkpoint = Checkpoint::from_json(json).unwrap();
+
+        assert_eq!(checkpoint.format_version, FORMAT_VERSION);
+        assert_eq!(checkpoint.nodes.len(), 2);
+        assert_eq!(checkpoint.nodes[0].z, 0.);
+        assert_eq!(checkpoint.arrows.len(), 1);
+        assert!(checkpoint.drawings.is_empty());
+    }
+
+    #[test]
+    fn test_current_doc_roundtrip() {
+        let doc_file = DocFile::from_json(DOC_V1).unwrap();
+        let roundtrip = DocFile::from_json(&doc_file.to_json_pretty()).unwrap();
+
+        assert_eq!(normalize(&doc_file), normalize(&roundtrip));
+    }
+
+    #[test]
+    fn test_images_move_out_of_checkpoints() {
+        let checkpoint = r#"{"format_version": 1, "images": {"node": "aW1n"}, "nodes": [], "arrows": [], "drawings": []}"#;
+        let hash = image_hash("aW1n");
+        let upgraded = Checkpoint::from_json(checkpoint).unwrap();
+        assert_eq!(upgraded.images["node"], hash);
+        assert_eq!(upgraded.image_data("node").unwrap(), "aW1n");
+
+        let mut doc_file = DocFile::from_json(DOC_V1).unwrap();
+        doc_file.doc.tabs[0]
+            .checkpoints
+            .push_back(checkpoint.to_string());
+        let doc_file = DocFile::from_json(&doc_file.to_json_pretty()).unwrap();
+        assert_eq!(
+            doc_file.images,
+            BTreeMap::from([(hash.clone(), "aW1n".to_string())])
+        );
+        let stored = Checkpoint::from_json(doc_file.doc.tabs[0].checkpoints.back().unwrap());
+        assert!(stored.unwrap().blobs.is_empty());
+
+        // only referenced images are exported
+        let exported = DocFile::new(doc_file.doc.clone(), |hash| {
+            Some(format!("data of {}", hash))
+        });
+        assert_eq!(exported.images.len(), 1);
+        assert_eq!(exported.images[&hash], format!("data of {}", hash));
+        assert!(DocFile::new(doc_file.doc, |_| None).images.is_empty());
+    }
+
+    #[test]
+    fn test_reject_newer_version() {
+        let json = format!(
+            r#"{{"format_version": {}, "images": {{}}, "nodes": [], "arrows": [], "drawings": []}}"#,
+            FORMAT_VERSION + 1
+        );
+
+        assert!(Checkpoint::from_json(&json).is_err());
+    }
+
+    #[test]
+    fn test_reject_non_object() {
+        assert!(Checkpoint::from_json("123").is_err());
+        assert!(Checkpoint::from_json(r#""text""#).is_err());
+        assert!(DocFile::from_json("[]").is_err());
+    }
+
+    #[test]
+    fn test_legacy_plain_doc_is_readable() {
+        // files exported before versioning are bare `Doc` objects
+        let doc_file = DocFile::from_json(DOC_V0).unwrap();
+
+        assert_eq!(doc_file.doc.name, "Retro");
+        assert_eq!(doc_file.doc.tabs[0].z_index, 1.);
+        assert!(doc_file.doc.tags.is_empty());
+    }
+}
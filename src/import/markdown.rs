@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+use bevy_markdown::markdown::{
+    self,
+    mdast::{List, Node},
+};
+use uuid::Uuid;
+
+use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowMeta, ArrowType};
+use crate::format::Checkpoint;
+use crate::utils::ReflectableUuid;
+use crate::{JsonNode, JsonNodeText, NodeType, TextPos};
+
+/// Space between a node and its children.
+const HORIZONTAL_GAP: f32 = 80.;
+/// Space between two sibling leaves.
+const VERTICAL_GAP: f32 = 20.;
+
+/// Headings and list items of a markdown document, nested by heading depth and list indentation.
+#[derive(Default)]
+struct Outline {
+    items: Vec<(String, Vec<usize>)>,
+    roots: Vec<usize>,
+}
+
+impl Outline {
+    fn parse(root: &Node) -> Self {
+        let mut outline = Self::default();
+        let mut headings: Vec<(u8, usize)> = vec![];
+        for child in root.children().into_iter().flatten() {
+            match child {
+                Node::Heading(heading) => {
+                    while matches!(headings.last(), Some((depth, _)) if *depth >= heading.depth) {
+                        headings.pop();
+                    }
+                    let parent = headings.last().map(|(_, id)| *id);
+                    let text = format!(
+                        "{} {}",
+                        "#".repeat(heading.depth as usize),
+                        child.to_string()
+                    );
+                    let id = outline.add(parent, text);
+                    headings.push((heading.depth, id));
+                }
+                Node::List(list) => outline.add_list(headings.last().map(|(_, id)| *id), list),
+                _ => {}
+            }
+        }
+        outline
+    }
+
+    fn add(&mut self, parent: Option<usize>, text: String) -> usize {
+        let id = self.items.len();
+        self.items.push((text, vec![]));
+        match parent {
+            Some(parent) => self.items[parent].1.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+
+    fn add_list(&mut self, parent: Option<usize>, list: &List) {
+        for child in list.children.iter() {
+            if let Node::ListItem(item) = child {
+                let text = item
+                    .children
+                    .iter()
+                    .filter(|node| !matches!(node, Node::List(_)))
+                    .map(|node| node.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let id = self.add(parent, text);
+                for node in item.children.iter() {
+                    if let Node::List(inner_list) = node {
+                        self.add_list(Some(id), inner_list);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Places the subtree of `id` left to right, leaves take the next free row.
+    /// Returns the id of the created node and its vertical position.
+    fn layout(
+        &self,
+        id: usize,
+        depth: usize,
+        row: &mut usize,
+        node_size: Vec2,
+        checkpoint: &mut Checkpoint,
+    ) -> (Uuid, f32) {
+        let (text, children) = &self.items[id];
+        let children: Vec<(Uuid, f32)> = children
+            .iter()
+            .map(|child| self.layout(*child, depth + 1, row, node_size, checkpoint))
+            .collect();
+        let y = match (children.first(), children.last()) {
+            (Some(first), Some(last)) => (first.1 + last.1) / 2.,
+            _ => {
+                let y = -(*row as f32) * (node_size.y + VERTICAL_GAP);
+                *row += 1;
+                y
+            }
+        };
+        let node_id = Uuid::new_v4();
+        checkpoint.nodes.push(JsonNode {
+            id: node_id,
+            node_type: NodeType::Rect,
+            x: depth as f32 * (node_size.x + HORIZONTAL_GAP),
+            y,
+            z: 1. + checkpoint.nodes.len() as f32 * 0.01,
+            width: node_size.x,
+            height: node_size.y,
+            text: JsonNodeText {
+                text: text.clone(),
+                pos: TextPos::Center,
+            },
+            bg_color: "node_bg".to_string(),
+        });
+        for (child_id, _) in children {
+            checkpoint.arrows.push(ArrowMeta {
+                arrow_type: ArrowType::ParallelArrow,
+                start: ArrowConnect {
+                    id: ReflectableUuid(node_id),
+                    pos: ArrowConnectPos::Right,
+                },
+                end: ArrowConnect {
+                    id: ReflectableUuid(child_id),
+                    pos: ArrowConnectPos::Left,
+                },
+            });
+        }
+        (node_id, y)
+    }
+}
+
+/// Builds a mind-map checkpoint from the headings and nested list items of a markdown document.
+pub fn markdown_to_checkpoint(text: &str, node_size: Vec2) -> Result<Checkpoint, String> {
+    let root =
+        markdown::to_mdast(text, &markdown::ParseOptions::gfm()).map_err(|e| e.to_string())?;
+    let outline = Outline::parse(&root);
+    let mut checkpoint = Checkpoint::default();
+    let mut row = 0;
+    for id in outline.roots.iter() {
+        outline.layout(*id, 0, &mut row, node_size, &mut checkpoint);
+    }
+    Ok(checkpoint)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTLINE: &str = "# Retro
+
+## Went well
+
+- shipped the editor
+- fixed search
+  - fuzzy matching
+
+## To improve
+
+1. tests
+";
+
+    fn node<'a>(checkpoint: &'a Checkpoint, text: &str) -> &'a JsonNode<String> {
+        checkpoint
+            .nodes
+            .iter()
+            .find(|node| node.text.text == text)
+            .unwrap()
+    }
+
+    fn is_connected(checkpoint: &Checkpoint, parent: &str, child: &str) -> bool {
+        let (parent, child) = (node(checkpoint, parent).id, node(checkpoint, child).id);
+        checkpoint
+            .arrows
+            .iter()
+            .any(|arrow| arrow.start.id.0 == parent && arrow.end.id.0 == child)
+    }
+
+    #[test]
+    fn test_markdown_outline_to_tree() {
+        let checkpoint = markdown_to_checkpoint(OUTLINE, Vec2::new(100., 50.)).unwrap();
+
+        assert_eq!(checkpoint.nodes.len(), 7);
+        assert_eq!(checkpoint.arrows.len(), 6);
+        assert!(is_connected(&checkpoint, "# Retro", "## Went well"));
+        assert!(is_connected(&checkpoint, "# Retro", "## To improve"));
+        assert!(is_connected(&checkpoint, "## Went well", "fixed search"));
+        assert!(is_connected(&checkpoint, "fixed search", "fuzzy matching"));
+        assert!(is_connected(&checkpoint, "## To improve", "tests"));
+
+        assert_eq!(node(&checkpoint, "# Retro").x, 0.);
+        assert_eq!(node(&checkpoint, "## Went well").x, 180.);
+        assert_eq!(node(&checkpoint, "fuzzy matching").x, 540.);
+        // leaves are stacked top to bottom, parents are centered on their children
+        assert_eq!(node(&checkpoint, "shipped the editor").y, 0.);
+        assert_eq!(node(&checkpoint, "fuzzy matching").y, -70.);
+        assert_eq!(node(&checkpoint, "fixed search").y, -70.);
+        assert_eq!(node(&checkpoint, "tests").y, -140.);
+        assert_eq!(node(&checkpoint, "## Went well").y, -35.);
+    }
+
+    #[test]
+    fn test_markdown_without_outline() {
+        let checkpoint = markdown_to_checkpoint("just a paragraph", Vec2::ONE).unwrap();
+
+        assert!(checkpoint.nodes.is_empty());
+        assert!(checkpoint.arrows.is_empty());
+    }
+}
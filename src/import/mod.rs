@@ -0,0 +1,4 @@
+//! Conversion of external formats into tab checkpoints.
+
+mod markdown;
+pub use self::markdown::*;
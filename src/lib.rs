@@ -1,6 +1,16 @@
 mod canvas;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cli;
 mod components;
+mod crypto;
+mod diff;
+mod error;
+mod export;
+mod format;
+mod import;
 mod resources;
+mod share;
+mod store;
 mod systems;
 mod themes;
 mod ui_plugin;
@@ -12,9 +22,9 @@ use bevy_embedded_assets::EmbeddedAssetPlugin;
 #[cfg(not(target_arch = "wasm32"))]
 use bevy_hanabi::HanabiPlugin;
 use bevy_pancam::PanCamPlugin;
-use bevy_pkv::PkvStore;
 use canvas::CanvasPlugin;
 use resources::FontSystemState;
+use store::{open_store, Storage};
 use systems::*;
 use ui_plugin::*;
 
@@ -47,7 +57,7 @@ impl Plugin for VeloPlugin {
             .add_plugins(CanvasPlugin)
             .add_plugins(UiPlugin)
             .add_plugins(PanCamPlugin)
-            .insert_resource(PkvStore::new(ORG_NAME, APP_NAME))
+            .insert_resource(Storage(open_store()))
             .init_resource::<FontSystemState>();
 
         #[cfg(not(target_arch = "wasm32"))]
@@ -1,20 +1,63 @@
 use crate::components::Doc;
+use crate::format::Checkpoint;
+use crate::store::{DocListLayout, DocumentStore};
 #[cfg(not(target_arch = "wasm32"))]
 use crate::ui_plugin::SearchIndexState;
 use crate::utils::ReflectableUuid;
+use bevy::asset::HandleId;
 use bevy::prelude::*;
 use bevy_cosmic_edit::CosmicFont;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
 
 #[derive(Resource, Default)]
 pub struct AppState {
     pub current_document: Option<ReflectableUuid>,
     pub docs: HashMap<ReflectableUuid, Doc>,
-    pub github_token: Option<String>,
+    /// Documents in memory from the least to the most recently opened.
+    pub recent_docs: VecDeque<ReflectableUuid>,
+    /// Passphrases of the locked documents opened in this session.
+    pub passphrases: HashMap<ReflectableUuid, String>,
+    /// Images of locked documents by hash. They stay out of the image store, which keeps
+    /// images in the clear, and only go to disk sealed with their document.
+    pub locked_images: HashMap<String, String>,
+    /// Documents in memory with changes the store does not have yet.
+    pub dirty_docs: HashSet<ReflectableUuid>,
     #[cfg(not(target_arch = "wasm32"))]
     pub search_index: Option<SearchIndexState>,
     pub doc_list_ui: HashSet<ReflectableUuid>,
+    /// Tags the document list is narrowed to, a listed document has all of them.
+    pub tag_filter: BTreeSet<String>,
+    pub doc_list_layout: DocListLayout,
+}
+
+impl AppState {
+    /// Base64 png of an image, whether it belongs to a locked document or is stored.
+    pub fn image(&self, store: &dyn DocumentStore, hash: &str) -> Option<String> {
+        self.locked_images
+            .get(hash)
+            .cloned()
+            .or_else(|| store.image(hash).ok())
+    }
+
+    /// Document in memory, marked as changed since it was last saved.
+    pub fn doc_mut(&mut self, id: &ReflectableUuid) -> Option<&mut Doc> {
+        let doc = self.docs.get_mut(id)?;
+        self.dirty_docs.insert(*id);
+        Some(doc)
+    }
+
+    /// Puts a document the store does not have, or has an older state of, in memory.
+    pub fn insert_doc(&mut self, doc: Doc) {
+        self.dirty_docs.insert(doc.id);
+        self.docs.insert(doc.id, doc);
+    }
+
+    /// Marks a document as changed since it was last saved, for changes that only turn
+    /// out to be ones once the document is borrowed.
+    pub fn mark_dirty(&mut self, id: ReflectableUuid) {
+        self.dirty_docs.insert(id);
+    }
 }
 
 #[derive(Resource, Debug)]
@@ -29,6 +72,39 @@ pub struct SaveTabRequest {
     pub tab_id: ReflectableUuid,
 }
 
+/// Asks for the passphrase of a locked document before it is shown.
+#[derive(Resource, Debug)]
+pub struct UnlockDocRequest {
+    pub doc_id: ReflectableUuid,
+}
+
+#[derive(Resource, Debug)]
+pub struct JournalRequest {
+    pub doc_id: ReflectableUuid,
+}
+
+#[derive(Resource, Debug)]
+pub struct SnapshotRequest {
+    pub doc_id: ReflectableUuid,
+    pub tab_id: ReflectableUuid,
+    pub name: String,
+}
+
+/// Saves the current document, or only the tab `tab_id` of it, as a template.
+#[derive(Resource, Debug)]
+pub struct TemplateRequest {
+    pub doc_id: ReflectableUuid,
+    pub tab_id: Option<ReflectableUuid>,
+    pub name: String,
+}
+
+/// Copies a link carrying the latest state of the tab, see `encode_board`.
+#[derive(Resource, Debug)]
+pub struct ShareLinkRequest {
+    pub doc_id: ReflectableUuid,
+    pub tab_id: ReflectableUuid,
+}
+
 #[derive(Resource, Debug)]
 pub struct LoadDocRequest {
     pub doc_id: ReflectableUuid,
@@ -39,7 +115,31 @@ pub struct LoadTabRequest {
     pub doc_id: ReflectableUuid,
     pub tab_id: ReflectableUuid,
     pub drop_last_checkpoint: bool, // Useful for undo functionality
+    pub checkpoint: Option<String>, // Shown instead of the latest checkpoint, e.g. a preview
 }
 
+#[derive(Resource, Debug, PartialEq, Eq)]
+pub enum HistoryRequest {
+    Undo,
+    Redo,
+}
+
+#[derive(Resource, Debug, PartialEq, Eq)]
+pub enum ClipboardRequest {
+    Copy,
+    Cut,
+    Duplicate,
+}
+
+#[derive(Resource, Debug)]
+pub struct PasteRequest {
+    pub checkpoint: Checkpoint, // Fragment of a tab, ids are replaced when pasting
+    pub offset: Vec2,
+}
+
+/// Image hash of each node background on the canvas, so every image is encoded once.
+#[derive(Resource, Default)]
+pub struct ImageHashes(pub HashMap<HandleId, String>);
+
 #[derive(Resource, Default)]
 pub struct FontSystemState(pub Option<Handle<CosmicFont>>);
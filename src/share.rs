@@ -0,0 +1,597 @@
+//! Publishing documents so that others can open them with a link. The backend is picked
+//! with `share_backend` in `~/.velo.toml`: "gist" (the default), "gitlab" or "http".
+//! Small boards need no backend, they travel inside the link itself.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use base64::{engine::general_purpose, Engine};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::components::{Doc, Tab};
+use crate::error::VeloError;
+use crate::format::Checkpoint;
+use crate::utils::{Config, ReflectableUuid};
+
+const FILENAME: &str = "velo.json";
+const DESCRIPTION: &str = "Velo Document";
+const APP_URL: &str = "https://staffengineer.github.io/velo";
+
+/// Longest board data a link carries, longer links get cut by chat apps and servers.
+pub const MAX_BOARD_DATA_LEN: usize = 8 * 1024;
+/// Limit on the json a link expands to, so a crafted link cannot exhaust memory.
+const MAX_BOARD_JSON_LEN: u64 = 1024 * 1024;
+
+/// A published document, kept to update it when the document is shared again.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Share {
+    /// `ShareBackend::name` of the backend that published it.
+    pub backend: String,
+    /// Id of the gist or snippet, the url of the document for plain http.
+    pub id: String,
+    /// Where the document json can be downloaded.
+    pub raw_url: String,
+}
+
+impl Share {
+    /// Link opening the shared document in velo.
+    pub fn link(&self) -> String {
+        format!("{}?document={}", APP_URL, self.raw_url)
+    }
+}
+
+pub trait ShareBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Request publishing `contents`, it updates `previous` instead of creating a new
+    /// share when given.
+    fn request(
+        &self,
+        doc_id: &ReflectableUuid,
+        previous: Option<&Share>,
+        contents: &str,
+    ) -> ehttp::Request;
+    /// The share described by the response to `request`.
+    fn share(&self, doc_id: &ReflectableUuid, response: &str) -> Result<Share, VeloError>;
+}
+
+/// Request publishing `contents` with `backend`, a share made by another backend is
+/// left alone.
+pub fn share_request(
+    backend: &dyn ShareBackend,
+    doc_id: &ReflectableUuid,
+    previous: Option<&Share>,
+    contents: &str,
+) -> ehttp::Request {
+    let previous = previous.filter(|share| share.backend == backend.name());
+    backend.request(doc_id, previous, contents)
+}
+
+/// The backend set up in the config, if the config has what it needs.
+pub fn share_backend(config: &Config) -> Option<Arc<dyn ShareBackend>> {
+    match config.share_backend.as_deref().unwrap_or("gist") {
+        "gist" => {
+            let token = config.github_access_token.clone()?;
+            Some(Arc::new(GistBackend::new(token)))
+        }
+        "gitlab" => {
+            let token = config.gitlab_access_token.clone()?;
+            let mut backend = GitLabBackend::new(token);
+            if let Some(url) = &config.gitlab_url {
+                backend.url = url.clone();
+            }
+            Some(Arc::new(backend))
+        }
+        "http" => Some(Arc::new(HttpBackend {
+            url: config.share_url.clone()?,
+            token: config.share_token.clone(),
+        })),
+        _ => None,
+    }
+}
+
+fn json_request(
+    method: &str,
+    url: String,
+    body: Value,
+    headers: &[(&str, String)],
+) -> ehttp::Request {
+    let mut request = ehttp::Request::post(url, body.to_string());
+    request.method = method.to_string();
+    request
+        .headers
+        .insert("Content-Type".to_string(), "application/json".to_string());
+    for (name, value) in headers {
+        request.headers.insert(name.to_string(), value.clone());
+    }
+    request
+}
+
+fn parse_response(response: &str) -> Result<Value, VeloError> {
+    serde_json::from_str(response).map_err(|e| VeloError::Share(e.to_string()))
+}
+
+fn missing(field: &str) -> VeloError {
+    VeloError::Share(format!("the response has no {}", field))
+}
+
+/// Public GitHub gists, `github_access_token` needs the gist scope.
+pub struct GistBackend {
+    /// GitHub API, changed for GitHub Enterprise or tests.
+    pub api_url: String,
+    pub token: String,
+}
+
+impl GistBackend {
+    pub fn new(token: String) -> Self {
+        Self {
+            api_url: "https://api.github.com".to_string(),
+            token,
+        }
+    }
+}
+
+impl ShareBackend for GistBackend {
+    fn name(&self) -> &'static str {
+        "gist"
+    }
+
+    fn request(
+        &self,
+        _doc_id: &ReflectableUuid,
+        previous: Option<&Share>,
+        contents: &str,
+    ) -> ehttp::Request {
+        let api_url = self.api_url.trim_end_matches('/');
+        let files = json!({ "velo.json": { "content": contents } });
+        let headers = [
+            ("Accept", "application/vnd.github.v3+json".to_string()),
+            ("Authorization", format!("token {}", self.token)),
+        ];
+        match previous {
+            Some(share) => json_request(
+                "PATCH",
+                format!("{}/gists/{}", api_url, share.id),
+                json!({ "files": files }),
+                &headers,
+            ),
+            None => json_request(
+                "POST",
+                format!("{}/gists", api_url),
+                json!({ "description": DESCRIPTION, "public": true, "files": files }),
+                &headers,
+            ),
+        }
+    }
+
+    fn share(&self, _doc_id: &ReflectableUuid, response: &str) -> Result<Share, VeloError> {
+        let response = parse_response(response)?;
+        let id = response["id"].as_str().ok_or_else(|| missing("gist id"))?;
+        let raw_url = response["files"][FILENAME]["raw_url"]
+            .as_str()
+            .ok_or_else(|| missing("velo.json"))?;
+        Ok(Share {
+            backend: self.name().to_string(),
+            id: id.to_string(),
+            raw_url: raw_url.to_string(),
+        })
+    }
+}
+
+/// Public GitLab snippets, `gitlab_access_token` needs the api scope.
+pub struct GitLabBackend {
+    /// The GitLab instance, `gitlab_url` in the config.
+    pub url: String,
+    pub token: String,
+}
+
+impl GitLabBackend {
+    pub fn new(token: String) -> Self {
+        Self {
+            url: "https://gitlab.com".to_string(),
+            token,
+        }
+    }
+}
+
+impl ShareBackend for GitLabBackend {
+    fn name(&self) -> &'static str {
+        "gitlab"
+    }
+
+    fn request(
+        &self,
+        _doc_id: &ReflectableUuid,
+        previous: Option<&Share>,
+        contents: &str,
+    ) -> ehttp::Request {
+        let snippets_url = format!("{}/api/v4/snippets", self.url.trim_end_matches('/'));
+        let headers = [("PRIVATE-TOKEN", self.token.clone())];
+        match previous {
+            Some(share) => json_request(
+                "PUT",
+                format!("{}/{}", snippets_url, share.id),
+                json!({
+                    "files": [{ "action": "update", "file_path": FILENAME, "content": contents }]
+                }),
+                &headers,
+            ),
+            None => json_request(
+                "POST",
+                snippets_url,
+                json!({
+                    "title": DESCRIPTION,
+                    "visibility": "public",
+                    "files": [{ "file_path": FILENAME, "content": contents }]
+                }),
+                &headers,
+            ),
+        }
+    }
+
+    fn share(&self, _doc_id: &ReflectableUuid, response: &str) -> Result<Share, VeloError> {
+        let response = parse_response(response)?;
+        let id = response["id"]
+            .as_u64()
+            .ok_or_else(|| missing("snippet id"))?;
+        let raw_url = response["raw_url"]
+            .as_str()
+            .ok_or_else(|| missing("raw_url"))?;
+        Ok(Share {
+            backend: self.name().to_string(),
+            id: id.to_string(),
+            raw_url: raw_url.to_string(),
+        })
+    }
+}
+
+/// Any server taking documents by PUT, e.g. a WebDAV folder or an object storage bucket.
+/// Each document goes to `<url>/<doc id>.json`, so sharing again replaces it.
+pub struct HttpBackend {
+    /// `share_url` in the config.
+    pub url: String,
+    /// Sent as a bearer token, `share_token` in the config.
+    pub token: Option<String>,
+}
+
+impl HttpBackend {
+    fn doc_url(&self, doc_id: &ReflectableUuid) -> String {
+        format!("{}/{}.json", self.url.trim_end_matches('/'), doc_id.0)
+    }
+}
+
+impl ShareBackend for HttpBackend {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn request(
+        &self,
+        doc_id: &ReflectableUuid,
+        _previous: Option<&Share>,
+        contents: &str,
+    ) -> ehttp::Request {
+        let mut request = ehttp::Request::post(self.doc_url(doc_id), contents.to_string());
+        request.method = "PUT".to_string();
+        request
+            .headers
+            .insert("Content-Type".to_string(), "application/json".to_string());
+        if let Some(token) = &self.token {
+            request
+                .headers
+                .insert("Authorization".to_string(), format!("Bearer {}", token));
+        }
+        request
+    }
+
+    fn share(&self, doc_id: &ReflectableUuid, _response: &str) -> Result<Share, VeloError> {
+        let url = self.doc_url(doc_id);
+        Ok(Share {
+            backend: self.name().to_string(),
+            id: url.clone(),
+            raw_url: url,
+        })
+    }
+}
+
+/// The checkpoint compressed and base64url encoded, for a link. Images are left out,
+/// they would not fit.
+pub fn encode_board(checkpoint: &Checkpoint) -> Result<String, VeloError> {
+    let checkpoint = Checkpoint {
+        images: BTreeMap::new(),
+        blobs: BTreeMap::new(),
+        ..checkpoint.clone()
+    };
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(checkpoint.to_json().as_bytes())
+        .map_err(|e| VeloError::Share(e.to_string()))?;
+    let bytes = encoder
+        .finish()
+        .map_err(|e| VeloError::Share(e.to_string()))?;
+    let data = general_purpose::URL_SAFE_NO_PAD.encode(bytes);
+    if data.len() > MAX_BOARD_DATA_LEN {
+        return Err(VeloError::Share(format!(
+            "the board takes {} characters, a link carries {} at most",
+            data.len(),
+            MAX_BOARD_DATA_LEN
+        )));
+    }
+    Ok(data)
+}
+
+pub fn decode_board(data: &str) -> Result<Checkpoint, VeloError> {
+    if data.len() > MAX_BOARD_DATA_LEN {
+        return Err(VeloError::InvalidDocument(
+            "the link is too long".to_string(),
+        ));
+    }
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(data)
+        .map_err(|e| VeloError::InvalidDocument(e.to_string()))?;
+    let mut json = String::new();
+    DeflateDecoder::new(bytes.as_slice())
+        .take(MAX_BOARD_JSON_LEN + 1)
+        .read_to_string(&mut json)
+        .map_err(|e| VeloError::InvalidDocument(e.to_string()))?;
+    if json.len() as u64 > MAX_BOARD_JSON_LEN {
+        return Err(VeloError::InvalidDocument(
+            "the board in the link is too large".to_string(),
+        ));
+    }
+    let mut checkpoint = Checkpoint::from_json(&json)?;
+    checkpoint.images.clear();
+    checkpoint.blobs.clear();
+    Ok(checkpoint)
+}
+
+/// Link opening the board in velo, the data goes in the fragment so it is not sent to
+/// the server.
+pub fn board_link(data: &str) -> String {
+    format!("{}#board={}", APP_URL, data)
+}
+
+/// Board data of a link, in its query or fragment.
+pub fn board_data(link: &str) -> Option<&str> {
+    let start = link.find(|c| c == '?' || c == '#')?;
+    link[start + 1..]
+        .split(|c| c == '&' || c == '#' || c == '?')
+        .find_map(|pair| pair.strip_prefix("board="))
+}
+
+/// A document with the board of a link as its only tab. Its id comes from the data, so
+/// opening the same link again does not add another copy.
+pub fn doc_from_board(data: &str) -> Result<Doc, VeloError> {
+    let checkpoint = decode_board(data)?;
+    let hash = Sha256::digest(data.as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    let z_index = checkpoint
+        .nodes
+        .iter()
+        .map(|node| node.z)
+        .fold(1., f32::max);
+    Ok(Doc {
+        id: ReflectableUuid(uuid::Builder::from_random_bytes(bytes).into_uuid()),
+        name: "Shared board".to_string(),
+        tags: vec![],
+        tabs: vec![Tab {
+            id: ReflectableUuid::generate(),
+            name: "Tab 1".to_string(),
+            checkpoints: [checkpoint.to_json()].into(),
+            is_active: true,
+            z_index,
+            history: vec![],
+        }],
+        sealed: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use mockito::{Matcher, Server};
+
+    use super::*;
+    use crate::ui_plugin::response_text;
+
+    fn publish(
+        backend: &dyn ShareBackend,
+        doc_id: &ReflectableUuid,
+        previous: Option<&Share>,
+    ) -> Result<Share, VeloError> {
+        let request = share_request(backend, doc_id, previous, "{}");
+        let text = response_text(ehttp::fetch_blocking(&request))?;
+        backend.share(doc_id, &text)
+    }
+
+    #[test]
+    fn test_gist_backend() {
+        let mut server = Server::new();
+        let gist = json!({
+            "id": "abc",
+            "files": { "velo.json": { "raw_url": "https://gist.example/raw/1" } }
+        });
+        let create = server
+            .mock("POST", "/gists")
+            .match_header("authorization", "token secret")
+            .match_body(Matcher::PartialJson(json!({
+                "public": true,
+                "files": { "velo.json": { "content": "{}" } }
+            })))
+            .with_status(201)
+            .with_body(gist.to_string())
+            .create();
+        let update = server
+            .mock("PATCH", "/gists/abc")
+            .match_body(Matcher::PartialJson(
+                json!({ "files": { "velo.json": { "content": "{}" } } }),
+            ))
+            .with_body(gist.to_string())
+            .create();
+        let backend = GistBackend {
+            api_url: server.url(),
+            token: "secret".to_string(),
+        };
+        let doc_id = ReflectableUuid::generate();
+
+        let share = publish(&backend, &doc_id, None).unwrap();
+        assert_eq!(share.id, "abc");
+        assert_eq!(
+            share.link(),
+            "https://staffengineer.github.io/velo?document=https://gist.example/raw/1"
+        );
+        publish(&backend, &doc_id, Some(&share)).unwrap();
+        create.assert();
+        update.assert();
+    }
+
+    #[test]
+    fn test_gitlab_backend() {
+        let mut server = Server::new();
+        let snippet = json!({ "id": 42, "raw_url": "https://gitlab.example/raw" });
+        let create = server
+            .mock("POST", "/api/v4/snippets")
+            .match_header("private-token", "secret")
+            .match_body(Matcher::PartialJson(json!({ "visibility": "public" })))
+            .with_status(201)
+            .with_body(snippet.to_string())
+            .create();
+        let update = server
+            .mock("PUT", "/api/v4/snippets/42")
+            .match_body(Matcher::PartialJson(json!({
+                "files": [{ "action": "update", "file_path": "velo.json", "content": "{}" }]
+            })))
+            .with_body(snippet.to_string())
+            .create();
+        let backend = GitLabBackend {
+            url: format!("{}/", server.url()),
+            token: "secret".to_string(),
+        };
+        let doc_id = ReflectableUuid::generate();
+
+        let share = publish(&backend, &doc_id, None).unwrap();
+        assert_eq!(share.id, "42");
+        publish(&backend, &doc_id, Some(&share)).unwrap();
+        create.assert();
+        update.assert();
+    }
+
+    #[test]
+    fn test_http_backend() {
+        let mut server = Server::new();
+        let doc_id = ReflectableUuid::generate();
+        let put = server
+            .mock("PUT", format!("/velo/{}.json", doc_id.0).as_str())
+            .match_header("authorization", "Bearer secret")
+            .match_body("{}")
+            .expect(2)
+            .create();
+        let backend = HttpBackend {
+            url: format!("{}/velo", server.url()),
+            token: Some("secret".to_string()),
+        };
+        let share = publish(&backend, &doc_id, None).unwrap();
+        assert_eq!(
+            share.raw_url,
+            format!("{}/velo/{}.json", server.url(), doc_id.0)
+        );
+        publish(&backend, &doc_id, Some(&share)).unwrap();
+        put.assert();
+    }
+
+    #[test]
+    fn test_share_of_another_backend_is_not_updated() {
+        let backend = GistBackend::new("secret".to_string());
+        let share = Share {
+            backend: "gitlab".to_string(),
+            id: "42".to_string(),
+            raw_url: String::new(),
+        };
+        let request = share_request(&backend, &ReflectableUuid::generate(), Some(&share), "{}");
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "https://api.github.com/gists");
+    }
+
+    #[test]
+    fn test_failed_share() {
+        let mut server = Server::new();
+        server.mock("POST", "/gists").with_status(401).create();
+        let backend = GistBackend {
+            api_url: server.url(),
+            token: "wrong".to_string(),
+        };
+        assert!(publish(&backend, &ReflectableUuid::generate(), None).is_err());
+    }
+
+    fn board() -> Checkpoint {
+        let mut checkpoint = Checkpoint::default();
+        for i in 0..20 {
+            checkpoint.nodes.push(crate::JsonNode {
+                id: uuid::Uuid::new_v4(),
+                text: crate::JsonNodeText {
+                    text: format!("note {}", i),
+                    pos: crate::TextPos::Center,
+                },
+                bg_color: "node_bg".to_string(),
+                ..Default::default()
+            });
+        }
+        let node_id = checkpoint.nodes[0].id.to_string();
+        checkpoint.insert_image(node_id, "aW1n".repeat(1000));
+        checkpoint
+    }
+
+    #[test]
+    fn test_board_link_roundtrip() {
+        let checkpoint = board();
+        let data = encode_board(&checkpoint).unwrap();
+        let link = board_link(&data);
+        assert_eq!(board_data(&link), Some(data.as_str()));
+        assert_eq!(
+            board_data(&format!("https://example.com/velo?x=1&board={}", data)),
+            Some(data.as_str())
+        );
+        assert_eq!(board_data("https://example.com/velo?document=x"), None);
+
+        let decoded = decode_board(&data).unwrap();
+        assert_eq!(decoded.nodes.len(), 20);
+        assert_eq!(decoded.nodes[3].text.text, "note 3");
+        // images do not travel in links
+        assert!(decoded.images.is_empty());
+        assert!(decoded.blobs.is_empty());
+
+        let first = doc_from_board(&data).unwrap();
+        let second = doc_from_board(&data).unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!(first.tabs.len(), 1);
+    }
+
+    #[test]
+    fn test_board_size_limits() {
+        let mut checkpoint = Checkpoint::default();
+        for _ in 0..2000 {
+            checkpoint.nodes.push(crate::JsonNode {
+                id: uuid::Uuid::new_v4(),
+                bg_color: "node_bg".to_string(),
+                ..Default::default()
+            });
+        }
+        assert!(encode_board(&checkpoint).is_err());
+        assert!(decode_board(&"A".repeat(MAX_BOARD_DATA_LEN + 1)).is_err());
+
+        // a few bytes expanding to more than a board may take
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&vec![b' '; MAX_BOARD_JSON_LEN as usize + 10])
+            .unwrap();
+        let data = general_purpose::URL_SAFE_NO_PAD.encode(encoder.finish().unwrap());
+        assert!(data.len() <= MAX_BOARD_DATA_LEN);
+        assert!(decode_board(&data).is_err());
+        assert!(decode_board("not base64!").is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use super::{DocumentStore, StoreError};
+
+/// A directory of plain json files, one per key, easy to back up or keep in git.
+pub struct DirStore {
+    dir: PathBuf,
+}
+
+impl DirStore {
+    pub fn open(dir: &Path) -> Result<Self, StoreError> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+impl DocumentStore for DirStore {
+    fn get_raw(&self, key: &str) -> Result<Value, StoreError> {
+        let path = self.path(key);
+        if !path.exists() {
+            return Err(StoreError::NotFound(key.to_string()));
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Writes next to the target and renames, so a crash never leaves half a file.
+    fn set_raw(&mut self, key: &str, value: Value) -> Result<(), StoreError> {
+        let path = self.path(key);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(&value)?)?;
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), StoreError> {
+        let path = self.path(key);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::store::DocEntry;
+    use crate::utils::ReflectableUuid;
+
+    #[test]
+    fn test_dir_store_persists_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let id = ReflectableUuid::generate();
+        {
+            let mut store = DirStore::open(&temp_dir.path().join("velo")).unwrap();
+            assert!(matches!(store.index(), Err(StoreError::NotFound(_))));
+            let entry = DocEntry {
+                name: "Untitled".to_string(),
+                tags: vec!["work".to_string()],
+                ..Default::default()
+            };
+            store.set_index(&HashMap::from([(id, entry)])).unwrap();
+        }
+
+        let store = DirStore::open(&temp_dir.path().join("velo")).unwrap();
+        assert_eq!(store.index().unwrap()[&id].tags, vec!["work"]);
+        assert!(temp_dir.path().join("velo/index.json").exists());
+    }
+}
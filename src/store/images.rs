@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{or_default, DocumentStore, StoreError, TrashedItem};
+use crate::components::Doc;
+use crate::format::{checkpoint_jsons, Checkpoint};
+use crate::utils::image_hash;
+
+/// Stores a base64 png unless it is stored already, returns its hash.
+pub fn put_image(store: &mut dyn DocumentStore, data: &str) -> Result<String, StoreError> {
+    let hash = image_hash(data);
+    let mut hashes = or_default(store.image_hashes())?;
+    if !hashes.contains(&hash) {
+        store.set_image(&hash, data)?;
+        hashes.insert(hash.clone());
+        store.set_image_hashes(&hashes)?;
+    }
+    Ok(hash)
+}
+
+/// Stores images brought in by an imported document or an older checkpoint.
+pub fn import_images(
+    store: &mut dyn DocumentStore,
+    images: &BTreeMap<String, String>,
+) -> Result<(), StoreError> {
+    let mut hashes = or_default(store.image_hashes())?;
+    let len = hashes.len();
+    for (hash, data) in images.iter() {
+        if hashes.insert(hash.clone()) {
+            store.set_image(hash, data)?;
+        }
+    }
+    if hashes.len() > len {
+        store.set_image_hashes(&hashes)?;
+    }
+    Ok(())
+}
+
+fn add_references(doc: &Doc, referenced: &mut BTreeSet<String>) {
+    for json in checkpoint_jsons(doc) {
+        if let Ok(checkpoint) = Checkpoint::from_json(json) {
+            referenced.extend(checkpoint.images.into_values());
+        }
+    }
+}
+
+/// Hashes of the images any checkpoint of the document refers to.
+pub fn doc_images(doc: &Doc) -> BTreeSet<String> {
+    let mut referenced = BTreeSet::new();
+    add_references(doc, &mut referenced);
+    referenced
+}
+
+/// Images the stored documents, the journal and the trash refer to. Sealed documents
+/// refer to none, their images travel inside them.
+fn referenced_images(store: &dyn DocumentStore) -> Result<BTreeSet<String>, StoreError> {
+    let mut referenced = BTreeSet::new();
+    for id in or_default(store.index())?.keys() {
+        match store.doc(id) {
+            Ok(doc) => add_references(&doc, &mut referenced),
+            Err(StoreError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    match store.journal() {
+        Ok(journal) => add_references(&journal, &mut referenced),
+        Err(StoreError::NotFound(_)) => {}
+        Err(e) => return Err(e),
+    }
+    for entry in or_default(store.trash())? {
+        match entry.item {
+            TrashedItem::Doc(doc) => add_references(&doc, &mut referenced),
+            TrashedItem::Tab { tab, .. } => add_references(
+                &Doc {
+                    tabs: vec![tab],
+                    ..Default::default()
+                },
+                &mut referenced,
+            ),
+        }
+    }
+    Ok(referenced)
+}
+
+/// Removes the images no stored document, journal or trashed item refers to, returns
+/// how many went. Documents only kept in memory must be stored first.
+pub fn collect_garbage(store: &mut dyn DocumentStore) -> Result<usize, StoreError> {
+    let hashes = or_default(store.image_hashes())?;
+    if hashes.is_empty() {
+        return Ok(0);
+    }
+    remove_unreferenced(store, hashes)
+}
+
+/// Takes the images of a locked document out of the store once it is stored sealed,
+/// keeping those that other documents refer to.
+pub fn release_images(
+    store: &mut dyn DocumentStore,
+    hashes: &BTreeSet<String>,
+) -> Result<usize, StoreError> {
+    let stored: BTreeSet<String> = or_default(store.image_hashes())?
+        .intersection(hashes)
+        .cloned()
+        .collect();
+    if stored.is_empty() {
+        return Ok(0);
+    }
+    remove_unreferenced(store, stored)
+}
+
+fn remove_unreferenced(
+    store: &mut dyn DocumentStore,
+    candidates: BTreeSet<String>,
+) -> Result<usize, StoreError> {
+    let referenced = referenced_images(store)?;
+    let unreferenced: BTreeSet<String> = candidates
+        .into_iter()
+        .filter(|hash| !referenced.contains(hash))
+        .collect();
+    for hash in unreferenced.iter() {
+        store.remove_image(hash)?;
+    }
+    if !unreferenced.is_empty() {
+        let mut hashes = or_default(store.image_hashes())?;
+        hashes.retain(|hash| !unreferenced.contains(hash));
+        store.set_image_hashes(&hashes)?;
+    }
+    Ok(unreferenced.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::store::{move_to_trash, DocEntry, MemoryStore};
+
+    fn doc_with_image(hash: &str) -> Doc {
+        let mut checkpoint = Checkpoint::default();
+        checkpoint
+            .images
+            .insert(uuid::Uuid::new_v4().to_string(), hash.to_string());
+        Doc::test("doc", &[checkpoint.to_json().as_str()])
+    }
+
+    #[test]
+    fn test_images_are_stored_once() {
+        let mut store = MemoryStore::default();
+        let hash = put_image(&mut store, "aW1n").unwrap();
+        assert_eq!(put_image(&mut store, "aW1n").unwrap(), hash);
+        import_images(
+            &mut store,
+            &BTreeMap::from([(hash.clone(), "aW1n".to_string())]),
+        )
+        .unwrap();
+        assert_eq!(store.image(&hash).unwrap(), "aW1n");
+        assert_eq!(store.image_hashes().unwrap(), BTreeSet::from([hash]));
+    }
+
+    #[test]
+    fn test_collect_garbage() {
+        let mut store = MemoryStore::default();
+        let [saved, trashed, unused] = ["c2F2ZWQ=", "dHJhc2hlZA==", "dW51c2Vk"]
+            .map(|data| put_image(&mut store, data).unwrap());
+        let doc = doc_with_image(&saved);
+        store.set_doc(&doc).unwrap();
+        store
+            .set_index(&HashMap::from([(doc.id, DocEntry::new(&doc))]))
+            .unwrap();
+        move_to_trash(&mut store, TrashedItem::Doc(doc_with_image(&trashed)), 1.).unwrap();
+
+        assert_eq!(collect_garbage(&mut store).unwrap(), 1);
+        assert!(store.image(&unused).is_err());
+        assert_eq!(
+            store.image_hashes().unwrap(),
+            BTreeSet::from([saved, trashed])
+        );
+        assert_eq!(collect_garbage(&mut store).unwrap(), 0);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use super::{DocumentStore, StoreError};
+
+/// Keeps everything as json in memory and forgets it on exit. Meant for tests and
+/// for trying the app without touching saved documents.
+#[derive(Default)]
+pub struct MemoryStore {
+    values: HashMap<String, Value>,
+}
+
+impl DocumentStore for MemoryStore {
+    fn get_raw(&self, key: &str) -> Result<Value, StoreError> {
+        self.values
+            .get(key)
+            .cloned()
+            .ok_or_else(|| StoreError::NotFound(key.to_string()))
+    }
+
+    fn set_raw(&mut self, key: &str, value: Value) -> Result<(), StoreError> {
+        self.values.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), StoreError> {
+        self.values.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Doc;
+
+    #[test]
+    fn test_memory_store_roundtrip() {
+        let mut store = MemoryStore::default();
+        assert!(matches!(store.index(), Err(StoreError::NotFound(_))));
+
+        let doc = Doc::test("Untitled", &[]);
+        store.set_doc(&doc).unwrap();
+        store.set_last_saved(&doc.id).unwrap();
+
+        assert_eq!(store.doc(&doc.id).unwrap().name, "Untitled");
+        assert_eq!(store.last_saved().unwrap(), doc.id);
+        store.remove_doc(&doc.id).unwrap();
+        assert!(matches!(store.doc(&doc.id), Err(StoreError::NotFound(_))));
+    }
+}
@@ -0,0 +1,437 @@
+//! Persistence of documents. The backend is picked once at startup by `open_store`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::components::Doc;
+use crate::share::Share;
+use crate::utils::{dedup_tags, ReflectableUuid, UserPreferences};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod dir;
+#[cfg(not(target_arch = "wasm32"))]
+pub use dir::*;
+mod memory;
+pub use memory::*;
+mod pkv;
+pub use pkv::*;
+mod images;
+pub use images::*;
+mod trash;
+pub use trash::*;
+mod templates;
+pub use templates::*;
+
+#[derive(Debug)]
+pub enum StoreError {
+    NotFound(String),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Backend(String),
+    /// The document is sealed, it can only change once it is unlocked.
+    Locked(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound(key) => write!(f, "nothing stored under {}", key),
+            StoreError::Io(e) => write!(f, "{}", e),
+            StoreError::Json(e) => write!(f, "{}", e),
+            StoreError::Backend(e) => write!(f, "{}", e),
+            StoreError::Locked(name) => write!(f, "{} is locked", name),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StoreError {
+    fn from(e: serde_json::Error) -> Self {
+        StoreError::Json(e)
+    }
+}
+
+/// What the document list needs to know about a document without loading it.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DocEntry {
+    pub name: String,
+    pub tags: Vec<String>,
+    /// Folder path with "/" between nested folders, empty for the top level.
+    pub folder: String,
+    /// Pinned documents head the list.
+    pub pinned: bool,
+    /// Milliseconds since the epoch, 0 when it is not known.
+    pub created: f64,
+    pub modified: f64,
+    /// Place in the order set by dragging documents in the list.
+    pub position: f64,
+}
+
+impl DocEntry {
+    pub fn new(doc: &Doc) -> Self {
+        Self {
+            name: doc.name.clone(),
+            tags: doc.tags.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Brings the index entry of `doc` up to date, a new entry goes last in the manual order.
+/// `modified_at` is given when the content of the document has just changed.
+pub fn update_index_entry(
+    index: &mut HashMap<ReflectableUuid, DocEntry>,
+    doc: &Doc,
+    modified_at: Option<f64>,
+) {
+    let last = index
+        .values()
+        .map(|entry| entry.position)
+        .fold(0., f64::max);
+    let entry = index.entry(doc.id).or_insert_with(|| DocEntry {
+        position: last + 1.,
+        ..Default::default()
+    });
+    entry.name = doc.name.clone();
+    entry.tags = dedup_tags(&doc.tags);
+    if let Some(modified_at) = modified_at {
+        if entry.created == 0. {
+            entry.created = modified_at;
+        }
+        entry.modified = modified_at;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DocSort {
+    #[default]
+    Name,
+    /// Newest first.
+    Created,
+    /// Most recently changed first.
+    Modified,
+    /// As the documents were dragged.
+    Manual,
+}
+
+impl DocSort {
+    pub fn next(self) -> Self {
+        match self {
+            DocSort::Name => DocSort::Created,
+            DocSort::Created => DocSort::Modified,
+            DocSort::Modified => DocSort::Manual,
+            DocSort::Manual => DocSort::Name,
+        }
+    }
+}
+
+impl fmt::Display for DocSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocSort::Name => write!(f, "name"),
+            DocSort::Created => write!(f, "creation time"),
+            DocSort::Modified => write!(f, "modification time"),
+            DocSort::Manual => write!(f, "hand"),
+        }
+    }
+}
+
+/// How the document list is laid out, kept next to the index.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct DocListLayout {
+    pub sort: DocSort,
+    /// Paths of the folders whose documents are hidden.
+    pub collapsed: BTreeSet<String>,
+}
+
+const INDEX_KEY: &str = "index";
+const DOC_LIST_KEY: &str = "doc_list";
+const LAST_SAVED_KEY: &str = "last_saved";
+const PREFERENCES_KEY: &str = "user_preferences";
+const JOURNAL_KEY: &str = "journal";
+const TRASH_KEY: &str = "trash";
+const TEMPLATES_KEY: &str = "templates";
+const SHARES_KEY: &str = "shares";
+const IMAGES_KEY: &str = "images";
+/// Keys of earlier versions, only read to migrate them.
+const LEGACY_DOCS_KEY: &str = "docs";
+const LEGACY_NAMES_KEY: &str = "names";
+const LEGACY_TAGS_KEY: &str = "tags";
+
+/// The stored value, or an empty one when nothing was stored yet.
+pub fn or_default<T: Default>(result: Result<T, StoreError>) -> Result<T, StoreError> {
+    match result {
+        Err(StoreError::NotFound(_)) => Ok(T::default()),
+        result => result,
+    }
+}
+
+fn doc_key(id: &ReflectableUuid) -> String {
+    format!("doc-{}", id.0)
+}
+
+fn image_key(hash: &str) -> String {
+    format!("image-{}", hash)
+}
+
+fn get<T: DeserializeOwned, S: DocumentStore + ?Sized>(
+    store: &S,
+    key: &str,
+) -> Result<T, StoreError> {
+    Ok(serde_json::from_value(store.get_raw(key)?)?)
+}
+
+fn set<T: Serialize + ?Sized, S: DocumentStore + ?Sized>(
+    store: &mut S,
+    key: &str,
+    value: &T,
+) -> Result<(), StoreError> {
+    store.set_raw(key, serde_json::to_value(value)?)
+}
+
+/// Everything the app keeps between runs. Each document lives under its own key and
+/// the index lists them all. Backends only store json values under string keys, the
+/// typed accessors are shared. Getters fail with `StoreError::NotFound` when nothing
+/// was stored yet.
+pub trait DocumentStore: Send + Sync {
+    fn get_raw(&self, key: &str) -> Result<Value, StoreError>;
+    fn set_raw(&mut self, key: &str, value: Value) -> Result<(), StoreError>;
+    /// Removing a missing key is not an error.
+    fn remove(&mut self, key: &str) -> Result<(), StoreError>;
+
+    fn doc(&self, id: &ReflectableUuid) -> Result<Doc, StoreError> {
+        get(self, &doc_key(id))
+    }
+
+    fn set_doc(&mut self, doc: &Doc) -> Result<(), StoreError> {
+        set(self, &doc_key(&doc.id), doc)
+    }
+
+    fn remove_doc(&mut self, id: &ReflectableUuid) -> Result<(), StoreError> {
+        self.remove(&doc_key(id))
+    }
+
+    fn index(&self) -> Result<HashMap<ReflectableUuid, DocEntry>, StoreError> {
+        get(self, INDEX_KEY)
+    }
+
+    fn set_index(&mut self, index: &HashMap<ReflectableUuid, DocEntry>) -> Result<(), StoreError> {
+        set(self, INDEX_KEY, index)
+    }
+
+    fn doc_list_layout(&self) -> Result<DocListLayout, StoreError> {
+        get(self, DOC_LIST_KEY)
+    }
+
+    fn set_doc_list_layout(&mut self, layout: &DocListLayout) -> Result<(), StoreError> {
+        set(self, DOC_LIST_KEY, layout)
+    }
+
+    fn last_saved(&self) -> Result<ReflectableUuid, StoreError> {
+        get(self, LAST_SAVED_KEY)
+    }
+
+    fn set_last_saved(&mut self, doc_id: &ReflectableUuid) -> Result<(), StoreError> {
+        set(self, LAST_SAVED_KEY, doc_id)
+    }
+
+    fn preferences(&self) -> Result<UserPreferences, StoreError> {
+        get(self, PREFERENCES_KEY)
+    }
+
+    fn set_preferences(&mut self, preferences: &UserPreferences) -> Result<(), StoreError> {
+        set(self, PREFERENCES_KEY, preferences)
+    }
+
+    /// Autosaved copy of the document being edited, dropped once it is saved.
+    fn journal(&self) -> Result<Doc, StoreError> {
+        get(self, JOURNAL_KEY)
+    }
+
+    fn set_journal(&mut self, doc: &Doc) -> Result<(), StoreError> {
+        set(self, JOURNAL_KEY, doc)
+    }
+
+    fn remove_journal(&mut self) -> Result<(), StoreError> {
+        self.remove(JOURNAL_KEY)
+    }
+
+    /// Deleted documents and tabs, oldest first.
+    fn trash(&self) -> Result<Vec<TrashEntry>, StoreError> {
+        get(self, TRASH_KEY)
+    }
+
+    fn set_trash(&mut self, trash: &[TrashEntry]) -> Result<(), StoreError> {
+        set(self, TRASH_KEY, trash)
+    }
+
+    /// Templates saved by the user, the built-in ones are not stored.
+    fn templates(&self) -> Result<Vec<Template>, StoreError> {
+        get(self, TEMPLATES_KEY)
+    }
+
+    fn set_templates(&mut self, templates: &[Template]) -> Result<(), StoreError> {
+        set(self, TEMPLATES_KEY, templates)
+    }
+
+    /// Where each shared document was published, to update it when shared again.
+    fn shares(&self) -> Result<HashMap<ReflectableUuid, Share>, StoreError> {
+        get(self, SHARES_KEY)
+    }
+
+    fn set_shares(&mut self, shares: &HashMap<ReflectableUuid, Share>) -> Result<(), StoreError> {
+        set(self, SHARES_KEY, shares)
+    }
+
+    /// Node backgrounds as base64 png, each stored once under its `image_hash`.
+    fn image(&self, hash: &str) -> Result<String, StoreError> {
+        get(self, &image_key(hash))
+    }
+
+    fn set_image(&mut self, hash: &str, data: &str) -> Result<(), StoreError> {
+        set(self, &image_key(hash), data)
+    }
+
+    fn remove_image(&mut self, hash: &str) -> Result<(), StoreError> {
+        self.remove(&image_key(hash))
+    }
+
+    /// Hashes of all stored images.
+    fn image_hashes(&self) -> Result<BTreeSet<String>, StoreError> {
+        get(self, IMAGES_KEY)
+    }
+
+    fn set_image_hashes(&mut self, hashes: &BTreeSet<String>) -> Result<(), StoreError> {
+        set(self, IMAGES_KEY, hashes)
+    }
+
+    /// The map of all documents stored under "docs" by earlier versions.
+    fn legacy_docs(&self) -> Result<HashMap<ReflectableUuid, Doc>, StoreError> {
+        get(self, LEGACY_DOCS_KEY)
+    }
+
+    /// Drops "docs" and the "names" and "tags" maps that came with it.
+    fn remove_legacy_docs(&mut self) -> Result<(), StoreError> {
+        self.remove(LEGACY_DOCS_KEY)?;
+        self.remove(LEGACY_NAMES_KEY)?;
+        self.remove(LEGACY_TAGS_KEY)
+    }
+}
+
+/// Moves the documents of earlier versions to their own keys, once: the legacy map
+/// is removed afterwards. Returns the number of migrated documents.
+pub fn migrate_legacy_docs(store: &mut dyn DocumentStore) -> Result<usize, StoreError> {
+    let docs = match store.legacy_docs() {
+        Ok(docs) => docs,
+        Err(StoreError::NotFound(_)) => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut index = or_default(store.index())?;
+    for doc in docs.values() {
+        // documents saved since are newer than their legacy copy
+        if index.contains_key(&doc.id) {
+            continue;
+        }
+        store.set_doc(doc)?;
+        index.insert(doc.id, DocEntry::new(doc));
+    }
+    store.set_index(&index)?;
+    store.remove_legacy_docs()?;
+    Ok(docs.len())
+}
+
+/// The document store of the running app.
+#[derive(Resource, Deref, DerefMut)]
+pub struct Storage(pub Box<dyn DocumentStore>);
+
+/// Opens the configured store and brings data of earlier versions up to date.
+pub fn open_store() -> Box<dyn DocumentStore> {
+    let mut store = open_backend();
+    match migrate_legacy_docs(store.as_mut()) {
+        Ok(0) => {}
+        Ok(count) => info!("Moved {} documents to per-document keys", count),
+        Err(e) => error!("Error migrating documents: {}", e),
+    }
+    store
+}
+
+/// Opens the backend configured in `~/.velo.toml`:
+///
+/// ```toml
+/// store = "dir"                   # "pkv" (default), "dir" or "memory"
+/// store_dir = "/home/me/velo"     # directory of json files for "dir"
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+fn open_backend() -> Box<dyn DocumentStore> {
+    let config = crate::utils::read_config_file().unwrap_or_default();
+    match (config.store.as_deref(), config.store_dir) {
+        (Some("memory"), _) => Box::new(MemoryStore::default()),
+        (Some("dir"), Some(dir)) => match DirStore::open(&dir) {
+            Ok(store) => Box::new(store),
+            Err(e) => {
+                error!("Error opening store in {}: {}", dir.display(), e);
+                Box::new(PkvDocumentStore::open())
+            }
+        },
+        (Some("dir"), None) => {
+            error!("store_dir is required for store = \"dir\"");
+            Box::new(PkvDocumentStore::open())
+        }
+        _ => Box::new(PkvDocumentStore::open()),
+    }
+}
+
+/// Browsers only offer local storage, which is what pkv uses.
+#[cfg(target_arch = "wasm32")]
+fn open_backend() -> Box<dyn DocumentStore> {
+    Box::new(PkvDocumentStore::open())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(name: &str) -> Doc {
+        Doc {
+            tags: vec!["tag".to_string()],
+            ..Doc::test(name, &[])
+        }
+    }
+
+    #[test]
+    fn test_migrate_legacy_docs() {
+        let (old, newer) = (doc("old"), doc("newer"));
+        let mut store = MemoryStore::default();
+        let legacy = HashMap::from([(old.id, old.clone()), (newer.id, newer.clone())]);
+        set(&mut store, LEGACY_DOCS_KEY, &legacy).unwrap();
+        let mut renamed = newer.clone();
+        renamed.name = "renamed".to_string();
+        store.set_doc(&renamed).unwrap();
+        store
+            .set_index(&HashMap::from([(renamed.id, DocEntry::new(&renamed))]))
+            .unwrap();
+
+        assert_eq!(migrate_legacy_docs(&mut store).unwrap(), 2);
+
+        assert_eq!(store.doc(&old.id).unwrap().name, "old");
+        assert_eq!(store.doc(&newer.id).unwrap().name, "renamed");
+        let index = store.index().unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[&old.id], DocEntry::new(&old));
+        assert!(matches!(store.legacy_docs(), Err(StoreError::NotFound(_))));
+        assert_eq!(migrate_legacy_docs(&mut store).unwrap(), 0);
+    }
+}
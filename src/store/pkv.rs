@@ -0,0 +1,35 @@
+use bevy_pkv::{GetError, PkvStore};
+use serde_json::Value;
+
+use super::{DocumentStore, StoreError};
+use crate::{APP_NAME, ORG_NAME};
+
+/// Key-value store of the platform: a database file on native, local storage on web.
+pub struct PkvDocumentStore(PkvStore);
+
+impl PkvDocumentStore {
+    pub fn open() -> Self {
+        Self(PkvStore::new(ORG_NAME, APP_NAME))
+    }
+}
+
+impl DocumentStore for PkvDocumentStore {
+    fn get_raw(&self, key: &str) -> Result<Value, StoreError> {
+        self.0.get(key).map_err(|e| match e {
+            GetError::NotFound => StoreError::NotFound(key.to_string()),
+            e => StoreError::Backend(format!("{:?}", e)),
+        })
+    }
+
+    fn set_raw(&mut self, key: &str, value: Value) -> Result<(), StoreError> {
+        self.0
+            .set(key, &value)
+            .map_err(|e| StoreError::Backend(format!("{:?}", e)))
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), StoreError> {
+        self.0
+            .remove(key)
+            .map_err(|e| StoreError::Backend(format!("{:?}", e)))
+    }
+}
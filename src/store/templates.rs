@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+
+use super::{or_default, DocumentStore, StoreError};
+use crate::components::{Doc, Tab};
+use crate::format::Checkpoint;
+use crate::utils::ReflectableUuid;
+
+/// A starting point for new documents, saved from a document or a single tab.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Template {
+    pub id: ReflectableUuid,
+    pub name: String,
+    pub tabs: Vec<TemplateTab>,
+}
+
+/// Latest state of a tab as a checkpoint in json, with its images carried along so
+/// the template does not depend on the image store.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TemplateTab {
+    pub name: String,
+    pub checkpoint: String,
+}
+
+impl TemplateTab {
+    pub fn new(tab: &Tab, lookup: impl Fn(&str) -> Option<String>) -> Result<Self, StoreError> {
+        let mut checkpoint = match tab.checkpoints.back() {
+            Some(json) => Checkpoint::from_json(json)?,
+            None => Checkpoint::default(),
+        };
+        checkpoint.inline_images(lookup);
+        Ok(Self {
+            name: tab.name.clone(),
+            checkpoint: checkpoint.to_json(),
+        })
+    }
+}
+
+impl Template {
+    pub fn from_doc(
+        doc: &Doc,
+        name: String,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, StoreError> {
+        if doc.sealed.is_some() {
+            return Err(StoreError::Locked(doc.name.clone()));
+        }
+        let tabs = doc
+            .tabs
+            .iter()
+            .map(|tab| TemplateTab::new(tab, &lookup))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            id: ReflectableUuid::generate(),
+            name,
+            tabs,
+        })
+    }
+
+    pub fn from_tab(
+        tab: &Tab,
+        name: String,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, StoreError> {
+        Ok(Self {
+            id: ReflectableUuid::generate(),
+            name,
+            tabs: vec![TemplateTab::new(tab, lookup)?],
+        })
+    }
+}
+
+pub fn add_template(store: &mut dyn DocumentStore, template: Template) -> Result<(), StoreError> {
+    let mut templates = or_default(store.templates())?;
+    templates.push(template);
+    store.set_templates(&templates)
+}
+
+pub fn remove_template(
+    store: &mut dyn DocumentStore,
+    id: &ReflectableUuid,
+) -> Result<(), StoreError> {
+    let mut templates = or_default(store.templates())?;
+    templates.retain(|template| template.id != *id);
+    store.set_templates(&templates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+    use crate::utils::image_hash;
+
+    fn tab() -> Tab {
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.insert_image("node".to_string(), "aW1n".to_string());
+        checkpoint.blobs.clear();
+        Tab {
+            id: ReflectableUuid::generate(),
+            name: "Board".to_string(),
+            checkpoints: [Checkpoint::default().to_json(), checkpoint.to_json()].into(),
+            is_active: true,
+            z_index: 1.,
+            history: vec![],
+        }
+    }
+
+    #[test]
+    fn test_template_carries_images() {
+        let hash = image_hash("aW1n");
+        let lookup = |h: &str| (h == hash).then(|| "aW1n".to_string());
+        let template = Template::from_tab(&tab(), "Board".to_string(), lookup).unwrap();
+        assert_eq!(template.tabs.len(), 1);
+        let checkpoint = Checkpoint::from_json(&template.tabs[0].checkpoint).unwrap();
+        assert_eq!(checkpoint.image_data("node"), Some(&"aW1n".to_string()));
+    }
+
+    #[test]
+    fn test_locked_doc_is_not_a_template() {
+        let doc = Doc {
+            tabs: vec![],
+            sealed: Some(crate::crypto::Sealed {
+                salt: String::new(),
+                nonce: String::new(),
+                ciphertext: String::new(),
+            }),
+            ..Doc::test("Secret", &[])
+        };
+        assert!(Template::from_doc(&doc, "Secret".to_string(), |_| None).is_err());
+    }
+
+    #[test]
+    fn test_add_and_remove_template() {
+        let mut store = MemoryStore::default();
+        assert!(or_default(store.templates()).unwrap().is_empty());
+        let first = Template::from_tab(&tab(), "First".to_string(), |_| None).unwrap();
+        let second = Template::from_tab(&tab(), "Second".to_string(), |_| None).unwrap();
+        add_template(&mut store, first.clone()).unwrap();
+        add_template(&mut store, second.clone()).unwrap();
+        remove_template(&mut store, &first.id).unwrap();
+        assert_eq!(or_default(store.templates()).unwrap(), vec![second]);
+    }
+}
@@ -0,0 +1,192 @@
+use serde::{Deserialize, Serialize};
+
+use super::{or_default, DocEntry, DocumentStore, StoreError};
+use crate::components::{Doc, Tab};
+use crate::utils::ReflectableUuid;
+
+/// A deleted document or tab, kept until it is restored or purged.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TrashEntry {
+    pub id: ReflectableUuid,
+    /// Milliseconds since the epoch, as returned by `get_timestamp`.
+    pub deleted_at: f64,
+    pub item: TrashedItem,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum TrashedItem {
+    Doc(Doc),
+    Tab {
+        doc_id: ReflectableUuid,
+        doc_name: String,
+        tab: Tab,
+    },
+}
+
+impl TrashEntry {
+    pub fn name(&self) -> String {
+        match &self.item {
+            TrashedItem::Doc(doc) => doc.name.clone(),
+            TrashedItem::Tab { doc_name, tab, .. } => format!("{} ({})", tab.name, doc_name),
+        }
+    }
+}
+
+/// Moves a document out of the index, or keeps a tab removed from its document.
+pub fn move_to_trash(
+    store: &mut dyn DocumentStore,
+    item: TrashedItem,
+    deleted_at: f64,
+) -> Result<(), StoreError> {
+    if let TrashedItem::Doc(doc) = &item {
+        match store.remove_doc(&doc.id) {
+            Ok(()) | Err(StoreError::NotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        let mut index = or_default(store.index())?;
+        if index.remove(&doc.id).is_some() {
+            store.set_index(&index)?;
+        }
+    }
+    let mut trash = or_default(store.trash())?;
+    trash.push(TrashEntry {
+        id: ReflectableUuid::generate(),
+        deleted_at,
+        item,
+    });
+    store.set_trash(&trash)
+}
+
+/// Puts the item back where it was deleted from. A tab goes back to the end of its
+/// document, which must still be stored and not be locked.
+pub fn restore_from_trash(
+    store: &mut dyn DocumentStore,
+    id: &ReflectableUuid,
+) -> Result<TrashedItem, StoreError> {
+    let mut trash = or_default(store.trash())?;
+    let Some(position) = trash.iter().position(|entry| entry.id == *id) else {
+        return Err(StoreError::NotFound(format!("trash-{}", id.0)));
+    };
+    let entry = trash.remove(position);
+    match &entry.item {
+        TrashedItem::Doc(doc) => {
+            store.set_doc(doc)?;
+            let mut index = or_default(store.index())?;
+            index.insert(doc.id, DocEntry::new(doc));
+            store.set_index(&index)?;
+        }
+        TrashedItem::Tab { doc_id, tab, .. } => {
+            let mut doc = store.doc(doc_id)?;
+            if doc.sealed.is_some() {
+                return Err(StoreError::Locked(doc.name));
+            }
+            if doc.tabs.iter().all(|other| other.id != tab.id) {
+                doc.tabs.push(Tab {
+                    is_active: false,
+                    ..tab.clone()
+                });
+                store.set_doc(&doc)?;
+            }
+        }
+    }
+    store.set_trash(&trash)?;
+    Ok(entry.item)
+}
+
+pub fn purge_from_trash(
+    store: &mut dyn DocumentStore,
+    id: &ReflectableUuid,
+) -> Result<(), StoreError> {
+    let mut trash = or_default(store.trash())?;
+    trash.retain(|entry| entry.id != *id);
+    store.set_trash(&trash)
+}
+
+/// Drops everything deleted before `deleted_before`, returns how many items went.
+pub fn purge_trash_before(
+    store: &mut dyn DocumentStore,
+    deleted_before: f64,
+) -> Result<usize, StoreError> {
+    let mut trash = or_default(store.trash())?;
+    let len = trash.len();
+    trash.retain(|entry| entry.deleted_at >= deleted_before);
+    if trash.len() < len {
+        store.set_trash(&trash)?;
+    }
+    Ok(len - trash.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::store::MemoryStore;
+
+    #[test]
+    fn test_trash_and_restore_doc() {
+        let mut store = MemoryStore::default();
+        let doc = Doc::test("doc", &[]);
+        store.set_doc(&doc).unwrap();
+        store
+            .set_index(&HashMap::from([(doc.id, DocEntry::new(&doc))]))
+            .unwrap();
+
+        move_to_trash(&mut store, TrashedItem::Doc(doc.clone()), 1.).unwrap();
+        assert!(store.doc(&doc.id).is_err());
+        assert!(store.index().unwrap().is_empty());
+        let trash = store.trash().unwrap();
+        assert_eq!(trash[0].name(), "doc");
+
+        restore_from_trash(&mut store, &trash[0].id).unwrap();
+        assert_eq!(store.doc(&doc.id).unwrap().name, "doc");
+        assert!(store.index().unwrap().contains_key(&doc.id));
+        assert!(store.trash().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_restore_tab_once() {
+        let mut store = MemoryStore::default();
+        let mut doc = Doc::test("doc", &[]);
+        let tab = doc.tabs[0].clone();
+        let item = TrashedItem::Tab {
+            doc_id: doc.id,
+            doc_name: doc.name.clone(),
+            tab,
+        };
+        move_to_trash(&mut store, item.clone(), 1.).unwrap();
+        move_to_trash(&mut store, item, 2.).unwrap();
+        let trash = store.trash().unwrap();
+        // the document has to be stored for its tab to come back
+        assert!(restore_from_trash(&mut store, &trash[0].id).is_err());
+        assert_eq!(store.trash().unwrap().len(), 2);
+
+        doc.tabs.clear();
+        store.set_doc(&doc).unwrap();
+        restore_from_trash(&mut store, &trash[0].id).unwrap();
+        restore_from_trash(&mut store, &trash[1].id).unwrap();
+        let tabs = store.doc(&doc.id).unwrap().tabs;
+        assert_eq!(tabs.len(), 1);
+        assert!(!tabs[0].is_active);
+    }
+
+    #[test]
+    fn test_purge_trash() {
+        let mut store = MemoryStore::default();
+        for deleted_at in [1., 2., 3.] {
+            move_to_trash(
+                &mut store,
+                TrashedItem::Doc(Doc::test("doc", &[])),
+                deleted_at,
+            )
+            .unwrap();
+        }
+        assert_eq!(purge_trash_before(&mut store, 2.).unwrap(), 1);
+        assert_eq!(purge_trash_before(&mut store, 2.).unwrap(), 0);
+        let id = store.trash().unwrap()[0].id;
+        purge_from_trash(&mut store, &id).unwrap();
+        let trash = store.trash().unwrap();
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].deleted_at, 3.);
+    }
+}
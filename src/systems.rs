@@ -1,5 +1,6 @@
 use crate::{
     components::{EffectsCamera, MainCamera},
+    store::Storage,
     themes::{get_theme_by_name, Theme},
     utils::get_theme_key,
 };
@@ -9,10 +10,9 @@ use bevy::{
     render::{camera::ScalingMode, view::RenderLayers},
 };
 use bevy_pancam::PanCam;
-use bevy_pkv::PkvStore;
 
-pub fn setup_velo_theme(mut commands: Commands, pkv: Res<PkvStore>) {
-    let theme_key = get_theme_key(&pkv);
+pub fn setup_velo_theme(mut commands: Commands, storage: Res<Storage>) {
+    let theme_key = get_theme_key(&storage);
     let theme = get_theme_by_name(&theme_key);
     commands.insert_resource(theme);
 }
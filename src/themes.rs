@@ -25,10 +25,14 @@ pub struct Theme {
     pub color_change_4: Color,
     pub color_change_5: Color,
     pub del_button: Color,
+    pub diff_added: Color,
+    pub diff_changed: Color,
+    pub diff_removed: Color,
     pub doc_list_bg: Color,
     pub font_name: String,
     pub font_size: f32,
     pub font: Color,
+    pub frame_bg: Color,
     pub front_back_btn_bg: Color,
     pub inline_code: Color,
     pub left_panel_bg: Color,
@@ -85,10 +89,14 @@ pub fn velo_light() -> Theme {
         color_change_4: Color::rgb(239., 68.0 / 255.0, 68.0 / 255.0),
         color_change_5: Color::rgb(34.0 / 255.0, 197.0 / 255.0, 94.0 / 255.0),
         del_button: Color::BLACK,
+        diff_added: Color::rgb(67.0 / 255.0, 160.0 / 255.0, 71.0 / 255.0),
+        diff_changed: Color::rgb(1., 160.0 / 255.0, 0.),
+        diff_removed: Color::rgb(229.0 / 255.0, 57.0 / 255.0, 53.0 / 255.0),
         doc_list_bg: Color::rgb(158., 158., 158.),
         font_name: "Victor Mono".to_string(),
         font_size: 14.,
         font: Color::rgb(0.0, 0.0, 0.0),
+        frame_bg: Color::rgba(0.5, 0.5, 0.5, 0.08),
         front_back_btn_bg: Color::rgb(207.0 / 255.0, 216.0 / 255.0, 220.0 / 255.0),
         inline_code: Color::GRAY,
         left_panel_bg: Color::rgb(224.0 / 255.0, 224.0 / 255.0, 224.0 / 255.0),
@@ -146,10 +154,14 @@ pub fn velo_dark() -> Theme {
         color_change_4: Color::rgb(239., 68.0 / 255.0, 68.0 / 255.0),
         color_change_5: Color::rgb(34.0 / 255.0, 197.0 / 255.0, 94.0 / 255.0),
         del_button: Color::WHITE,
+        diff_added: Color::rgb(67.0 / 255.0, 160.0 / 255.0, 71.0 / 255.0),
+        diff_changed: Color::rgb(1., 160.0 / 255.0, 0.),
+        diff_removed: Color::rgb(229.0 / 255.0, 57.0 / 255.0, 53.0 / 255.0),
         doc_list_bg: Color::rgb(0.2, 0.2, 0.2),
         font_name: "Source Code Pro".to_string(),
         font_size: 14.,
         font: Color::rgb(240. / 255.0, 240. / 255.0, 240. / 255.0),
+        frame_bg: Color::rgba(1., 1., 1., 0.05),
         front_back_btn_bg: Color::rgb(0.9, 0.9, 0.9),
         inline_code: Color::WHITE,
         left_panel_bg: Color::GRAY,
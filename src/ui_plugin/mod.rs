@@ -3,11 +3,13 @@ use bevy::prelude::*;
 
 use serde::{Deserialize, Serialize};
 
-use crate::resources::AppState;
+use crate::error::VeloError;
+use crate::resources::{AppState, ImageHashes};
 
 use crate::canvas::arrow::components::{ArrowConnect, ArrowType};
 use crate::canvas::arrow::events::{CreateArrow, RedrawArrow};
 use crate::utils::ReflectableUuid;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use uuid::Uuid;
 #[path = "ui_helpers/ui_helpers.rs"]
@@ -80,6 +82,54 @@ pub use effects::*;
 mod drawing;
 use drawing::*;
 
+#[path = "systems/selection.rs"]
+mod selection;
+use selection::*;
+
+#[path = "systems/history.rs"]
+mod history;
+pub use history::*;
+
+#[path = "systems/frames.rs"]
+mod frames;
+pub use frames::*;
+
+#[path = "systems/clipboard.rs"]
+mod clipboard;
+use clipboard::*;
+
+#[path = "systems/autosave.rs"]
+mod autosave;
+use autosave::*;
+
+#[path = "systems/trash.rs"]
+mod trash;
+use trash::*;
+
+#[path = "systems/snapshots.rs"]
+mod snapshots;
+use snapshots::*;
+
+#[path = "systems/lock.rs"]
+mod lock;
+use lock::*;
+
+#[path = "systems/tags.rs"]
+mod tags;
+use tags::*;
+
+#[path = "systems/templates.rs"]
+mod templates;
+use templates::*;
+
+#[path = "systems/share.rs"]
+mod share;
+use share::*;
+
+#[path = "systems/toasts.rs"]
+mod toasts;
+use toasts::*;
+
 #[path = "systems/active_editor_changed.rs"]
 mod active_editor_changed;
 use active_editor_changed::*;
@@ -117,34 +167,71 @@ pub struct NodeInteraction {
 #[derive(Event)]
 pub struct UpdateDeleteDocBtn;
 
+/// The document index changed, e.g. a document was pinned or moved to a folder.
+#[derive(Event)]
+pub struct RefreshDocList;
+
+/// Documents json on its way to `load_doc_handler`, from files, urls and shared links.
 #[derive(Resource, Clone)]
 pub struct CommChannels {
     pub tx: Sender<String>,
     pub rx: Receiver<String>,
 }
 
+impl Default for CommChannels {
+    fn default() -> Self {
+        let (tx, rx) = async_channel::unbounded();
+        Self { tx, rx }
+    }
+}
+
+impl CommChannels {
+    pub fn send(&self, json: String) -> Result<(), VeloError> {
+        self.tx
+            .try_send(json)
+            .map_err(|e| VeloError::InvalidDocument(e.to_string()))
+    }
+}
+
+/// Body of a successful http response.
+pub fn response_text(result: Result<ehttp::Response, String>) -> Result<String, VeloError> {
+    let response = result.map_err(VeloError::Network)?;
+    if !response.ok {
+        return Err(VeloError::Network(format!(
+            "{} {}",
+            response.status, response.status_text
+        )));
+    }
+    response
+        .text()
+        .map(String::from)
+        .ok_or_else(|| VeloError::Network("response is not text".to_string()))
+}
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Reflect, Default, Debug)]
 pub enum NodeType {
     #[default]
     Rect,
     Paper,
     Circle,
+    /// Titled container, nodes lying within it and above it move and resize with it.
+    Frame,
 }
 
-#[derive(Serialize, Deserialize, Clone, Default)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
 pub enum TextPos {
     #[default]
     Center,
     TopLeft,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct JsonNodeText {
     pub text: String,
     pub pos: TextPos,
 }
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct JsonNode<T> {
     pub id: Uuid,
     pub node_type: NodeType,
@@ -157,7 +244,7 @@ pub struct JsonNode<T> {
     pub bg_color: T,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DrawingJsonNode<T> {
     pub x: f32,
     pub y: f32,
@@ -168,8 +255,43 @@ pub struct DrawingJsonNode<T> {
     pub width: f32,
 }
 
+impl<T> JsonNode<T> {
+    /// Same node with its color converted, e.g. between theme keys and resolved colors.
+    pub fn map_color<U>(self, f: impl FnOnce(T) -> U) -> JsonNode<U> {
+        JsonNode {
+            id: self.id,
+            node_type: self.node_type,
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            width: self.width,
+            height: self.height,
+            text: self.text,
+            bg_color: f(self.bg_color),
+        }
+    }
+}
+
+impl<T> DrawingJsonNode<T> {
+    /// Same drawing with its color converted, e.g. between theme keys and resolved colors.
+    pub fn map_color<U>(self, f: impl FnOnce(T) -> U) -> DrawingJsonNode<U> {
+        DrawingJsonNode {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+            id: self.id,
+            points: self.points,
+            drawing_color: f(self.drawing_color),
+            width: self.width,
+        }
+    }
+}
+
 pub const MAX_CHECKPOINTS: i32 = 7;
-pub const MAX_SAVED_DOCS_IN_MEMORY: i32 = 7;
+/// Documents kept in memory, older ones are loaded from the store again when opened.
+pub const MAX_SAVED_DOCS_IN_MEMORY: usize = 7;
+/// Estimated size of the documents kept in memory, see `estimated_doc_size`.
+pub const MAX_DOCS_MEMORY_BYTES: usize = 32 * 1024 * 1024;
 
 #[derive(Resource, Default)]
 pub struct UiState {
@@ -185,18 +307,37 @@ pub struct UiState {
     pub draw_color_pair: Option<(String, Color)>,
     pub arrow_to_draw_start: Option<ArrowConnect>,
     pub drawing_mode: bool,
+    pub selected: HashSet<ReflectableUuid>,
+    pub marquee_start: Option<Vec2>,
+}
+
+impl UiState {
+    /// Nodes affected by node actions: the selection and the node in editing mode.
+    pub fn selected_nodes(&self) -> HashSet<ReflectableUuid> {
+        let mut nodes = self.selected.clone();
+        nodes.extend(self.entity_to_edit);
+        nodes
+    }
 }
 
 impl Plugin for UiPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<UiState>();
         app.init_resource::<AppState>();
+        app.init_resource::<History>();
+        app.init_resource::<Autosave>();
+        app.init_resource::<Toasts>();
+        app.init_resource::<Trash>();
+        app.init_resource::<Sharing>();
+        app.init_resource::<HistoryBrowser>();
+        app.init_resource::<ImageHashes>();
 
         app.add_event::<AddRect<(String, Color)>>();
         app.add_event::<CreateArrow>();
         app.add_event::<RedrawArrow>();
         app.add_event::<SaveStore>();
         app.add_event::<UpdateDeleteDocBtn>();
+        app.add_event::<RefreshDocList>();
         app.add_event::<NodeInteraction>();
 
         #[cfg(not(target_arch = "wasm32"))]
@@ -206,7 +347,13 @@ impl Plugin for UiPlugin {
         );
         #[cfg(target_arch = "wasm32")]
         app.add_systems(Startup, load_from_url.before(init_layout));
-        app.add_systems(Startup, init_layout);
+        app.add_systems(Startup, (init_layout, init_toasts));
+        app.add_systems(
+            Startup,
+            (purge_expired_trash, collect_image_garbage)
+                .chain()
+                .after(init_layout),
+        );
 
         app.add_systems(
             Update,
@@ -245,11 +392,18 @@ impl Plugin for UiPlugin {
 
         app.add_systems(
             Update,
-            (load_tab, remove_load_tab_request)
+            (load_tab, clear_history, remove_load_tab_request)
                 .chain()
                 .distributive_run_if(should_load_tab),
         );
 
+        app.add_systems(
+            Update,
+            (prompt_passphrase, remove_unlock_doc_request)
+                .chain()
+                .distributive_run_if(should_unlock_doc),
+        );
+
         app.add_systems(
             Update,
             (
@@ -261,7 +415,6 @@ impl Plugin for UiPlugin {
                 rename_tab_handler,
                 mouse_scroll_list,
                 list_item_click,
-                new_doc_handler,
                 rename_doc_handler,
                 delete_doc_handler,
                 save_doc_handler,
@@ -303,6 +456,105 @@ impl Plugin for UiPlugin {
         );
         app.add_systems(Update, (set_focused_entity, clickable_links).chain());
 
+        app.add_systems(
+            Update,
+            (
+                marquee_selection.after(canvas_click),
+                highlight_selection.after(entity_to_edit_changed),
+            ),
+        );
+
+        app.add_systems(
+            Update,
+            (apply_history, remove_history_request)
+                .chain()
+                .distributive_run_if(should_apply_history),
+        );
+        app.add_systems(
+            Update,
+            (copy_selection, remove_clipboard_request)
+                .chain()
+                .distributive_run_if(should_handle_clipboard),
+        );
+        app.add_systems(
+            Update,
+            (paste_checkpoint, remove_paste_request)
+                .chain()
+                .distributive_run_if(should_paste),
+        );
+        app.add_systems(Update, (track_unsaved_changes, autosave).chain());
+        app.add_systems(Update, (show_toasts, dismiss_toasts));
+        app.add_systems(Update, (open_trash, fill_trash_list, trash_item_handler));
+        app.add_systems(Update, (record_shares, copy_share_link_handler));
+        app.add_systems(
+            Update,
+            (copy_share_link, remove_share_link_request)
+                .chain()
+                .after(save_tab)
+                .distributive_run_if(should_copy_share_link),
+        );
+        app.add_systems(
+            Update,
+            (
+                open_new_doc_modal,
+                fill_template_list,
+                template_button_handler,
+                open_template_modal,
+            ),
+        );
+        app.add_systems(
+            Update,
+            (save_template, remove_template_request)
+                .chain()
+                .after(save_tab)
+                .distributive_run_if(should_save_template),
+        );
+        app.add_systems(Update, open_lock_modal);
+        app.add_systems(Update, (open_tags_modal, tag_chip_click));
+        app.add_systems(
+            Update,
+            (
+                pin_doc_handler,
+                folder_click,
+                sort_docs_handler,
+                doc_list_drag,
+                open_folder_modal,
+            ),
+        );
+        app.add_systems(
+            Update,
+            (
+                open_snapshot_modal,
+                open_tab_history,
+                fill_history_list.after(save_tab),
+                snapshot_button_handler,
+                draw_diff_overlay,
+            ),
+        );
+        app.add_systems(
+            Update,
+            (save_snapshot, remove_snapshot_request)
+                .chain()
+                .after(save_tab)
+                .distributive_run_if(should_save_snapshot),
+        );
+        app.add_systems(
+            Update,
+            (write_journal, remove_journal_request)
+                .chain()
+                .after(save_tab)
+                .distributive_run_if(should_write_journal),
+        );
+        app.add_systems(
+            Update,
+            track_node_changes
+                .after(set_focused_entity)
+                .after(resize_entity_start)
+                .after(resize_entity_end)
+                .before(update_rectangle_position)
+                .before(resize_entity_run),
+        );
+
         app.add_systems(
             Update,
             entity_to_edit_changed
@@ -319,10 +571,16 @@ impl Plugin for UiPlugin {
 }
 
 #[cfg(target_arch = "wasm32")]
-fn load_from_url(mut commands: Commands) {
-    let (tx, rx) = async_channel::bounded(1);
-    commands.insert_resource(CommChannels { tx: tx.clone(), rx });
+fn load_from_url(mut commands: Commands, toasts: Res<Toasts>) {
+    let comm_channels = CommChannels::default();
+    commands.insert_resource(comm_channels.clone());
     let href = web_sys::window().unwrap().location().href().unwrap();
+    if let Some(data) = crate::share::board_data(&href) {
+        if let Err(e) = open_board(&comm_channels, data) {
+            toasts.error("Could not open shared board", e);
+        }
+        return;
+    }
     let url = url::Url::parse(href.as_str()).unwrap();
     let query_pairs: std::collections::HashMap<_, _> = url.query_pairs().into_owned().collect();
     if let Some(url) = query_pairs.get("document") {
@@ -332,12 +590,14 @@ fn load_from_url(mut commands: Commands) {
         let links: Vec<_> = finder.links(url).collect();
         if links.len() == 1 {
             let url = links.first().unwrap().as_str().to_owned();
-            let cc = tx.clone();
+            let cc = comm_channels.clone();
+            let toasts = toasts.clone();
             let task = pool.spawn(async move {
                 let request = ehttp::Request::get(url);
                 ehttp::fetch(request, move |result| {
-                    let json_string = result.unwrap().text().unwrap();
-                    cc.try_send(json_string).unwrap();
+                    if let Err(e) = response_text(result).and_then(|json| cc.send(json)) {
+                        toasts.error("Could not open shared document", e);
+                    }
                 });
             });
             task.detach();
@@ -346,11 +606,20 @@ fn load_from_url(mut commands: Commands) {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn read_native_config(mut app_state: ResMut<AppState>) {
+fn read_native_config(
+    mut autosave: ResMut<Autosave>,
+    mut trash: ResMut<Trash>,
+    mut sharing: ResMut<Sharing>,
+) {
+    use crate::share::share_backend;
     use crate::utils::read_config_file;
 
     let config = read_config_file().unwrap_or_default();
-    if let Some(github_token) = &config.github_access_token {
-        app_state.github_token = Some(github_token.clone());
+    sharing.backend = share_backend(&config);
+    if let Some(secs) = config.autosave_idle_secs {
+        autosave.idle = std::time::Duration::from_secs_f64(secs.max(0.));
+    }
+    if let Some(days) = config.trash_retention_days {
+        trash.retention_days = days;
     }
 }
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use super::{ui_helpers::RawText, History};
+use crate::components::Doc;
+use crate::crypto::doc_at_rest;
+use crate::error::VeloError;
+use crate::resources::{AppState, JournalRequest, SaveTabRequest};
+use crate::store::Storage;
+use crate::utils::ReflectableUuid;
+
+/// Writes the recovery journal once the canvas has been idle for `idle`,
+/// `autosave_idle_secs` in `~/.velo.toml`.
+#[derive(Resource)]
+pub struct Autosave {
+    pub idle: Duration,
+    changed_at: Option<Duration>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            idle: Duration::from_secs(5),
+            changed_at: None,
+        }
+    }
+}
+
+/// Every canvas change goes through the history, text is taken when editing ends.
+pub fn track_unsaved_changes(
+    time: Res<Time>,
+    history: Res<History>,
+    changed_text: Query<(), Changed<RawText>>,
+    mut autosave: ResMut<Autosave>,
+) {
+    if history.is_changed() || !changed_text.is_empty() {
+        autosave.changed_at = Some(time.elapsed());
+    }
+}
+
+pub fn autosave(
+    mut commands: Commands,
+    time: Res<Time>,
+    app_state: Res<AppState>,
+    mut autosave: ResMut<Autosave>,
+) {
+    let Some(changed_at) = autosave.changed_at else {
+        return;
+    };
+    if time.elapsed() - changed_at < autosave.idle {
+        return;
+    }
+    autosave.changed_at = None;
+    let Some(doc_id) = app_state.current_document else {
+        return;
+    };
+    let Some(tab) = app_state
+        .docs
+        .get(&doc_id)
+        .and_then(|doc| doc.tabs.iter().find(|tab| tab.is_active))
+    else {
+        return;
+    };
+    commands.insert_resource(SaveTabRequest {
+        doc_id,
+        tab_id: tab.id,
+    });
+    commands.insert_resource(JournalRequest { doc_id });
+}
+
+pub fn should_write_journal(request: Option<Res<JournalRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_journal_request(world: &mut World) {
+    world.remove_resource::<JournalRequest>().unwrap();
+}
+
+pub fn write_journal(
+    request: Res<JournalRequest>,
+    app_state: Res<AppState>,
+    mut storage: ResMut<Storage>,
+) {
+    let Some(doc) = app_state.docs.get(&request.doc_id) else {
+        return;
+    };
+    let result = doc_at_rest(doc, &app_state.passphrases, |hash| {
+        app_state.image(storage.0.as_ref(), hash)
+    })
+    .and_then(|doc| storage.set_journal(&doc).map_err(VeloError::from));
+    if let Err(e) = result {
+        error!("Error writing recovery journal: {}", e);
+    }
+}
+
+/// Whether the journal holds work the store lacks: changes to `doc_id`, the document
+/// opened on startup, or a document that was never saved.
+pub fn has_unsaved_journal(storage: &Storage, doc_id: Option<ReflectableUuid>) -> bool {
+    let Ok(journal) = storage.journal() else {
+        return false;
+    };
+    match storage.doc(&journal.id) {
+        Ok(saved) => Some(journal.id) == doc_id && !same_latest_state(&saved, &journal),
+        Err(_) => true,
+    }
+}
+
+/// Compares documents as they are shown, ignoring the older checkpoints.
+fn same_latest_state(doc: &Doc, other: &Doc) -> bool {
+    doc.name == other.name
+        && doc.tags == other.tags
+        && doc.sealed == other.sealed
+        && doc.tabs.len() == other.tabs.len()
+        && doc
+            .tabs
+            .iter()
+            .zip(other.tabs.iter())
+            .all(|(tab, other_tab)| {
+                tab.id == other_tab.id
+                    && tab.name == other_tab.name
+                    && tab.checkpoints.back() == other_tab.checkpoints.back()
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn doc(checkpoints: &[&str]) -> Doc {
+        Doc::test("doc", checkpoints).with_id(ReflectableUuid(uuid::Uuid::from_u128(1)))
+    }
+
+    #[test]
+    fn test_unsaved_journal() {
+        let mut storage = Storage(Box::<MemoryStore>::default());
+        let doc_id = Some(doc(&[]).id);
+        assert!(!has_unsaved_journal(&storage, doc_id));
+
+        storage.set_journal(&doc(&["a", "b"])).unwrap();
+        assert!(has_unsaved_journal(&storage, None));
+
+        storage.set_doc(&doc(&["b"])).unwrap();
+        assert!(!has_unsaved_journal(&storage, doc_id));
+
+        storage.set_journal(&doc(&["b", "c"])).unwrap();
+        assert!(has_unsaved_journal(&storage, doc_id));
+        assert!(!has_unsaved_journal(
+            &storage,
+            Some(ReflectableUuid::generate())
+        ));
+    }
+}
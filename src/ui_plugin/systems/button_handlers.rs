@@ -1,16 +1,12 @@
 #![allow(clippy::duplicate_mod)]
-use std::collections::HashMap;
-use std::{collections::VecDeque, time::Duration};
+use std::time::Duration;
 
 use bevy::sprite::collide_aabb::collide;
 use bevy::{prelude::*, window::PrimaryWindow};
 
 use bevy_cosmic_edit::{CosmicEdit, CosmicEditHistory, CosmicFont};
-use bevy_pkv::PkvStore;
-use bevy_prototype_lyon::prelude::Fill;
+use bevy_prototype_lyon::prelude::{Fill, Stroke};
 use cosmic_text::{Cursor, Edit};
-use serde::Serialize;
-use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::themes::Theme;
@@ -18,15 +14,19 @@ use crate::{AddRect, JsonNode, JsonNodeText, NodeType, UiState};
 
 use super::ui_helpers::{
     spawn_modal, ButtonAction, ChangeColor, ChangeTheme, DeleteDoc, DocListItemButton, DrawPencil,
-    Drawing, GenericButton, NewDoc, RawText, SaveDoc, TextPosMode, Tooltip, VeloNode, VeloShape,
+    Drawing, GenericButton, RawText, SaveDoc, TextPosMode, Tooltip, VeloNode, VeloShape,
+};
+use super::{
+    drawing_snapshot, sent_back_z, CanvasCommand, ExportToFile, History, ImportFromFile,
+    ImportFromUrl, MainPanel, NodeSnapshot,
 };
-use super::{ExportToFile, ImportFromFile, ImportFromUrl, MainPanel, ShareDoc};
 use crate::canvas::arrow::components::{ArrowMeta, ArrowMode};
-use crate::components::{Doc, MainCamera, Tab};
-use crate::resources::{AppState, FontSystemState, LoadDocRequest, SaveDocRequest};
+use crate::components::MainCamera;
+use crate::resources::{AppState, FontSystemState, SaveDocRequest};
+use crate::store::Storage;
 use crate::utils::{
-    bevy_color_to_cosmic, get_timestamp, load_doc_to_memory, ReflectableUuid, UserPreferences,
-    DARK_THEME_ICON_CODE, LIGHT_THEME_ICON_CODE,
+    bevy_color_to_cosmic, get_timestamp, ReflectableUuid, UserPreferences, DARK_THEME_ICON_CODE,
+    LIGHT_THEME_ICON_CODE,
 };
 
 #[path = "../../macros.rs"]
@@ -41,10 +41,13 @@ pub fn rec_button_handlers(
         (Changed<Interaction>, With<ButtonAction>),
     >,
     mut raw_text_query: Query<(&mut CosmicEdit, &RawText, &Parent), With<RawText>>,
-    border_query: Query<&Parent, With<VeloShape>>,
+    border_query: Query<(&Parent, &VeloShape), With<VeloShape>>,
     mut velo_node_query: Query<(Entity, &VeloNode, &mut Transform), With<VeloNode>>,
     mut arrows: Query<(Entity, &ArrowMeta), (With<ArrowMeta>, Without<Tooltip>)>,
-    mut drawings: Query<Entity, With<Drawing<(String, Color)>>>,
+    mut drawings: Query<
+        (Entity, &Drawing<(String, Color)>, &Transform, &Stroke),
+        (With<Drawing<(String, Color)>>, Without<VeloNode>),
+    >,
     mut ui_state: ResMut<UiState>,
     mut app_state: ResMut<AppState>,
     mut camera_proj_query: Query<
@@ -56,6 +59,7 @@ pub fn rec_button_handlers(
         ),
     >,
     theme: Res<Theme>,
+    mut history: ResMut<History>,
 ) {
     let camera_transform = camera_proj_query.single_mut();
     let x = camera_transform.translation.x;
@@ -120,43 +124,83 @@ pub fn rec_button_handlers(
                         image: None,
                     });
                 }
+                super::ui_helpers::ButtonTypes::AddFrame => {
+                    events.send(AddRect {
+                        node: JsonNode {
+                            id: Uuid::new_v4(),
+                            node_type: NodeType::Frame,
+                            x,
+                            y,
+                            width: 3. * theme.node_width,
+                            height: 3. * theme.node_height,
+                            text: JsonNodeText {
+                                text: "Frame".to_string(),
+                                pos: crate::TextPos::TopLeft,
+                            },
+                            bg_color: pair_struct!(theme.frame_bg),
+                            ..default()
+                        },
+                        image: None,
+                    });
+                }
                 super::ui_helpers::ButtonTypes::Del => {
-                    if ui_state.drawing_mode {
-                        for entity in &mut drawings.iter_mut() {
+                    let mut removed = vec![];
+                    let selected_nodes = ui_state.selected_nodes();
+                    for (entity, drawing, transform, stroke) in &mut drawings.iter_mut() {
+                        if ui_state.drawing_mode || selected_nodes.contains(&drawing.id) {
+                            removed.push(CanvasCommand::RemoveDrawing(drawing_snapshot(
+                                transform, drawing, stroke,
+                            )));
                             commands.entity(entity).despawn_recursive();
                         }
                     }
-                    if let Some(id) = ui_state.entity_to_edit {
+                    if !selected_nodes.is_empty() {
                         commands.insert_resource(bevy_cosmic_edit::ActiveEditor { entity: None });
                         *ui_state = UiState::default();
-                        for (entity, node, _) in velo_node_query.iter() {
-                            if node.id == id {
+                        for (entity, arrow) in &mut arrows.iter_mut() {
+                            if selected_nodes.contains(&arrow.start.id)
+                                || selected_nodes.contains(&arrow.end.id)
+                            {
+                                removed.push(CanvasCommand::RemoveArrow(*arrow));
                                 commands.entity(entity).despawn_recursive();
                             }
                         }
-                        for (entity, arrow) in &mut arrows.iter_mut() {
-                            if arrow.start.id == id || arrow.end.id == id {
+                        for (cosmic_edit, raw_text, parent) in raw_text_query.iter() {
+                            if selected_nodes.contains(&raw_text.id) {
+                                let (border, velo_shape) = border_query.get(parent.get()).unwrap();
+                                let (_, _, transform) = velo_node_query.get(border.get()).unwrap();
+                                removed.push(CanvasCommand::RemoveNode(NodeSnapshot::new(
+                                    raw_text,
+                                    cosmic_edit,
+                                    velo_shape,
+                                    transform,
+                                )));
+                            }
+                        }
+                        for (entity, node, _) in velo_node_query.iter() {
+                            if selected_nodes.contains(&node.id) {
                                 commands.entity(entity).despawn_recursive();
                             }
                         }
                     }
+                    history.push(CanvasCommand::Batch(removed));
                 }
                 super::ui_helpers::ButtonTypes::Front => {
                     let current_document = app_state.current_document.unwrap();
                     let tab = app_state
-                        .docs
-                        .get_mut(&current_document)
+                        .doc_mut(&current_document)
                         .unwrap()
                         .tabs
                         .iter_mut()
                         .find(|x| x.is_active)
                         .unwrap();
-                    if let Some(id) = ui_state.entity_to_edit {
+                    let mut reordered = vec![];
+                    for id in ui_state.selected_nodes() {
                         let mut data = None;
                         // fint current z_index
                         for (cosmic_edit, raw_text, parent) in &mut raw_text_query.iter_mut() {
                             if raw_text.id == id {
-                                let border = border_query.get(parent.get()).unwrap();
+                                let (border, _) = border_query.get(parent.get()).unwrap();
                                 let top = velo_node_query.get_mut(border.get()).unwrap();
                                 let size = Vec2::new(cosmic_edit.width, cosmic_edit.height);
                                 let translation = top.2.translation;
@@ -167,7 +211,7 @@ pub fn rec_button_handlers(
                         // find higher z_index if collide
                         for (cosmic_edit, raw_text, parent) in &mut raw_text_query.iter_mut() {
                             if raw_text.id != id {
-                                let border = border_query.get(parent.get()).unwrap();
+                                let (border, _) = border_query.get(parent.get()).unwrap();
                                 let top = velo_node_query.get_mut(border.get()).unwrap();
                                 let size = Vec2::new(cosmic_edit.width, cosmic_edit.height);
                                 let translation = top.2.translation;
@@ -185,6 +229,7 @@ pub fn rec_button_handlers(
                         // update z_index
                         for (_, node, mut transform) in velo_node_query.iter_mut() {
                             if node.id == id {
+                                let from = transform.translation.z;
                                 if let Some((_, translation)) = data {
                                     transform.translation.z = (translation.z + 0.03) % f32::MAX;
                                 } else {
@@ -194,18 +239,25 @@ pub fn rec_button_handlers(
                                 if tab.z_index < transform.translation.z {
                                     tab.z_index = transform.translation.z;
                                 }
+                                reordered.push(CanvasCommand::ReorderNode {
+                                    id,
+                                    from,
+                                    to: transform.translation.z,
+                                });
                                 break;
                             }
                         }
                     }
+                    history.push(CanvasCommand::Batch(reordered));
                 }
                 super::ui_helpers::ButtonTypes::Back => {
-                    if let Some(id) = ui_state.entity_to_edit {
+                    let mut reordered = vec![];
+                    for id in ui_state.selected_nodes() {
                         let mut data = None;
                         // fint current z_index
                         for (cosmic_edit, raw_text, parent) in &mut raw_text_query.iter_mut() {
                             if raw_text.id == id {
-                                let border = border_query.get(parent.get()).unwrap();
+                                let (border, _) = border_query.get(parent.get()).unwrap();
                                 let top = velo_node_query.get_mut(border.get()).unwrap();
                                 let size = Vec2::new(cosmic_edit.width, cosmic_edit.height);
                                 let translation = top.2.translation;
@@ -216,7 +268,7 @@ pub fn rec_button_handlers(
                         // find lower z_index if collide
                         for (cosmic_edit, raw_text, parent) in &mut raw_text_query.iter_mut() {
                             if raw_text.id != id {
-                                let border = border_query.get(parent.get()).unwrap();
+                                let (border, _) = border_query.get(parent.get()).unwrap();
                                 let top = velo_node_query.get_mut(border.get()).unwrap();
                                 let size = Vec2::new(cosmic_edit.width, cosmic_edit.height);
                                 let translation = top.2.translation;
@@ -234,16 +286,19 @@ pub fn rec_button_handlers(
                         // update z_index
                         for (_, node, mut transform) in velo_node_query.iter_mut() {
                             if node.id == id {
-                                if let Some((_, translation)) = data {
-                                    transform.translation.z = f32::max(translation.z - 0.03, 1.);
-                                } else {
-                                    transform.translation.z =
-                                        f32::max(transform.translation.z - 0.03, 1.);
-                                }
+                                let from = transform.translation.z;
+                                transform.translation.z =
+                                    sent_back_z(from, data.map(|(_, translation)| translation.z));
+                                reordered.push(CanvasCommand::ReorderNode {
+                                    id,
+                                    from,
+                                    to: transform.translation.z,
+                                });
                                 break;
                             }
                         }
                     }
+                    history.push(CanvasCommand::Batch(reordered));
                 }
             },
             Interaction::Hovered => {}
@@ -259,17 +314,28 @@ pub fn change_color_pallete(
     >,
     mut velo_border: Query<(&mut Fill, &mut VeloShape), With<VeloShape>>,
     mut ui_state: ResMut<UiState>,
+    mut history: ResMut<History>,
 ) {
     for (interaction, change_color) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
                 let pair_color = change_color.pair_color.clone();
-                for (mut fill, mut velo_border) in velo_border.iter_mut() {
-                    if Some(velo_border.id) == ui_state.entity_to_edit {
-                        fill.color = pair_color.1;
-                        velo_border.pair_color = pair_color;
-                        return;
+                let selected_nodes = ui_state.selected_nodes();
+                if !selected_nodes.is_empty() {
+                    let mut recolored = vec![];
+                    for (mut fill, mut velo_border) in velo_border.iter_mut() {
+                        if selected_nodes.contains(&velo_border.id) {
+                            recolored.push(CanvasCommand::RecolorNode {
+                                id: velo_border.id,
+                                from: velo_border.pair_color.clone(),
+                                to: pair_color.clone(),
+                            });
+                            fill.color = pair_color.1;
+                            velo_border.pair_color = pair_color.clone();
+                        }
                     }
+                    history.push(CanvasCommand::Batch(recolored));
+                    return;
                 }
                 ui_state.draw_color_pair = Some(pair_color);
             }
@@ -290,12 +356,11 @@ pub fn change_text_pos(
     for (interaction, text_pos_mode) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
-                if let Some(entity_to_edit) = state.entity_to_edit {
-                    for (raw_text, mut cosmit_edit) in raw_text_node_query.iter_mut() {
-                        if raw_text.id == entity_to_edit {
-                            cosmit_edit.text_pos = text_pos_mode.text_pos.clone().into();
-                            cosmit_edit.editor.buffer_mut().set_redraw(true);
-                        }
+                let selected_nodes = state.selected_nodes();
+                for (raw_text, mut cosmit_edit) in raw_text_node_query.iter_mut() {
+                    if selected_nodes.contains(&raw_text.id) {
+                        cosmit_edit.text_pos = text_pos_mode.text_pos.clone().into();
+                        cosmit_edit.editor.buffer_mut().set_redraw(true);
                     }
                 }
             }
@@ -323,57 +388,6 @@ pub fn change_arrow_type(
     }
 }
 
-pub fn new_doc_handler(
-    mut commands: Commands,
-    mut new_doc_query: Query<&Interaction, (Changed<Interaction>, With<NewDoc>)>,
-    mut app_state: ResMut<AppState>,
-) {
-    for interaction in &mut new_doc_query.iter_mut() {
-        match *interaction {
-            Interaction::Pressed => {
-                let doc_id = ReflectableUuid::generate();
-                let name = "Untitled".to_string();
-                let tab_id = ReflectableUuid::generate();
-                let mut checkpoints = VecDeque::new();
-                checkpoints.push_back(
-                    json!({
-                        "nodes": [],
-                        "arrows": [],
-                        "images": {},
-                        "drawings": []
-                    })
-                    .to_string(),
-                );
-                let tabs = vec![Tab {
-                    id: tab_id,
-                    name: "Tab 1".to_string(),
-                    checkpoints,
-                    is_active: true,
-                    z_index: 1.,
-                }];
-                app_state.docs.insert(
-                    doc_id,
-                    Doc {
-                        id: doc_id,
-                        name: name.clone(),
-                        tabs,
-                        tags: vec![],
-                    },
-                );
-                commands.insert_resource(SaveDocRequest {
-                    doc_id: app_state.current_document.unwrap(),
-                    path: None,
-                });
-                app_state.current_document = Some(doc_id);
-                commands.insert_resource(LoadDocRequest { doc_id });
-                app_state.doc_list_ui.insert(doc_id);
-            }
-            Interaction::Hovered => {}
-            Interaction::None => {}
-        }
-    }
-}
-
 pub fn rename_doc_handler(
     mut commands: Commands,
     mut rename_doc_query: Query<
@@ -431,7 +445,7 @@ pub fn delete_doc_handler(
     mut app_state: ResMut<AppState>,
     main_panel_query: Query<Entity, With<MainPanel>>,
     windows: Query<&Window, With<PrimaryWindow>>,
-    pkv: Res<PkvStore>,
+    storage: Res<Storage>,
     mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
     font_system_state: ResMut<FontSystemState>,
     theme: Res<Theme>,
@@ -441,13 +455,15 @@ pub fn delete_doc_handler(
         match *interaction {
             Interaction::Pressed => {
                 if app_state.docs.len() == 1 {
-                    if let Ok(docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-                        if docs.len() > 1 {
-                            for (id, doc) in docs.iter() {
+                    if let Ok(index) = storage.index() {
+                        if index.len() > 1 {
+                            for id in index.keys() {
                                 if app_state.docs.len() != 1 {
                                     break;
                                 }
-                                app_state.docs.insert(*id, doc.clone());
+                                if let Ok(doc) = storage.doc(id) {
+                                    app_state.docs.insert(*id, doc);
+                                }
                             }
                         } else {
                             // do not allow deletion if there is less than two docs
@@ -534,11 +550,25 @@ pub fn export_to_file(
 }
 
 #[cfg(target_arch = "wasm32")]
-pub fn set_window_property(mut app_state: ResMut<AppState>, mut pkv: ResMut<PkvStore>) {
+pub fn set_window_property(mut app_state: ResMut<AppState>, storage: Res<Storage>) {
+    use crate::format::DocFile;
+    use crate::utils::load_doc_to_memory;
+
     if let Some(doc_id) = app_state.current_document {
-        load_doc_to_memory(doc_id, &mut app_state, &mut pkv);
-        let current_doc = app_state.docs.get(&doc_id).unwrap().clone();
-        let value = serde_json::to_string_pretty(&current_doc).unwrap();
+        // failures are reported when the document is opened
+        if load_doc_to_memory(doc_id, &mut app_state, &storage).is_err() {
+            return;
+        }
+        // sealing takes a while, locked documents are shown as they were last saved
+        let current_doc = if app_state.passphrases.contains_key(&doc_id) {
+            match storage.doc(&doc_id) {
+                Ok(doc) => doc,
+                Err(_) => return,
+            }
+        } else {
+            app_state.docs.get(&doc_id).unwrap().clone()
+        };
+        let value = DocFile::new(current_doc, |hash| storage.image(hash).ok()).to_json_pretty();
         let window = wasm_bindgen::JsValue::from(web_sys::window().unwrap());
         let velo_var = wasm_bindgen::JsValue::from("velo");
         let state = wasm_bindgen::JsValue::from(value);
@@ -546,83 +576,6 @@ pub fn set_window_property(mut app_state: ResMut<AppState>, mut pkv: ResMut<PkvS
     }
 }
 
-#[derive(Serialize)]
-struct GistFile {
-    content: String,
-}
-
-#[derive(Serialize)]
-struct GistCreateRequest {
-    description: String,
-    public: bool,
-    files: std::collections::HashMap<String, GistFile>,
-}
-
-pub fn shared_doc_handler(
-    mut app_state: ResMut<AppState>,
-    mut query: Query<&Interaction, (Changed<Interaction>, With<ShareDoc>)>,
-    mut pkv: ResMut<PkvStore>,
-) {
-    for interaction in &mut query.iter_mut() {
-        match *interaction {
-            Interaction::Pressed => {
-                if let Some(doc_id) = app_state.current_document {
-                    load_doc_to_memory(doc_id, &mut app_state, &mut pkv);
-                    let current_doc = app_state.docs.get(&doc_id).unwrap().clone();
-                    let contents = serde_json::to_string_pretty(&current_doc).unwrap();
-                    let mut files = std::collections::HashMap::new();
-                    let filename = "velo.json";
-                    let file = GistFile {
-                        content: contents.to_string(),
-                    };
-                    files.insert(filename.to_string(), file);
-
-                    let request = GistCreateRequest {
-                        description: "Velo Document".to_string(),
-                        public: true,
-                        files,
-                    };
-
-                    let mut request = ehttp::Request::post(
-                        "https://api.github.com/gists",
-                        serde_json::to_string_pretty(&request).unwrap(),
-                    );
-                    request.headers.insert(
-                        "Accept".to_string(),
-                        "application/vnd.github.v3+json".to_string(),
-                    );
-                    request.headers.insert(
-                        "Authorization".to_string(),
-                        format!("token {}", app_state.github_token.as_ref().unwrap()),
-                    );
-                    #[cfg(not(target_arch = "wasm32"))]
-                    let mut clipboard = arboard::Clipboard::new().unwrap();
-                    ehttp::fetch(request, move |result| {
-                        let response = result.unwrap();
-                        if response.ok {
-                            let res_json: Value =
-                                serde_json::from_str(response.text().unwrap().as_str()).unwrap();
-                            let files: Value = res_json["files"].clone();
-                            let velo = files["velo.json"].clone();
-                            #[cfg(not(target_arch = "wasm32"))]
-                            clipboard
-                                .set_text(format!(
-                                    "https://staffengineer.github.io/velo?document={}",
-                                    velo["raw_url"].to_string().replace('\"', "")
-                                ))
-                                .unwrap();
-                        } else {
-                            error!("Error sharing document: {}", response.status_text);
-                        }
-                    });
-                }
-            }
-            Interaction::Hovered => {}
-            Interaction::None => {}
-        }
-    }
-}
-
 pub fn import_from_file(
     mut commands: Commands,
     mut query: Query<&Interaction, (Changed<Interaction>, With<ImportFromFile>)>,
@@ -751,7 +704,7 @@ pub fn enable_drawing_mode(
 }
 
 pub fn change_theme(
-    mut pkv: ResMut<PkvStore>,
+    mut storage: ResMut<Storage>,
     mut change_theme_button: Query<&Interaction, (Changed<Interaction>, With<ChangeTheme>)>,
     mut change_theme_label: Query<&mut Text, (With<ChangeTheme>, Without<Tooltip>)>,
     mut tooltip_label: Query<&mut Text, (With<Tooltip>, Without<ChangeTheme>)>,
@@ -772,12 +725,9 @@ pub fn change_theme(
                             }
                         }
                         text.sections[0].value = LIGHT_THEME_ICON_CODE.to_string();
-                        let _ = pkv.set(
-                            "user_preferences",
-                            &UserPreferences {
-                                theme_name: Some("dark".to_string()),
-                            },
-                        );
+                        let _ = storage.set_preferences(&UserPreferences {
+                            theme_name: Some("dark".to_string()),
+                        });
                     }
                     if icon_code == LIGHT_THEME_ICON_CODE {
                         for mut tooltip in &mut tooltip_label.iter_mut() {
@@ -790,12 +740,9 @@ pub fn change_theme(
                             }
                         }
                         text.sections[0].value = DARK_THEME_ICON_CODE.to_string();
-                        let _ = pkv.set(
-                            "user_preferences",
-                            &UserPreferences {
-                                theme_name: Some("light".to_string()),
-                            },
-                        );
+                        let _ = storage.set_preferences(&UserPreferences {
+                            theme_name: Some("light".to_string()),
+                        });
                     }
                 }
             }
@@ -2,8 +2,9 @@ use bevy::{prelude::*, window::PrimaryWindow};
 
 use super::{
     ui_helpers::{MainPanel, RawText},
-    NodeInteraction, UiState,
+    NodeInteraction, NodeInteractionType, UiState,
 };
+use crate::components::MainCamera;
 
 pub fn canvas_click(
     interaction_query: Query<&Interaction, (Changed<Interaction>, With<MainPanel>)>,
@@ -11,16 +12,32 @@ pub fn canvas_click(
     mut windows: Query<&mut Window, With<PrimaryWindow>>,
     mut node_interaction_events: EventReader<NodeInteraction>,
     raw_text: Query<With<RawText>>,
+    input: Res<Input<KeyCode>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
 ) {
     let mut primary_window = windows.single_mut();
     for interaction in interaction_query.iter() {
         if *interaction == Interaction::Pressed {
+            let mut on_interactive_node = false;
             for event in node_interaction_events.iter() {
                 if raw_text.get(event.entity).is_ok() {
                     return;
                 }
+                if event.node_interaction_type == NodeInteractionType::LeftClick {
+                    on_interactive_node = true;
+                }
             }
             ui_state.entity_to_edit = None;
+            if !input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                ui_state.selected.clear();
+            }
+            // pressing on the empty canvas starts a marquee selection
+            if !ui_state.drawing_mode && ui_state.modal_id.is_none() && !on_interactive_node {
+                let (camera, camera_transform) = camera_q.single();
+                ui_state.marquee_start = primary_window
+                    .cursor_position()
+                    .and_then(|pos| camera.viewport_to_world_2d(camera_transform, pos));
+            }
         }
         if *interaction == Interaction::Hovered {
             primary_window.cursor.icon = CursorIcon::default();
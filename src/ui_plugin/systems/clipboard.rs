@@ -0,0 +1,397 @@
+#![allow(clippy::duplicate_mod)]
+use std::collections::HashMap;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::{CosmicEdit, CosmicFont};
+use bevy_prototype_lyon::prelude::Stroke;
+use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use super::ui_helpers::{spawn_drawing, spawn_sprite_node, Drawing, RawText, VeloNode, VeloShape};
+use super::{drawing_snapshot, CanvasCommand, History, NodeSnapshot, UiState};
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::canvas::arrow::events::CreateArrow;
+use crate::canvas::shadows::CustomShadowMaterial;
+use crate::format::Checkpoint;
+use crate::resources::{AppState, ClipboardRequest, FontSystemState, ImageHashes, PasteRequest};
+use crate::store::{import_images, Storage};
+use crate::themes::Theme;
+use crate::utils::{base64_to_image, image_to_base64, ReflectableUuid};
+use crate::{DrawingJsonNode, JsonNode};
+
+#[path = "../../macros.rs"]
+#[macro_use]
+mod macros;
+
+/// Distance of duplicated items from the originals.
+const DUPLICATE_OFFSET: Vec2 = Vec2::new(20., -20.);
+
+pub fn should_handle_clipboard(request: Option<Res<ClipboardRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn should_paste(request: Option<Res<PasteRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_clipboard_request(world: &mut World) {
+    world.remove_resource::<ClipboardRequest>().unwrap();
+}
+
+pub fn remove_paste_request(world: &mut World) {
+    world.remove_resource::<PasteRequest>().unwrap();
+}
+
+/// Serializes the selected nodes, the arrows between them and the selected drawings
+/// as a checkpoint fragment, so it can be pasted into any tab of any document.
+pub fn copy_selection(
+    request: Res<ClipboardRequest>,
+    mut commands: Commands,
+    mut ui_state: ResMut<UiState>,
+    mut history: ResMut<History>,
+    images: Res<Assets<Image>>,
+    raw_text_query: Query<(&RawText, &CosmicEdit, &Parent), With<RawText>>,
+    border_query: Query<(&Parent, &VeloShape), With<VeloShape>>,
+    velo_node_query: Query<(Entity, &Transform), With<VeloNode>>,
+    arrows: Query<(Entity, &ArrowMeta), With<ArrowMeta>>,
+    drawings: Query<
+        (Entity, &Drawing<(String, Color)>, &Transform, &Stroke),
+        (With<Drawing<(String, Color)>>, Without<VeloNode>),
+    >,
+) {
+    let selection = ui_state.selected_nodes();
+    if selection.is_empty() {
+        return;
+    }
+    let cut = *request == ClipboardRequest::Cut;
+    let mut fragment = Checkpoint::default();
+    let mut removed_nodes = vec![];
+    let mut removed_arrows = vec![];
+    let mut removed_drawings = vec![];
+
+    for (raw_text, cosmic_edit, parent) in raw_text_query.iter() {
+        if !selection.contains(&raw_text.id) {
+            continue;
+        }
+        let (border_parent, velo_shape) = border_query.get(parent.get()).unwrap();
+        let (entity, transform) = velo_node_query.get(border_parent.get()).unwrap();
+        let snapshot = NodeSnapshot::new(raw_text, cosmic_edit, velo_shape, transform);
+        if let Some(image) = snapshot
+            .image
+            .as_ref()
+            .and_then(|handle| images.get(handle))
+            .and_then(image_to_base64)
+        {
+            fragment.insert_image(raw_text.id.0.to_string(), image);
+        }
+        fragment
+            .nodes
+            .push(snapshot.node.clone().map_color(|(key, _)| key));
+        if cut {
+            commands.entity(entity).despawn_recursive();
+            removed_nodes.push(CanvasCommand::RemoveNode(snapshot));
+        }
+    }
+    for (entity, arrow_meta) in arrows.iter() {
+        let start = selection.contains(&arrow_meta.start.id);
+        let end = selection.contains(&arrow_meta.end.id);
+        if start && end {
+            fragment.arrows.push(*arrow_meta);
+        }
+        if cut && (start || end) {
+            commands.entity(entity).despawn_recursive();
+            removed_arrows.push(CanvasCommand::RemoveArrow(*arrow_meta));
+        }
+    }
+    for (entity, drawing, transform, stroke) in drawings.iter() {
+        if !selection.contains(&drawing.id) {
+            continue;
+        }
+        let snapshot = drawing_snapshot(transform, drawing, stroke);
+        fragment
+            .drawings
+            .push(snapshot.clone().map_color(|(key, _)| key));
+        if cut {
+            commands.entity(entity).despawn_recursive();
+            removed_drawings.push(CanvasCommand::RemoveDrawing(snapshot));
+        }
+    }
+
+    match *request {
+        ClipboardRequest::Copy | ClipboardRequest::Cut => {
+            #[cfg(not(target_arch = "wasm32"))]
+            copy_to_clipboard(&fragment);
+        }
+        ClipboardRequest::Duplicate => {
+            commands.insert_resource(PasteRequest {
+                checkpoint: fragment,
+                offset: DUPLICATE_OFFSET,
+            });
+        }
+    }
+    if cut {
+        // arrows are removed first, so undo restores their nodes before them
+        removed_drawings.extend(removed_arrows);
+        removed_drawings.extend(removed_nodes);
+        history.push(CanvasCommand::Batch(removed_drawings));
+        ui_state.entity_to_edit = None;
+        ui_state.selected.clear();
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn copy_to_clipboard(fragment: &Checkpoint) {
+    if let Ok(mut clipboard) = arboard::Clipboard::new() {
+        if let Err(e) = clipboard.set_text(fragment.to_json()) {
+            error!("Error copying to clipboard: {}", e);
+        }
+    }
+}
+
+/// Spawns a checkpoint fragment on the active tab and selects the pasted items.
+pub fn paste_checkpoint(
+    request: Res<PasteRequest>,
+    mut commands: Commands,
+    mut app_state: ResMut<AppState>,
+    mut ui_state: ResMut<UiState>,
+    mut history: ResMut<History>,
+    mut res_images: ResMut<Assets<Image>>,
+    mut create_arrow: EventWriter<CreateArrow>,
+    mut materials_meshes: (ResMut<Assets<CustomShadowMaterial>>, ResMut<Assets<Mesh>>),
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: Res<FontSystemState>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    theme: Res<Theme>,
+    mut storage: ResMut<Storage>,
+    mut image_hashes: ResMut<ImageHashes>,
+) {
+    let Some(tab) = app_state
+        .current_document
+        .and_then(|doc_id| app_state.doc_mut(&doc_id))
+        .and_then(|doc| doc.tabs.iter_mut().find(|tab| tab.is_active))
+    else {
+        return;
+    };
+    let fragment = with_fresh_ids(&request.checkpoint);
+    if let Err(e) = import_images(storage.0.as_mut(), &fragment.blobs) {
+        error!("Error storing pasted images: {}", e);
+    }
+    let theme_colors = serde_json::to_value(&*theme)
+        .unwrap()
+        .as_object()
+        .unwrap()
+        .clone();
+    let scale_factor = windows.single().scale_factor() as f32;
+
+    // keep the stacking order of the fragment, on top of everything in the tab
+    let min_z = fragment
+        .nodes
+        .iter()
+        .map(|node| node.z)
+        .chain(fragment.drawings.iter().map(|drawing| drawing.z))
+        .fold(f32::MAX, f32::min);
+    let base_z = tab.z_index + 0.01;
+    let mut max_z = tab.z_index;
+    let mut stack = |z: f32| {
+        let z = base_z + z - min_z;
+        max_z = max_z.max(z);
+        z
+    };
+
+    let mut added = vec![];
+    ui_state.entity_to_edit = None;
+    ui_state.selected.clear();
+    for node in fragment.nodes.iter().cloned() {
+        let node_id = node.id.to_string();
+        let image = fragment
+            .image_data(&node_id)
+            .and_then(|image| base64_to_image(image))
+            .map(|image| {
+                let handle = res_images.add(image);
+                image_hashes
+                    .0
+                    .insert(handle.id(), fragment.images[&node_id].clone());
+                handle
+            });
+        let mut node = node.map_color(|key| {
+            theme_color(&theme_colors, key).unwrap_or(pair_struct!(theme.node_bg))
+        });
+        node.x += request.offset.x;
+        node.y += request.offset.y;
+        node.z = stack(node.z);
+        let snapshot = NodeSnapshot { node, image };
+        spawn_sprite_node(
+            &mut commands,
+            &mut materials_meshes.0,
+            &mut materials_meshes.1,
+            &theme,
+            &mut cosmic_fonts,
+            font_system_state.0.clone().unwrap(),
+            scale_factor,
+            snapshot.node_meta(),
+        );
+        ui_state.selected.insert(ReflectableUuid(snapshot.node.id));
+        added.push(CanvasCommand::AddNode(snapshot));
+    }
+    for arrow_meta in fragment.arrows.iter() {
+        create_arrow.send(CreateArrow {
+            start: arrow_meta.start,
+            end: arrow_meta.end,
+            arrow_type: arrow_meta.arrow_type,
+        });
+        added.push(CanvasCommand::AddArrow(*arrow_meta));
+    }
+    for drawing in fragment.drawings.iter().cloned() {
+        let mut drawing = drawing.map_color(|key| {
+            theme_color(&theme_colors, key).unwrap_or(pair_struct!(theme.drawing_pencil_btn))
+        });
+        drawing.x += request.offset.x;
+        drawing.y += request.offset.y;
+        drawing.z = stack(drawing.z);
+        spawn_drawing(&mut commands, drawing.clone());
+        ui_state.selected.insert(drawing.id);
+        added.push(CanvasCommand::AddDrawing(drawing));
+    }
+    tab.z_index = max_z;
+    history.push(CanvasCommand::Batch(added));
+}
+
+/// Copy of a fragment with new ids for every node and drawing. Arrows follow their
+/// nodes, arrows to nodes outside of the fragment are dropped.
+pub fn with_fresh_ids(checkpoint: &Checkpoint) -> Checkpoint {
+    let ids: HashMap<Uuid, Uuid> = checkpoint
+        .nodes
+        .iter()
+        .map(|node| (node.id, Uuid::new_v4()))
+        .collect();
+    let mut fragment = Checkpoint {
+        blobs: checkpoint.blobs.clone(),
+        ..Default::default()
+    };
+    for node in checkpoint.nodes.iter() {
+        let id = ids[&node.id];
+        if let Some(image) = checkpoint.images.get(&node.id.to_string()) {
+            fragment.images.insert(id.to_string(), image.clone());
+        }
+        fragment.nodes.push(JsonNode { id, ..node.clone() });
+    }
+    for arrow_meta in checkpoint.arrows.iter() {
+        if let (Some(start), Some(end)) = (
+            ids.get(&arrow_meta.start.id.0),
+            ids.get(&arrow_meta.end.id.0),
+        ) {
+            let mut arrow_meta = *arrow_meta;
+            arrow_meta.start.id = ReflectableUuid(*start);
+            arrow_meta.end.id = ReflectableUuid(*end);
+            fragment.arrows.push(arrow_meta);
+        }
+    }
+    for drawing in checkpoint.drawings.iter() {
+        fragment.drawings.push(DrawingJsonNode {
+            id: ReflectableUuid::generate(),
+            ..drawing.clone()
+        });
+    }
+    fragment
+}
+
+/// Center of the bounding box of all nodes and drawings of a fragment.
+pub fn fragment_center(checkpoint: &Checkpoint) -> Vec2 {
+    let mut min = Vec2::splat(f32::MAX);
+    let mut max = Vec2::splat(f32::MIN);
+    for node in checkpoint.nodes.iter() {
+        let half = Vec2::new(node.width, node.height) / 2.;
+        min = min.min(Vec2::new(node.x, node.y) - half);
+        max = max.max(Vec2::new(node.x, node.y) + half);
+    }
+    for drawing in checkpoint.drawings.iter() {
+        let offset = Vec2::new(drawing.x, drawing.y);
+        for point in drawing.points.iter() {
+            min = min.min(*point + offset);
+            max = max.max(*point + offset);
+        }
+    }
+    if min.x > max.x {
+        return Vec2::ZERO;
+    }
+    (min + max) / 2.
+}
+
+/// Theme color stored under `key`, none if the theme lacks it.
+pub fn theme_color(theme_colors: &Map<String, Value>, key: String) -> Option<(String, Color)> {
+    let color = serde_json::from_value(theme_colors.get(&key)?.clone()).ok()?;
+    Some((key, color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::arrow::components::ArrowConnect;
+
+    fn node(id: u128, x: f32, y: f32) -> JsonNode<String> {
+        JsonNode {
+            id: Uuid::from_u128(id),
+            x,
+            y,
+            width: 100.,
+            height: 50.,
+            bg_color: "node_bg".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn arrow(start: u128, end: u128) -> ArrowMeta {
+        ArrowMeta {
+            start: ArrowConnect {
+                id: ReflectableUuid(Uuid::from_u128(start)),
+                ..Default::default()
+            },
+            end: ArrowConnect {
+                id: ReflectableUuid(Uuid::from_u128(end)),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fresh_ids_keep_arrows_and_images() {
+        let mut checkpoint = Checkpoint {
+            nodes: vec![node(1, 0., 0.), node(2, 200., 0.)],
+            arrows: vec![arrow(1, 2), arrow(2, 3)],
+            ..Default::default()
+        };
+        checkpoint.insert_image(Uuid::from_u128(2).to_string(), "png".to_string());
+
+        let fragment = with_fresh_ids(&checkpoint);
+
+        let (first, second) = (fragment.nodes[0].id, fragment.nodes[1].id);
+        assert_ne!(first, Uuid::from_u128(1));
+        assert_ne!(second, Uuid::from_u128(2));
+        assert_eq!(fragment.arrows.len(), 1);
+        assert_eq!(fragment.arrows[0].start.id.0, first);
+        assert_eq!(fragment.arrows[0].end.id.0, second);
+        assert_eq!(fragment.image_data(&second.to_string()).unwrap(), "png");
+        assert_eq!(fragment.images.len(), 1);
+    }
+
+    #[test]
+    fn test_fragment_center() {
+        let checkpoint = Checkpoint {
+            nodes: vec![node(1, 0., 0.), node(2, 200., 100.)],
+            drawings: vec![DrawingJsonNode {
+                x: 10.,
+                y: 0.,
+                z: 0.,
+                id: ReflectableUuid::generate(),
+                points: vec![Vec2::new(-70., 0.), Vec2::new(0., 0.)],
+                drawing_color: "font".to_string(),
+                width: 2.,
+            }],
+            ..Default::default()
+        };
+
+        assert_eq!(fragment_center(&checkpoint), Vec2::new(95., 50.));
+        assert_eq!(fragment_center(&Checkpoint::default()), Vec2::ZERO);
+    }
+}
@@ -8,7 +8,10 @@ use crate::{
     utils::ReflectableUuid,
 };
 
-use super::{ui_helpers::spawn_sprite_node, AddRect, NodeMeta, UiState};
+use super::{
+    ui_helpers::spawn_sprite_node, AddRect, CanvasCommand, History, JsonNode, NodeMeta,
+    NodeSnapshot, NodeType, UiState, FRAME_Z,
+};
 
 pub fn create_new_node(
     mut commands: Commands,
@@ -22,6 +25,7 @@ pub fn create_new_node(
     mut z_index_local: Local<f32>,
     mut materials: ResMut<Assets<CustomShadowMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut history: ResMut<History>,
 ) {
     let window = windows.single_mut();
     for event in events.iter() {
@@ -35,6 +39,19 @@ pub fn create_new_node(
             .find(|x| x.is_active)
             .unwrap();
         *z_index_local += 0.01 % f32::MAX;
+        // frames are drawn behind the nodes put into them
+        let z = if event.node.node_type == NodeType::Frame {
+            FRAME_Z
+        } else {
+            tab.z_index + *z_index_local
+        };
+        history.push(CanvasCommand::AddNode(NodeSnapshot {
+            node: JsonNode {
+                z,
+                ..event.node.clone()
+            },
+            image: event.image.clone(),
+        }));
         *ui_state = UiState::default();
         ui_state.entity_to_edit = Some(ReflectableUuid(event.node.id));
         let _ = spawn_sprite_node(
@@ -52,7 +69,7 @@ pub fn create_new_node(
                 image: event.image.clone(),
                 text: event.node.text.text.clone(),
                 pair_bg_color: event.node.bg_color.clone(),
-                position: (event.node.x, event.node.y, tab.z_index + *z_index_local),
+                position: (event.node.x, event.node.y, z),
                 text_pos: event.node.text.pos.clone(),
                 is_active: true,
             },
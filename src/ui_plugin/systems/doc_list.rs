@@ -9,16 +9,21 @@ use super::ui_helpers::ScrollingList;
 use crate::{resources::FontSystemState, themes::Theme, ui_plugin::ui_helpers::DocListItemButton};
 
 use crate::resources::{AppState, LoadDocRequest, SaveDocRequest};
+use crate::store::{
+    or_default, update_index_entry, DocEntry, DocListLayout, DocSort, Storage, StoreError,
+};
 
-use std::collections::{HashMap, HashSet};
-
-use bevy_pkv::PkvStore;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, HashSet};
 
-use crate::{ui_plugin::ui_helpers::add_list_item, utils::ReflectableUuid};
+use crate::ui_plugin::ui_helpers::{add_folder_item, add_list_item, spawn_modal_with_value};
+use crate::utils::{dedup_tags, get_timestamp, matches_tags, ReflectableUuid};
 
 use super::{
-    ui_helpers::{DeleteDoc, DocList, DocListItemContainer},
-    UpdateDeleteDocBtn,
+    ui_helpers::{
+        DeleteDoc, DocList, DocListFolder, DocListItemContainer, MoveToFolder, PinDoc, SortDocs,
+    },
+    MainPanel, ModalAction, RefreshDocList, Toasts, UiState, UpdateDeleteDocBtn,
 };
 
 pub fn list_item_click(
@@ -89,15 +94,26 @@ pub fn doc_list_del_button_update(
     }
 }
 
+/// What the document list shows, it is rebuilt when any of it changes.
+#[derive(Default, PartialEq)]
+pub struct ShownDocList {
+    docs: HashSet<ReflectableUuid>,
+    tag_filter: BTreeSet<String>,
+    layout: DocListLayout,
+    // tags of the documents in memory, the others only change when they are
+    tags: HashMap<ReflectableUuid, Vec<String>>,
+}
+
 pub fn doc_list_ui_changed(
     mut commands: Commands,
     app_state: Res<AppState>,
-    mut last_doc_list: Local<HashSet<ReflectableUuid>>,
+    mut last_doc_list: Local<ShownDocList>,
     mut doc_list_query: Query<Entity, With<DocList>>,
     asset_server: Res<AssetServer>,
-    pkv: Res<PkvStore>,
-    mut query_container: Query<Entity, With<DocListItemContainer>>,
+    storage: Res<Storage>,
+    mut query_container: Query<Entity, Or<(With<DocListItemContainer>, With<DocListFolder>)>>,
     mut event_writer: EventWriter<UpdateDeleteDocBtn>,
+    mut refresh_events: EventReader<RefreshDocList>,
     theme: Res<Theme>,
     mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
     font_system_state: ResMut<FontSystemState>,
@@ -105,53 +121,536 @@ pub fn doc_list_ui_changed(
 ) {
     let primary_window = windows.single();
     let scale_factor = primary_window.scale_factor() as f32;
-    if app_state.is_changed() && app_state.doc_list_ui != *last_doc_list {
-        // Think about re-using UI elements instead of destroying and re-creating them
-        for entity in query_container.iter_mut() {
-            commands.entity(entity).despawn_recursive();
-        }
-        let doc_list = doc_list_query.single_mut();
-        let mut doc_tuples: Vec<(String, ReflectableUuid)> = app_state
-            .doc_list_ui
+    let refresh = refresh_events.iter().count() > 0;
+    if !app_state.is_changed() && !refresh {
+        return;
+    }
+    let shown = ShownDocList {
+        docs: app_state.doc_list_ui.clone(),
+        tag_filter: app_state.tag_filter.clone(),
+        layout: app_state.doc_list_layout.clone(),
+        tags: app_state
+            .docs
             .iter()
-            .map(|doc_id| {
-                let doc_name = get_doc_name(*doc_id, &pkv, &app_state);
-                (doc_name, *doc_id)
-            })
-            .collect();
-        // Sort the tuples alphabetically based on doc_name
-        doc_tuples.sort_by(|(name1, _), (name2, _)| name1.cmp(name2));
-        for (doc_name, doc_id) in doc_tuples {
-            let doc_list_item = add_list_item(
+            .filter(|(id, _)| app_state.doc_list_ui.contains(id))
+            .map(|(id, doc)| (*id, doc.tags.clone()))
+            .collect(),
+    };
+    if shown == *last_doc_list && !refresh {
+        return;
+    }
+    // Think about re-using UI elements instead of destroying and re-creating them
+    for entity in query_container.iter_mut() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let doc_list = doc_list_query.single_mut();
+    let index = storage.index().unwrap_or_default();
+    let entries: HashMap<ReflectableUuid, DocEntry> = app_state
+        .doc_list_ui
+        .iter()
+        .filter_map(|doc_id| {
+            let entry = match (app_state.docs.get(doc_id), index.get(doc_id)) {
+                (Some(doc), Some(entry)) => DocEntry {
+                    name: doc.name.clone(),
+                    tags: doc.tags.clone(),
+                    ..entry.clone()
+                },
+                // not saved yet, it is the newest
+                (Some(doc), None) => DocEntry {
+                    created: get_timestamp(),
+                    modified: get_timestamp(),
+                    position: f64::MAX,
+                    ..DocEntry::new(doc)
+                },
+                (None, Some(entry)) => entry.clone(),
+                (None, None) => DocEntry {
+                    name: "Unknown".to_string(),
+                    ..default()
+                },
+            };
+            let entry = DocEntry {
+                tags: dedup_tags(&entry.tags),
+                ..entry
+            };
+            matches_tags(&entry.tags, &app_state.tag_filter).then_some((*doc_id, entry))
+        })
+        .collect();
+    for row in doc_list_rows(&entries, &app_state.doc_list_layout) {
+        let item = match row {
+            DocListRow::Folder {
+                path,
+                name,
+                depth,
+                collapsed,
+            } => add_folder_item(
+                &mut commands,
+                &theme,
+                &asset_server,
+                path,
+                name,
+                depth,
+                collapsed,
+            ),
+            DocListRow::Doc { id, depth } => add_list_item(
                 &mut commands,
                 &mut cosmic_fonts,
                 font_system_state.0.clone().unwrap(),
                 &theme,
                 &asset_server,
-                doc_id,
-                doc_name,
+                id,
+                &entries[&id],
+                depth,
+                &app_state.tag_filter,
                 scale_factor,
-            );
-            commands.entity(doc_list).add_child(doc_list_item);
+            ),
+        };
+        commands.entity(doc_list).add_child(item);
+    }
+    event_writer.send(UpdateDeleteDocBtn);
+    *last_doc_list = shown;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DocListRow {
+    Folder {
+        path: String,
+        name: String,
+        depth: usize,
+        collapsed: bool,
+    },
+    Doc {
+        id: ReflectableUuid,
+        depth: usize,
+    },
+}
+
+/// Folder path typed by the user, without empty or padded names.
+pub fn folder_path(text: &str) -> String {
+    text.split('/')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn parent_folder(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn compare_entries(sort: DocSort, a: &DocEntry, b: &DocEntry) -> Ordering {
+    let order = match sort {
+        DocSort::Name => Ordering::Equal,
+        DocSort::Created => b.created.total_cmp(&a.created),
+        DocSort::Modified => b.modified.total_cmp(&a.modified),
+        DocSort::Manual => a.position.total_cmp(&b.position),
+    };
+    order.then_with(|| a.name.cmp(&b.name))
+}
+
+fn sorted_ids(entries: &HashMap<ReflectableUuid, DocEntry>, sort: DocSort) -> Vec<ReflectableUuid> {
+    let mut ids: Vec<_> = entries.keys().cloned().collect();
+    ids.sort_by(|a, b| compare_entries(sort, &entries[a], &entries[b]).then_with(|| a.0.cmp(&b.0)));
+    ids
+}
+
+/// Rows of the document list: pinned documents first, then the top level folders, each
+/// followed by its subfolders and documents unless it is collapsed, then the top level
+/// documents. Folders go by name, documents as `layout` sorts them.
+pub fn doc_list_rows(
+    entries: &HashMap<ReflectableUuid, DocEntry>,
+    layout: &DocListLayout,
+) -> Vec<DocListRow> {
+    let ids = sorted_ids(entries, layout.sort);
+    let mut rows: Vec<DocListRow> = ids
+        .iter()
+        .filter(|id| entries[id].pinned)
+        .map(|id| DocListRow::Doc { id: *id, depth: 0 })
+        .collect();
+    let mut folders = BTreeSet::new();
+    for entry in entries.values().filter(|entry| !entry.pinned) {
+        let mut path = folder_path(&entry.folder);
+        while !path.is_empty() {
+            let parent = parent_folder(&path).to_string();
+            folders.insert(path);
+            path = parent;
         }
-        event_writer.send(UpdateDeleteDocBtn);
-        *last_doc_list = app_state.doc_list_ui.clone();
+    }
+    fn add_folder(
+        path: &str,
+        depth: usize,
+        ids: &[ReflectableUuid],
+        entries: &HashMap<ReflectableUuid, DocEntry>,
+        folders: &BTreeSet<String>,
+        layout: &DocListLayout,
+        rows: &mut Vec<DocListRow>,
+    ) {
+        for folder in folders.iter().filter(|f| parent_folder(f) == path) {
+            let collapsed = layout.collapsed.contains(folder);
+            rows.push(DocListRow::Folder {
+                path: folder.clone(),
+                name: folder.rsplit('/').next().unwrap_or_default().to_string(),
+                depth,
+                collapsed,
+            });
+            if !collapsed {
+                add_folder(folder, depth + 1, ids, entries, folders, layout, rows);
+            }
+        }
+        for id in ids {
+            let entry = &entries[id];
+            if !entry.pinned && folder_path(&entry.folder) == path {
+                rows.push(DocListRow::Doc { id: *id, depth });
+            }
+        }
+    }
+    add_folder("", 0, &ids, entries, &folders, layout, &mut rows);
+    rows
+}
+
+/// Where a document is dropped in the list.
+#[derive(Debug, PartialEq)]
+pub enum DropTarget {
+    Doc(ReflectableUuid),
+    Folder(String),
+}
+
+/// Puts the document right before the target document, in its folder, or last in the
+/// target folder. The manual order is numbered anew.
+pub fn move_doc(
+    index: &mut HashMap<ReflectableUuid, DocEntry>,
+    id: ReflectableUuid,
+    target: &DropTarget,
+) {
+    if !index.contains_key(&id) || *target == DropTarget::Doc(id) {
+        return;
+    }
+    let mut order: Vec<_> = sorted_ids(index, DocSort::Manual)
+        .into_iter()
+        .filter(|other| *other != id)
+        .collect();
+    let folder = match target {
+        DropTarget::Doc(target_id) => {
+            let Some(target) = index.get(target_id) else {
+                return;
+            };
+            let position = order.iter().position(|other| other == target_id).unwrap();
+            order.insert(position, id);
+            target.folder.clone()
+        }
+        DropTarget::Folder(path) => {
+            order.push(id);
+            path.clone()
+        }
+    };
+    index.get_mut(&id).unwrap().folder = folder;
+    for (position, id) in order.iter().enumerate() {
+        index.get_mut(id).unwrap().position = position as f64 + 1.;
     }
 }
 
-pub fn get_doc_name(
-    doc_id: ReflectableUuid,
-    pkv: &Res<PkvStore>,
-    app_state: &Res<AppState>,
-) -> String {
-    if let Some(doc) = app_state.docs.get(&doc_id) {
-        return doc.name.clone();
+/// Changes the document index. Documents among `ids` that were never saved get an entry
+/// and are saved right away.
+fn change_index(
+    app_state: &AppState,
+    storage: &mut Storage,
+    commands: &mut Commands,
+    ids: &[ReflectableUuid],
+    change: impl FnOnce(&mut HashMap<ReflectableUuid, DocEntry>),
+) -> Result<(), StoreError> {
+    let mut index = or_default(storage.index())?;
+    for id in ids {
+        if index.contains_key(id) {
+            continue;
+        }
+        let Some(doc) = app_state.docs.get(id) else {
+            return Err(StoreError::NotFound(format!("doc-{}", id.0)));
+        };
+        update_index_entry(&mut index, doc, Some(get_timestamp()));
+        commands.insert_resource(SaveDocRequest {
+            doc_id: *id,
+            path: None,
+        });
     }
-    if let Ok(names) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
-        if let Some(name) = names.get(&doc_id) {
-            return name.clone();
+    change(&mut index);
+    storage.set_index(&index)
+}
+
+pub fn pin_doc_handler(
+    mut commands: Commands,
+    query: Query<(&Interaction, &PinDoc), Changed<Interaction>>,
+    app_state: Res<AppState>,
+    mut storage: ResMut<Storage>,
+    mut refresh: EventWriter<RefreshDocList>,
+    toasts: Res<Toasts>,
+) {
+    for (interaction, pin) in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let result = change_index(
+            &app_state,
+            &mut storage,
+            &mut commands,
+            &[pin.id],
+            |index| {
+                let entry = index.get_mut(&pin.id).unwrap();
+                entry.pinned = !entry.pinned;
+            },
+        );
+        match result {
+            Ok(()) => refresh.send(RefreshDocList),
+            Err(e) => toasts.error("Could not pin document", e.into()),
         }
     }
+}
 
-    "Unknown".to_string()
+pub fn folder_click(
+    query: Query<(&Interaction, &DocListFolder), Changed<Interaction>>,
+    mut app_state: ResMut<AppState>,
+    mut storage: ResMut<Storage>,
+    toasts: Res<Toasts>,
+) {
+    for (interaction, folder) in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let collapsed = &mut app_state.doc_list_layout.collapsed;
+        if !collapsed.remove(&folder.path) {
+            collapsed.insert(folder.path.clone());
+        }
+        if let Err(e) = storage.set_doc_list_layout(&app_state.doc_list_layout) {
+            toasts.error("Could not save the document list", e.into());
+        }
+    }
+}
+
+pub fn sort_docs_handler(
+    query: Query<&Interaction, (Changed<Interaction>, With<SortDocs>)>,
+    mut app_state: ResMut<AppState>,
+    mut storage: ResMut<Storage>,
+    toasts: Res<Toasts>,
+) {
+    for interaction in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        app_state.doc_list_layout.sort = app_state.doc_list_layout.sort.next();
+        match storage.set_doc_list_layout(&app_state.doc_list_layout) {
+            Ok(()) => toasts.info(format!(
+                "Documents sorted by {}",
+                app_state.doc_list_layout.sort
+            )),
+            Err(e) => toasts.error("Could not save the document list", e.into()),
+        }
+    }
+}
+
+/// A document dropped on another one goes right before it and the list is sorted by
+/// hand from then on, one dropped on a folder header goes into the folder.
+pub fn doc_list_drag(
+    mut commands: Commands,
+    buttons: Res<Input<MouseButton>>,
+    items: Query<(&Interaction, &DocListItemButton)>,
+    folders: Query<(&Interaction, &DocListFolder)>,
+    mut dragged: Local<Option<ReflectableUuid>>,
+    mut app_state: ResMut<AppState>,
+    mut storage: ResMut<Storage>,
+    mut refresh: EventWriter<RefreshDocList>,
+    toasts: Res<Toasts>,
+) {
+    if buttons.just_pressed(MouseButton::Left) {
+        *dragged = items
+            .iter()
+            .find(|(interaction, _)| **interaction == Interaction::Pressed)
+            .map(|(_, item)| item.id);
+    }
+    if !buttons.just_released(MouseButton::Left) {
+        return;
+    }
+    let Some(id) = dragged.take() else {
+        return;
+    };
+    let target = items
+        .iter()
+        .find(|(interaction, item)| **interaction == Interaction::Hovered && item.id != id)
+        .map(|(_, item)| DropTarget::Doc(item.id))
+        .or_else(|| {
+            folders
+                .iter()
+                .find(|(interaction, _)| **interaction == Interaction::Hovered)
+                .map(|(_, folder)| DropTarget::Folder(folder.path.clone()))
+        });
+    let Some(target) = target else {
+        return;
+    };
+    let mut ids = vec![id];
+    if let DropTarget::Doc(target_id) = &target {
+        ids.push(*target_id);
+    }
+    let result = change_index(&app_state, &mut storage, &mut commands, &ids, |index| {
+        move_doc(index, id, &target)
+    });
+    if let Err(e) = result {
+        toasts.error("Could not move document", e.into());
+        return;
+    }
+    if matches!(target, DropTarget::Doc(_)) && app_state.doc_list_layout.sort != DocSort::Manual {
+        app_state.doc_list_layout.sort = DocSort::Manual;
+        if let Err(e) = storage.set_doc_list_layout(&app_state.doc_list_layout) {
+            toasts.error("Could not save the document list", e.into());
+        }
+    }
+    refresh.send(RefreshDocList);
+}
+
+pub fn open_folder_modal(
+    mut commands: Commands,
+    query: Query<&Interaction, (Changed<Interaction>, With<MoveToFolder>)>,
+    app_state: Res<AppState>,
+    storage: Res<Storage>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    for interaction in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let folder = app_state
+            .current_document
+            .and_then(|id| storage.index().ok()?.remove(&id))
+            .map_or(String::new(), |entry| entry.folder);
+        let id = ReflectableUuid::generate();
+        *ui_state = UiState::default();
+        ui_state.modal_id = Some(id);
+        let entity = spawn_modal_with_value(
+            &mut commands,
+            &theme,
+            &mut cosmic_fonts,
+            font_system_state.0.clone().unwrap(),
+            windows.single(),
+            id,
+            ModalAction::MoveToFolder,
+            folder,
+        );
+        commands.entity(main_panel_query.single()).add_child(entity);
+    }
+}
+
+/// Moves the current document to the folder typed as "/" separated path, an empty one
+/// is the top level.
+pub fn move_to_folder(
+    app_state: &AppState,
+    storage: &mut Storage,
+    commands: &mut Commands,
+    refresh: &mut EventWriter<RefreshDocList>,
+    text: &str,
+    toasts: &Toasts,
+) {
+    let Some(id) = app_state.current_document else {
+        return;
+    };
+    let folder = folder_path(text);
+    let result = change_index(app_state, storage, commands, &[id], |index| {
+        move_doc(index, id, &DropTarget::Folder(folder))
+    });
+    match result {
+        Ok(()) => refresh.send(RefreshDocList),
+        Err(e) => toasts.error("Could not move document", e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, folder: &str, position: f64) -> DocEntry {
+        DocEntry {
+            name: name.to_string(),
+            folder: folder.to_string(),
+            created: position,
+            modified: -position,
+            position,
+            ..default()
+        }
+    }
+
+    fn id(id: u128) -> ReflectableUuid {
+        ReflectableUuid(uuid::Uuid::from_u128(id))
+    }
+
+    #[test]
+    fn test_doc_list_rows() {
+        let mut entries = HashMap::from([
+            (id(1), entry("b", "", 1.)),
+            (id(2), entry("a", "", 2.)),
+            (id(3), entry("c", "work/ clients ", 3.)),
+            (id(4), entry("d", "work", 4.)),
+            (id(5), entry("e", "home", 5.)),
+        ]);
+        entries.get_mut(&id(5)).unwrap().pinned = true;
+        let mut layout = DocListLayout::default();
+        let folder = |path: &str, depth, collapsed| DocListRow::Folder {
+            path: path.to_string(),
+            name: path.rsplit('/').next().unwrap().to_string(),
+            depth,
+            collapsed,
+        };
+        let doc = |n, depth| DocListRow::Doc { id: id(n), depth };
+        assert_eq!(
+            doc_list_rows(&entries, &layout),
+            vec![
+                doc(5, 0),
+                folder("work", 0, false),
+                folder("work/clients", 1, false),
+                doc(3, 2),
+                doc(4, 1),
+                doc(2, 0),
+                doc(1, 0),
+            ]
+        );
+
+        layout.collapsed.insert("work/clients".to_string());
+        layout.sort = DocSort::Created;
+        assert_eq!(
+            doc_list_rows(&entries, &layout)[1..],
+            [
+                folder("work", 0, false),
+                folder("work/clients", 1, true),
+                doc(4, 1),
+                doc(2, 0),
+                doc(1, 0),
+            ]
+        );
+        layout.sort = DocSort::Modified;
+        assert_eq!(
+            doc_list_rows(&entries, &layout)[4..],
+            [doc(1, 0), doc(2, 0)]
+        );
+    }
+
+    #[test]
+    fn test_move_doc() {
+        let mut index = HashMap::from([
+            (id(1), entry("a", "", 1.)),
+            (id(2), entry("b", "", 2.)),
+            (id(3), entry("c", "work", 3.)),
+        ]);
+        move_doc(&mut index, id(1), &DropTarget::Doc(id(3)));
+        assert_eq!(index[&id(1)].folder, "work");
+        let order = sorted_ids(&index, DocSort::Manual);
+        assert_eq!(order, vec![id(2), id(1), id(3)]);
+        assert_eq!(index[&id(2)].position, 1.);
+
+        move_doc(&mut index, id(3), &DropTarget::Folder("".to_string()));
+        assert_eq!(index[&id(3)].folder, "");
+        assert_eq!(
+            sorted_ids(&index, DocSort::Manual),
+            vec![id(2), id(1), id(3)]
+        );
+        assert_eq!(folder_path(" a / /b "), "a/b");
+    }
 }
@@ -11,8 +11,9 @@ use crate::{
 };
 
 use super::{
+    drawing_snapshot,
     ui_helpers::{Drawing, MainPanel},
-    UiState,
+    CanvasCommand, History, UiState,
 };
 
 #[path = "../../macros.rs"]
@@ -29,11 +30,17 @@ pub fn drawing(
     camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     theme: Res<Theme>,
     mut drawing_line_q: Query<
-        (&mut Path, &mut Drawing<(String, Color)>),
+        (
+            &mut Path,
+            &mut Drawing<(String, Color)>,
+            &Transform,
+            &Stroke,
+        ),
         With<Drawing<(String, Color)>>,
     >,
     app_state: Res<AppState>,
     mut z_index_local: Local<f32>,
+    mut history: ResMut<History>,
 ) {
     let (camera, camera_transform) = camera_q.single();
     let mut primary_window = windows.single_mut();
@@ -64,6 +71,17 @@ pub fn drawing(
         if buttons.just_released(MouseButton::Left) {
             *holding_state = None;
             primary_window.cursor.icon = CursorIcon::Default;
+            if let Some(entity_to_draw) = ui_state.entity_to_draw {
+                for (_, drawing_line, transform, stroke) in drawing_line_q.iter() {
+                    if entity_to_draw == drawing_line.id {
+                        history.push(CanvasCommand::AddDrawing(drawing_snapshot(
+                            transform,
+                            drawing_line,
+                            stroke,
+                        )));
+                    }
+                }
+            }
             ui_state.entity_to_draw = None;
         }
 
@@ -73,7 +91,8 @@ pub fn drawing(
                 if let Some(pos) = primary_window.cursor_position() {
                     if let Some(pos) = camera.viewport_to_world_2d(camera_transform, pos) {
                         if let Some(entity_to_draw) = ui_state.entity_to_draw {
-                            for (mut path, mut drawing_line) in &mut drawing_line_q.iter_mut() {
+                            for (mut path, mut drawing_line, _, _) in &mut drawing_line_q.iter_mut()
+                            {
                                 if entity_to_draw == drawing_line.id {
                                     if drawing_line.points.last() == Some(&pos) {
                                         continue;
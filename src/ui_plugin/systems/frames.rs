@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use super::NodeType;
+use crate::utils::ReflectableUuid;
+
+/// Depth of new frames, below the nodes created on top of them.
+pub const FRAME_Z: f32 = 0.5;
+
+/// Footprint of a node on the canvas, `center.z` is the node depth.
+#[derive(Clone, Debug)]
+pub struct NodeBounds {
+    pub id: ReflectableUuid,
+    pub node_type: NodeType,
+    pub center: Vec3,
+    pub size: Vec2,
+}
+
+impl NodeBounds {
+    /// Whether `other` lies entirely within this node and is drawn above it.
+    pub fn contains(&self, other: &NodeBounds) -> bool {
+        let half = self.size / 2.;
+        let other_half = other.size / 2.;
+        let center = self.center.truncate();
+        let other_center = other.center.truncate();
+        other.id != self.id
+            && other.center.z > self.center.z
+            && (other_center - other_half).cmpge(center - half).all()
+            && (other_center + other_half).cmple(center + half).all()
+    }
+}
+
+/// Nodes carried by a frame. Membership follows the geometry stored in the checkpoint,
+/// so dropping a node onto a frame adds it and dragging it out removes it.
+pub fn framed_nodes(frame: &NodeBounds, nodes: &[NodeBounds]) -> Vec<ReflectableUuid> {
+    if frame.node_type != NodeType::Frame {
+        return vec![];
+    }
+    nodes
+        .iter()
+        .filter(|node| frame.contains(node))
+        .map(|node| node.id)
+        .collect()
+}
+
+/// Depth of a node at `z` sent back, under the overlapping node at `below` if there is
+/// one. Nodes stop at depth 1 and never rise, so a frame keeps carrying its nodes.
+pub fn sent_back_z(z: f32, below: Option<f32>) -> f32 {
+    f32::max(below.unwrap_or(z) - 0.03, f32::min(z, 1.))
+}
+
+/// Nodes following a drag of `id`: the rest of the selection if the node is selected,
+/// and everything framed by the moving frames. The dragged node comes first.
+pub fn dragged_nodes(
+    id: ReflectableUuid,
+    selected: &HashSet<ReflectableUuid>,
+    nodes: &[NodeBounds],
+) -> Vec<ReflectableUuid> {
+    let mut ids = vec![id];
+    if selected.contains(&id) {
+        ids.extend(selected.iter().filter(|selected_id| **selected_id != id));
+    }
+    let moving: Vec<&NodeBounds> = nodes.iter().filter(|node| ids.contains(&node.id)).collect();
+    for frame in moving {
+        for framed_id in framed_nodes(frame, nodes) {
+            if !ids.contains(&framed_id) {
+                ids.push(framed_id);
+            }
+        }
+    }
+    ids
+}
+
+/// Nodes scaled along with a frame while it is resized, as offsets from its center.
+#[derive(Default)]
+pub struct FrameResize {
+    pub id: Option<ReflectableUuid>,
+    pub size: Vec2,
+    pub offsets: Vec<(ReflectableUuid, Vec2)>,
+}
+
+impl FrameResize {
+    pub fn new(id: ReflectableUuid, nodes: &[NodeBounds]) -> Self {
+        let Some(frame) = nodes.iter().find(|node| node.id == id) else {
+            return Self {
+                id: Some(id),
+                ..default()
+            };
+        };
+        let offsets = framed_nodes(frame, nodes)
+            .into_iter()
+            .filter_map(|framed_id| nodes.iter().find(|node| node.id == framed_id))
+            .map(|node| (node.id, (node.center - frame.center).truncate()))
+            .collect();
+        Self {
+            id: Some(id),
+            size: frame.size,
+            offsets,
+        }
+    }
+
+    /// Positions of the framed nodes for the new frame size.
+    pub fn positions(&self, center: Vec2, size: Vec2) -> Vec<(ReflectableUuid, Vec2)> {
+        let scale = size / self.size.max(Vec2::ONE);
+        self.offsets
+            .iter()
+            .map(|(id, offset)| (*id, (center + *offset * scale).round()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn bounds(id: u128, node_type: NodeType, center: Vec3, size: f32) -> NodeBounds {
+        NodeBounds {
+            id: ReflectableUuid(Uuid::from_u128(id)),
+            node_type,
+            center,
+            size: Vec2::splat(size),
+        }
+    }
+
+    fn id(id: u128) -> ReflectableUuid {
+        ReflectableUuid(Uuid::from_u128(id))
+    }
+
+    #[test]
+    fn test_framed_nodes_inside_and_above() {
+        let nodes = vec![
+            bounds(1, NodeType::Frame, Vec3::new(0., 0., 0.5), 300.),
+            bounds(2, NodeType::Rect, Vec3::new(50., 50., 1.), 100.),
+            bounds(3, NodeType::Rect, Vec3::new(120., 0., 1.), 100.),
+            bounds(4, NodeType::Rect, Vec3::new(-50., -50., 0.1), 100.),
+            bounds(5, NodeType::Frame, Vec3::new(-80., 0., 0.6), 100.),
+        ];
+
+        assert_eq!(framed_nodes(&nodes[0], &nodes), vec![id(2), id(5)]);
+        assert!(framed_nodes(&nodes[1], &nodes).is_empty());
+    }
+
+    #[test]
+    fn test_dragged_nodes_carry_selection_and_frames() {
+        let nodes = vec![
+            bounds(1, NodeType::Frame, Vec3::new(0., 0., 0.5), 300.),
+            bounds(2, NodeType::Rect, Vec3::new(50., 50., 1.), 100.),
+            bounds(3, NodeType::Rect, Vec3::new(500., 0., 1.), 100.),
+        ];
+        let selected = HashSet::from([id(3), id(1)]);
+
+        assert_eq!(dragged_nodes(id(2), &selected, &nodes), vec![id(2)]);
+        assert_eq!(
+            dragged_nodes(id(1), &HashSet::new(), &nodes),
+            vec![id(1), id(2)]
+        );
+        assert_eq!(
+            dragged_nodes(id(3), &selected, &nodes),
+            vec![id(3), id(1), id(2)]
+        );
+    }
+
+    #[test]
+    fn test_frame_resize_scales_offsets() {
+        let nodes = vec![
+            bounds(1, NodeType::Frame, Vec3::new(100., 0., 0.5), 200.),
+            bounds(2, NodeType::Rect, Vec3::new(150., -50., 1.), 50.),
+        ];
+        let resize = FrameResize::new(id(1), &nodes);
+
+        assert_eq!(
+            resize.positions(Vec2::new(100., 0.), Vec2::new(400., 200.)),
+            vec![(id(2), Vec2::new(200., -50.))]
+        );
+        assert!(FrameResize::new(id(2), &nodes).offsets.is_empty());
+    }
+
+    #[test]
+    fn test_send_back_never_raises() {
+        let node = bounds(2, NodeType::Rect, Vec3::new(50., 50., 1.), 100.);
+        let mut frame = bounds(1, NodeType::Frame, Vec3::new(0., 0., FRAME_Z), 300.);
+        frame.center.z = sent_back_z(frame.center.z, None);
+        assert_eq!(frame.center.z, FRAME_Z);
+        assert_eq!(framed_nodes(&frame, &[node]), vec![id(2)]);
+
+        assert_eq!(sent_back_z(1.5, Some(1.2)), 1.2 - 0.03);
+        assert_eq!(sent_back_z(1.5, None), 1.47);
+        assert_eq!(sent_back_z(1.01, None), 1.);
+    }
+}
@@ -0,0 +1,506 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::{get_cosmic_text, CosmicEdit, CosmicFont};
+use bevy_prototype_lyon::prelude::{Fill, Stroke};
+
+use super::ui_helpers::{
+    spawn_drawing, spawn_sprite_node, Drawing, NodeMeta, RawText, VeloNode, VeloShape,
+};
+use super::{dragged_nodes, DrawingJsonNode, FrameResize, NodeBounds, UiState};
+use crate::canvas::arrow::components::ArrowMeta;
+use crate::canvas::arrow::events::{CreateArrow, RedrawArrow};
+use crate::canvas::shadows::CustomShadowMaterial;
+use crate::resources::{FontSystemState, HistoryRequest};
+use crate::themes::Theme;
+use crate::utils::ReflectableUuid;
+use crate::{JsonNode, JsonNodeText};
+
+/// Number of canvas commands that can be undone.
+pub const MAX_HISTORY: usize = 100;
+
+/// Everything needed to spawn a node again after it was removed.
+#[derive(Clone, Debug)]
+pub struct NodeSnapshot {
+    pub node: JsonNode<(String, Color)>,
+    pub image: Option<Handle<Image>>,
+}
+
+impl NodeSnapshot {
+    pub fn new(
+        raw_text: &RawText,
+        cosmic_edit: &CosmicEdit,
+        velo_shape: &VeloShape,
+        transform: &Transform,
+    ) -> Self {
+        // text of a node in editing mode is only synced to `RawText` when editing ends
+        let text = if cosmic_edit.readonly {
+            raw_text.last_text.clone()
+        } else {
+            get_cosmic_text(cosmic_edit.editor.buffer())
+        };
+        Self {
+            node: JsonNode {
+                id: raw_text.id.0,
+                node_type: velo_shape.node_type.clone(),
+                x: transform.translation.x,
+                y: transform.translation.y,
+                z: transform.translation.z,
+                width: cosmic_edit.width,
+                height: cosmic_edit.height,
+                text: JsonNodeText {
+                    text,
+                    pos: cosmic_edit.text_pos.clone().into(),
+                },
+                bg_color: velo_shape.pair_color.clone(),
+            },
+            image: cosmic_edit.bg_image.clone(),
+        }
+    }
+
+    pub fn node_meta(&self) -> NodeMeta {
+        NodeMeta {
+            id: ReflectableUuid(self.node.id),
+            node_type: self.node.node_type.clone(),
+            size: (self.node.width, self.node.height),
+            position: (self.node.x, self.node.y, self.node.z),
+            text: self.node.text.text.clone(),
+            pair_bg_color: self.node.bg_color.clone(),
+            image: self.image.clone(),
+            text_pos: self.node.text.pos.clone(),
+            is_active: false,
+        }
+    }
+}
+
+pub fn drawing_snapshot(
+    transform: &Transform,
+    drawing: &Drawing<(String, Color)>,
+    stroke: &Stroke,
+) -> DrawingJsonNode<(String, Color)> {
+    DrawingJsonNode {
+        x: transform.translation.x,
+        y: transform.translation.y,
+        z: transform.translation.z,
+        id: drawing.id,
+        points: drawing.points.clone(),
+        drawing_color: drawing.drawing_color.clone(),
+        width: stroke.options.line_width,
+    }
+}
+
+/// A reversible change of the canvas.
+#[derive(Clone, Debug)]
+pub enum CanvasCommand {
+    AddNode(NodeSnapshot),
+    RemoveNode(NodeSnapshot),
+    MoveNode {
+        id: ReflectableUuid,
+        from: Vec2,
+        to: Vec2,
+    },
+    ResizeNode {
+        id: ReflectableUuid,
+        from: Vec2,
+        to: Vec2,
+    },
+    RecolorNode {
+        id: ReflectableUuid,
+        from: (String, Color),
+        to: (String, Color),
+    },
+    ReorderNode {
+        id: ReflectableUuid,
+        from: f32,
+        to: f32,
+    },
+    AddArrow(ArrowMeta),
+    RemoveArrow(ArrowMeta),
+    AddDrawing(DrawingJsonNode<(String, Color)>),
+    RemoveDrawing(DrawingJsonNode<(String, Color)>),
+    /// Commands of a single user action, e.g. a node deleted together with its arrows.
+    Batch(Vec<CanvasCommand>),
+}
+
+impl CanvasCommand {
+    pub fn inverse(&self) -> Self {
+        match self.clone() {
+            CanvasCommand::AddNode(node) => CanvasCommand::RemoveNode(node),
+            CanvasCommand::RemoveNode(node) => CanvasCommand::AddNode(node),
+            CanvasCommand::MoveNode { id, from, to } => CanvasCommand::MoveNode {
+                id,
+                from: to,
+                to: from,
+            },
+            CanvasCommand::ResizeNode { id, from, to } => CanvasCommand::ResizeNode {
+                id,
+                from: to,
+                to: from,
+            },
+            CanvasCommand::RecolorNode { id, from, to } => CanvasCommand::RecolorNode {
+                id,
+                from: to,
+                to: from,
+            },
+            CanvasCommand::ReorderNode { id, from, to } => CanvasCommand::ReorderNode {
+                id,
+                from: to,
+                to: from,
+            },
+            CanvasCommand::AddArrow(arrow) => CanvasCommand::RemoveArrow(arrow),
+            CanvasCommand::RemoveArrow(arrow) => CanvasCommand::AddArrow(arrow),
+            CanvasCommand::AddDrawing(drawing) => CanvasCommand::RemoveDrawing(drawing),
+            CanvasCommand::RemoveDrawing(drawing) => CanvasCommand::AddDrawing(drawing),
+            CanvasCommand::Batch(commands) => {
+                CanvasCommand::Batch(commands.iter().rev().map(|c| c.inverse()).collect())
+            }
+        }
+    }
+
+    /// Single commands in the order they have to be applied.
+    pub fn flatten(self) -> Vec<CanvasCommand> {
+        match self {
+            CanvasCommand::Batch(commands) => commands
+                .into_iter()
+                .flat_map(|command| command.flatten())
+                .collect(),
+            command => vec![command],
+        }
+    }
+}
+
+/// Undo and redo stacks of the active tab.
+#[derive(Resource, Default)]
+pub struct History {
+    undo: Vec<CanvasCommand>,
+    redo: Vec<CanvasCommand>,
+}
+
+impl History {
+    pub fn push(&mut self, command: CanvasCommand) {
+        if matches!(&command, CanvasCommand::Batch(commands) if commands.is_empty()) {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push(command);
+        if self.undo.len() > MAX_HISTORY {
+            self.undo.remove(0);
+        }
+    }
+
+    /// Returns the command reverting the last change.
+    pub fn undo(&mut self) -> Option<CanvasCommand> {
+        let command = self.undo.pop()?;
+        let inverse = command.inverse();
+        self.redo.push(command);
+        Some(inverse)
+    }
+
+    /// Returns the last undone command.
+    pub fn redo(&mut self) -> Option<CanvasCommand> {
+        let command = self.redo.pop()?;
+        self.undo.push(command.clone());
+        Some(command)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+pub fn should_apply_history(request: Option<Res<HistoryRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_history_request(world: &mut World) {
+    world.remove_resource::<HistoryRequest>().unwrap();
+}
+
+pub fn clear_history(mut history: ResMut<History>) {
+    history.clear();
+}
+
+/// Records moves and resizes when the mouse is released, so one drag is one history entry.
+pub fn track_node_changes(
+    ui_state: Res<UiState>,
+    mut history: ResMut<History>,
+    mut moving: Local<(Option<ReflectableUuid>, Vec<(ReflectableUuid, Vec2)>)>,
+    mut resizing: Local<Option<(ReflectableUuid, Vec2, Vec<(ReflectableUuid, Vec2)>)>>,
+    velo_node_query: Query<&Transform, With<VeloNode>>,
+    border_query: Query<(&Parent, &VeloShape), With<VeloShape>>,
+    raw_text_query: Query<(&RawText, &CosmicEdit, &Parent), With<RawText>>,
+) {
+    if moving.0 == ui_state.hold_entity
+        && resizing.as_ref().map(|(id, ..)| *id) == ui_state.entity_to_resize
+    {
+        return;
+    }
+    let nodes: Vec<NodeBounds> = raw_text_query
+        .iter()
+        .filter_map(|(raw_text, cosmic_edit, parent)| {
+            let (border_parent, velo_shape) = border_query.get(parent.get()).ok()?;
+            let transform = velo_node_query.get(border_parent.get()).ok()?;
+            Some(NodeBounds {
+                id: raw_text.id,
+                node_type: velo_shape.node_type.clone(),
+                center: transform.translation,
+                size: Vec2::new(cosmic_edit.width, cosmic_edit.height),
+            })
+        })
+        .collect();
+    let bounds = |id: ReflectableUuid| nodes.iter().find(|node| node.id == id);
+    let position = |id: ReflectableUuid| bounds(id).map(|node| node.center.truncate());
+    let moves = |positions: Vec<(ReflectableUuid, Vec2)>| {
+        positions
+            .into_iter()
+            .filter_map(|(id, from)| {
+                position(id)
+                    .filter(|to| *to != from)
+                    .map(|to| CanvasCommand::MoveNode { id, from, to })
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if moving.0 != ui_state.hold_entity {
+        history.push(CanvasCommand::Batch(moves(std::mem::take(&mut moving.1))));
+        moving.0 = ui_state.hold_entity;
+        if let Some(hold_entity) = ui_state.hold_entity {
+            // the selection and the nodes of dragged frames move with the node
+            moving.1 = dragged_nodes(hold_entity, &ui_state.selected, &nodes)
+                .into_iter()
+                .filter_map(|id| position(id).map(|from| (id, from)))
+                .collect();
+        }
+    }
+
+    if resizing.as_ref().map(|(id, ..)| *id) != ui_state.entity_to_resize {
+        if let Some((id, from, framed)) = resizing.take() {
+            if let Some(to) = bounds(id).map(|node| node.size).filter(|to| *to != from) {
+                let mut commands = vec![CanvasCommand::ResizeNode { id, from, to }];
+                commands.extend(moves(framed));
+                history.push(CanvasCommand::Batch(commands));
+            }
+        }
+        *resizing = ui_state.entity_to_resize.and_then(|id| {
+            let framed = FrameResize::new(id, &nodes)
+                .offsets
+                .into_iter()
+                .filter_map(|(framed_id, _)| position(framed_id).map(|from| (framed_id, from)))
+                .collect();
+            bounds(id).map(|node| (id, node.size, framed))
+        });
+    }
+}
+
+pub fn apply_history(
+    request: Res<HistoryRequest>,
+    mut history: ResMut<History>,
+    mut commands: Commands,
+    mut velo_node_query: Query<(Entity, &VeloNode, &mut Transform), With<VeloNode>>,
+    mut velo_shape_query: Query<(&Parent, &mut Fill, &mut VeloShape), With<VeloShape>>,
+    raw_text_query: Query<(&RawText, &CosmicEdit, &Parent), With<RawText>>,
+    arrows: Query<(Entity, &ArrowMeta), With<ArrowMeta>>,
+    drawings: Query<(Entity, &Drawing<(String, Color)>), With<Drawing<(String, Color)>>>,
+    mut arrow_events: (EventWriter<CreateArrow>, EventWriter<RedrawArrow>),
+    mut materials_meshes: (ResMut<Assets<CustomShadowMaterial>>, ResMut<Assets<Mesh>>),
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: Res<FontSystemState>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    theme: Res<Theme>,
+) {
+    let command = match *request {
+        HistoryRequest::Undo => history.undo(),
+        HistoryRequest::Redo => history.redo(),
+    };
+    let scale_factor = windows.single().scale_factor() as f32;
+
+    for command in command.into_iter().flat_map(|command| command.flatten()) {
+        match command {
+            CanvasCommand::AddNode(snapshot) => {
+                spawn_sprite_node(
+                    &mut commands,
+                    &mut materials_meshes.0,
+                    &mut materials_meshes.1,
+                    &theme,
+                    &mut cosmic_fonts,
+                    font_system_state.0.clone().unwrap(),
+                    scale_factor,
+                    snapshot.node_meta(),
+                );
+            }
+            CanvasCommand::RemoveNode(snapshot) => {
+                for (entity, velo_node, _) in velo_node_query.iter() {
+                    if velo_node.id.0 == snapshot.node.id {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+            CanvasCommand::MoveNode { id, to, .. } => {
+                for (_, velo_node, mut transform) in velo_node_query.iter_mut() {
+                    if velo_node.id == id {
+                        transform.translation.x = to.x;
+                        transform.translation.y = to.y;
+                        arrow_events.1.send(RedrawArrow { id });
+                    }
+                }
+            }
+            CanvasCommand::ResizeNode { id, to, .. } => {
+                // node geometry is spread over many children, spawning it again is simpler
+                for (raw_text, cosmic_edit, parent) in raw_text_query.iter() {
+                    if raw_text.id != id {
+                        continue;
+                    }
+                    let (border_parent, _, velo_shape) =
+                        velo_shape_query.get(parent.get()).unwrap();
+                    let (entity, _, transform) = velo_node_query.get(border_parent.get()).unwrap();
+                    let mut snapshot =
+                        NodeSnapshot::new(raw_text, cosmic_edit, velo_shape, transform);
+                    snapshot.node.width = to.x;
+                    snapshot.node.height = to.y;
+                    commands.entity(entity).despawn_recursive();
+                    spawn_sprite_node(
+                        &mut commands,
+                        &mut materials_meshes.0,
+                        &mut materials_meshes.1,
+                        &theme,
+                        &mut cosmic_fonts,
+                        font_system_state.0.clone().unwrap(),
+                        scale_factor,
+                        snapshot.node_meta(),
+                    );
+                    arrow_events.1.send(RedrawArrow { id });
+                }
+            }
+            CanvasCommand::RecolorNode { id, to, .. } => {
+                for (_, mut fill, mut velo_shape) in velo_shape_query.iter_mut() {
+                    if velo_shape.id == id {
+                        fill.color = to.1;
+                        velo_shape.pair_color = to.clone();
+                    }
+                }
+            }
+            CanvasCommand::ReorderNode { id, to, .. } => {
+                for (_, velo_node, mut transform) in velo_node_query.iter_mut() {
+                    if velo_node.id == id {
+                        transform.translation.z = to;
+                    }
+                }
+            }
+            CanvasCommand::AddArrow(arrow_meta) => {
+                arrow_events.0.send(CreateArrow {
+                    start: arrow_meta.start,
+                    end: arrow_meta.end,
+                    arrow_type: arrow_meta.arrow_type,
+                });
+            }
+            CanvasCommand::RemoveArrow(arrow_meta) => {
+                // connector positions change when nodes move, only the ends identify an arrow
+                if let Some((entity, _)) = arrows.iter().find(|(_, arrow)| {
+                    arrow.start.id == arrow_meta.start.id
+                        && arrow.end.id == arrow_meta.end.id
+                        && arrow.arrow_type == arrow_meta.arrow_type
+                }) {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+            CanvasCommand::AddDrawing(drawing) => {
+                spawn_drawing(&mut commands, drawing);
+            }
+            CanvasCommand::RemoveDrawing(drawing_json_node) => {
+                for (entity, drawing) in drawings.iter() {
+                    if drawing.id == drawing_json_node.id {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                }
+            }
+            CanvasCommand::Batch(_) => unreachable!("batches are flattened"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::arrow::components::ArrowConnect;
+
+    fn move_node(id: ReflectableUuid, from: f32, to: f32) -> CanvasCommand {
+        CanvasCommand::MoveNode {
+            id,
+            from: Vec2::splat(from),
+            to: Vec2::splat(to),
+        }
+    }
+
+    fn moved_to(command: Option<CanvasCommand>) -> Option<f32> {
+        match command {
+            Some(CanvasCommand::MoveNode { to, .. }) => Some(to.x),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_history_undo_redo() {
+        let id = ReflectableUuid::generate();
+        let mut history = History::default();
+        history.push(move_node(id, 0., 1.));
+        history.push(move_node(id, 1., 2.));
+
+        assert_eq!(moved_to(history.undo()), Some(1.));
+        assert_eq!(moved_to(history.undo()), Some(0.));
+        assert_eq!(moved_to(history.undo()), None);
+        assert_eq!(moved_to(history.redo()), Some(1.));
+
+        // a new change drops the undone ones
+        history.push(move_node(id, 1., 3.));
+        assert_eq!(moved_to(history.redo()), None);
+        assert_eq!(moved_to(history.undo()), Some(1.));
+        assert_eq!(moved_to(history.undo()), Some(0.));
+
+        history.clear();
+        assert_eq!(moved_to(history.undo()), None);
+        assert_eq!(moved_to(history.redo()), None);
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let id = ReflectableUuid::generate();
+        let mut history = History::default();
+        for i in 0..MAX_HISTORY + 10 {
+            history.push(move_node(id, i as f32, i as f32 + 1.));
+        }
+        history.push(CanvasCommand::Batch(vec![]));
+
+        let mut undone = 0;
+        while history.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_HISTORY);
+    }
+
+    #[test]
+    fn test_batch_inverse_order() {
+        let node = NodeSnapshot {
+            node: JsonNode {
+                bg_color: ("node_bg".to_string(), Color::WHITE),
+                ..Default::default()
+            },
+            image: None,
+        };
+        let arrow = ArrowMeta {
+            start: ArrowConnect {
+                id: ReflectableUuid(node.node.id),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let delete = CanvasCommand::Batch(vec![
+            CanvasCommand::RemoveArrow(arrow),
+            CanvasCommand::RemoveNode(node),
+        ]);
+
+        let restore = delete.inverse().flatten();
+        assert_eq!(restore.len(), 2);
+        // the node has to exist before its arrows are created
+        assert!(matches!(restore[0], CanvasCommand::AddNode(_)));
+        assert!(matches!(restore[1], CanvasCommand::AddArrow(a) if a == arrow));
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::VecDeque;
 
 use bevy::{
     a11y::{
@@ -7,10 +7,10 @@ use bevy::{
     },
     prelude::*,
 };
-use bevy_pkv::PkvStore;
 
 use super::ui_helpers::ScrollingList;
 use crate::resources::{AppState, LoadDocRequest};
+use crate::store::Storage;
 use crate::ui_plugin::ui_helpers::DocList;
 use crate::utils::ReflectableUuid;
 use crate::{
@@ -22,9 +22,10 @@ pub fn add_list(
     commands: &mut Commands,
     theme: &Res<Theme>,
     app_state: &mut ResMut<AppState>,
-    pkv: &mut ResMut<PkvStore>,
+    storage: &Storage,
 ) -> Entity {
-    if let Ok(last_saved) = pkv.get::<ReflectableUuid>("last_saved") {
+    app_state.doc_list_layout = storage.doc_list_layout().unwrap_or_default();
+    if let Ok(last_saved) = storage.last_saved() {
         app_state.current_document = Some(last_saved);
         commands.insert_resource(LoadDocRequest { doc_id: last_saved });
     }
@@ -58,8 +59,8 @@ pub fn add_list(
         ))
         .id();
 
-    if let Ok(names) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
-        let keys: Vec<_> = names.keys().collect();
+    if let Ok(index) = storage.index() {
+        let keys: Vec<_> = index.keys().collect();
         app_state.doc_list_ui.extend(keys);
     } else {
         let tab_id = ReflectableUuid::generate();
@@ -69,18 +70,17 @@ pub fn add_list(
             name: tab_name,
             checkpoints: VecDeque::new(),
             z_index: 1.,
+            history: vec![],
             is_active: true,
         }];
         let doc_id = ReflectableUuid::generate();
-        app_state.docs.insert(
-            doc_id,
-            Doc {
-                id: doc_id,
-                name: "Untitled".to_string(),
-                tabs,
-                tags: vec![],
-            },
-        );
+        app_state.insert_doc(Doc {
+            id: doc_id,
+            name: "Untitled".to_string(),
+            tabs,
+            tags: vec![],
+            sealed: None,
+        });
         app_state.current_document = Some(doc_id);
         app_state.doc_list_ui.insert(doc_id);
         commands.insert_resource(LoadDocRequest { doc_id });
@@ -22,6 +22,17 @@ pub fn add_menu_button(
         "Import From URL" => "\u{e902}",
         "Save Document to window.velo object" => "\u{e866}",
         "Share Document (copy URL to clipboard)" => "\u{e80d}",
+        "Copy Share Link" => "\u{e157}",
+        "Trash" => "\u{e872}",
+        "Save Snapshot" => "\u{e3af}",
+        "Tab History" => "\u{e889}",
+        "Lock Document" => "\u{e897}",
+        "Edit Tags" => "\u{e54e}",
+        "Filter by Tags" => "\u{e152}",
+        "Sort Documents" => "\u{e164}",
+        "Move to Folder" => "\u{e2c7}",
+        "Save Document as Template" => "\u{e02e}",
+        "Save Tab as Template" => "\u{ea20}",
         "Enable dark theme (restart is required for now)" => DARK_THEME_ICON_CODE,
         "Enable light theme (restart is required for now)" => LIGHT_THEME_ICON_CODE,
         _ => panic!("Unknown menu button tooltip label: {}", label),
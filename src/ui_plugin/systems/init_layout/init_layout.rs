@@ -4,18 +4,22 @@ use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_cosmic_edit::{create_cosmic_font_system, CosmicFont, CosmicFontConfig};
 
-use bevy_pkv::PkvStore;
-
 use super::ui_helpers::{
-    self, AddTab, BottomPanel, ButtonAction, ChangeTheme, DrawPencil, LeftPanel, LeftPanelControls,
-    LeftPanelExplorer, MainPanel, Menu, NewDoc, ParticlesEffect, Root, SaveDoc, TextPosMode,
+    self, AddTab, BottomPanel, ButtonAction, ChangeTheme, CopyShareLink, DrawPencil, EditTags,
+    FilterTags, LeftPanel, LeftPanelControls, LeftPanelExplorer, LockDocument, MainPanel, Menu,
+    ModalAction, MoveToFolder, NewDoc, OpenTabHistory, OpenTrash, ParticlesEffect, Root, SaveDoc,
+    SaveDocTemplate, SaveSnapshot, SaveTabTemplate, SortDocs, TextPosMode,
+};
+use super::{
+    has_unsaved_journal, spawn_modal, CommChannels, ExportToFile, ImportFromFile, ImportFromUrl,
+    ShareDoc, Sharing,
 };
-use super::{CommChannels, ExportToFile, ImportFromFile, ImportFromUrl, ShareDoc};
 use crate::canvas::arrow::components::{ArrowMode, ArrowType};
 use crate::resources::{AppState, FontSystemState};
+use crate::store::Storage;
 use crate::themes::Theme;
-use crate::utils::get_theme_key;
-use crate::TextPos;
+use crate::utils::{get_theme_key, ReflectableUuid};
+use crate::{TextPos, UiState};
 
 #[path = "../../../macros.rs"]
 #[macro_use]
@@ -66,11 +70,13 @@ pub fn init_layout(
     mut commands: Commands,
     mut app_state: ResMut<AppState>,
     asset_server: Res<AssetServer>,
-    mut pkv: ResMut<PkvStore>,
+    storage: Res<Storage>,
     mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
     windows: Query<&Window, With<PrimaryWindow>>,
     mut fonts: ResMut<Assets<Font>>,
     theme: Res<Theme>,
+    mut ui_state: ResMut<UiState>,
+    sharing: Res<Sharing>,
 ) {
     // font setup
     let font_bytes_regular = include_bytes!("../../../../assets/fonts/VictorMono-Regular.ttf");
@@ -105,10 +111,7 @@ pub fn init_layout(
 
     let primary_window: &Window = windows.single();
     #[cfg(not(target_arch = "wasm32"))]
-    {
-        let (tx, rx) = async_channel::bounded(1);
-        commands.insert_resource(CommChannels { tx, rx });
-    }
+    commands.init_resource::<CommChannels>();
     let icon_font = asset_server.load("fonts/MaterialIcons-Regular.ttf");
     let bottom_panel = commands
         .spawn((
@@ -143,7 +146,7 @@ pub fn init_layout(
     );
     commands.entity(bottom_panel).add_child(add_tab);
 
-    let docs = add_list(&mut commands, &theme, &mut app_state, &mut pkv);
+    let docs = add_list(&mut commands, &theme, &mut app_state, &storage);
 
     let root_ui = commands
         .spawn((
@@ -240,7 +243,7 @@ pub fn init_layout(
     commands.entity(menu).add_child(import_file);
     #[cfg(not(target_arch = "wasm32"))]
     commands.entity(menu).add_child(import_url);
-    if app_state.github_token.is_some() {
+    if sharing.backend.is_some() {
         let share_doc = add_menu_button(
             &mut commands,
             &theme,
@@ -250,9 +253,97 @@ pub fn init_layout(
         );
         commands.entity(menu).add_child(share_doc);
     }
+    let copy_share_link = add_menu_button(
+        &mut commands,
+        &theme,
+        "Copy Share Link".to_string(),
+        &icon_font,
+        CopyShareLink,
+    );
+    commands.entity(menu).add_child(copy_share_link);
     #[cfg(target_arch = "wasm32")]
     commands.entity(menu).add_child(set_window_prop);
-    let theme_key = get_theme_key(&pkv);
+    let trash = add_menu_button(
+        &mut commands,
+        &theme,
+        "Trash".to_string(),
+        &icon_font,
+        OpenTrash,
+    );
+    commands.entity(menu).add_child(trash);
+    let save_snapshot = add_menu_button(
+        &mut commands,
+        &theme,
+        "Save Snapshot".to_string(),
+        &icon_font,
+        SaveSnapshot,
+    );
+    commands.entity(menu).add_child(save_snapshot);
+    let tab_history = add_menu_button(
+        &mut commands,
+        &theme,
+        "Tab History".to_string(),
+        &icon_font,
+        OpenTabHistory,
+    );
+    commands.entity(menu).add_child(tab_history);
+    let lock_doc = add_menu_button(
+        &mut commands,
+        &theme,
+        "Lock Document".to_string(),
+        &icon_font,
+        LockDocument,
+    );
+    commands.entity(menu).add_child(lock_doc);
+    let edit_tags = add_menu_button(
+        &mut commands,
+        &theme,
+        "Edit Tags".to_string(),
+        &icon_font,
+        EditTags,
+    );
+    commands.entity(menu).add_child(edit_tags);
+    let filter_tags = add_menu_button(
+        &mut commands,
+        &theme,
+        "Filter by Tags".to_string(),
+        &icon_font,
+        FilterTags,
+    );
+    commands.entity(menu).add_child(filter_tags);
+    let sort_docs = add_menu_button(
+        &mut commands,
+        &theme,
+        "Sort Documents".to_string(),
+        &icon_font,
+        SortDocs,
+    );
+    commands.entity(menu).add_child(sort_docs);
+    let move_to_folder = add_menu_button(
+        &mut commands,
+        &theme,
+        "Move to Folder".to_string(),
+        &icon_font,
+        MoveToFolder,
+    );
+    commands.entity(menu).add_child(move_to_folder);
+    let save_doc_template = add_menu_button(
+        &mut commands,
+        &theme,
+        "Save Document as Template".to_string(),
+        &icon_font,
+        SaveDocTemplate,
+    );
+    commands.entity(menu).add_child(save_doc_template);
+    let save_tab_template = add_menu_button(
+        &mut commands,
+        &theme,
+        "Save Tab as Template".to_string(),
+        &icon_font,
+        SaveTabTemplate,
+    );
+    commands.entity(menu).add_child(save_tab_template);
+    let theme_key = get_theme_key(&storage);
     let theme_msg = if theme_key == "light" {
         "Enable dark theme (restart is required for now)".to_string()
     } else {
@@ -349,7 +440,7 @@ pub fn init_layout(
         &mut commands,
         &theme,
         &mut cosmic_fonts,
-        cosmic_font_handle,
+        cosmic_font_handle.clone(),
         primary_window.scale_factor() as f32,
     );
     let left_panel_explorer = commands
@@ -388,6 +479,9 @@ pub fn init_layout(
         ButtonAction {
             button_type: ui_helpers::ButtonTypes::AddPaper,
         },
+        ButtonAction {
+            button_type: ui_helpers::ButtonTypes::AddFrame,
+        },
         ButtonAction {
             button_type: ui_helpers::ButtonTypes::Del,
         },
@@ -589,4 +683,19 @@ pub fn init_layout(
     commands.entity(main_bottom).add_child(right_panel);
     commands.entity(root_ui).add_child(menu);
     commands.entity(root_ui).add_child(main_bottom);
+
+    if has_unsaved_journal(&storage, app_state.current_document) {
+        let id = ReflectableUuid::generate();
+        ui_state.modal_id = Some(id);
+        let modal = spawn_modal(
+            &mut commands,
+            &theme,
+            &mut cosmic_fonts,
+            cosmic_font_handle,
+            primary_window,
+            id,
+            ModalAction::RestoreJournal,
+        );
+        commands.entity(main_panel).add_child(modal);
+    }
 }
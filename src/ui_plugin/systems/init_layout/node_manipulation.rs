@@ -12,6 +12,7 @@ pub fn node_manipulation(
     create_rec_component: ButtonAction,
     create_circle_component: ButtonAction,
     papernote_component: ButtonAction,
+    frame_component: ButtonAction,
     delete_component: ButtonAction,
 ) -> Entity {
     let node = commands
@@ -58,6 +59,16 @@ pub fn node_manipulation(
         create_circle_component,
     );
 
+    let top_new_frame = add_button_action(
+        commands,
+        theme,
+        "New Frame".to_string(),
+        icon_font,
+        "\u{e3c2}".to_string(),
+        theme.node_manipulation,
+        frame_component,
+    );
+
     let top_del = add_button_action(
         commands,
         theme,
@@ -72,6 +83,7 @@ pub fn node_manipulation(
     commands.entity(node).add_child(top_new_circle);
     commands.entity(node).add_child(top_new_rec);
     commands.entity(node).add_child(top_new_paper);
+    commands.entity(node).add_child(top_new_frame);
     node
 }
 
@@ -91,7 +103,7 @@ fn add_button_action(
                 flex_direction: FlexDirection::Column,
                 align_self: AlignSelf::Stretch,
                 margin: UiRect::all(Val::Px(5.)),
-                width: Val::Percent(23.),
+                width: Val::Percent(18.),
                 height: Val::Percent(100.),
                 ..default()
             },
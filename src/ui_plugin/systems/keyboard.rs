@@ -6,7 +6,7 @@ use bevy::{
 };
 
 use bevy_cosmic_edit::{
-    get_cosmic_text, get_text_spans, CosmicEdit, CosmicEditHistory, EditHistoryItem,
+    get_cosmic_text, get_text_spans, ActiveEditor, CosmicEdit, CosmicEditHistory, EditHistoryItem,
 };
 use cosmic_text::Edit;
 #[cfg(not(target_arch = "wasm32"))]
@@ -17,7 +17,7 @@ use uuid::Uuid;
 
 use crate::{
     components::MainCamera,
-    resources::{LoadTabRequest, SaveTabRequest},
+    resources::{ClipboardRequest, HistoryRequest, LoadTabRequest, SaveTabRequest},
     themes::Theme,
     utils::bevy_color_to_cosmic,
     AddRect, UiState,
@@ -44,6 +44,7 @@ pub fn keyboard_input_system(
     >,
     mut camera_proj_query: Query<&Transform, With<MainCamera>>,
     theme: Res<Theme>,
+    active_editor: Res<ActiveEditor>,
 ) {
     let camera_transform = camera_proj_query.single_mut();
     let x = camera_transform.translation.x;
@@ -54,7 +55,23 @@ pub fn keyboard_input_system(
     let shift = input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
     if command && input.just_pressed(KeyCode::V) {
         #[cfg(not(target_arch = "wasm32"))]
-        insert_from_clipboard(&mut images, &mut events, x, y, scale_factor, &theme);
+        if !insert_from_clipboard(&mut images, &mut events, x, y, scale_factor, &theme)
+            && active_editor.entity.is_none()
+        {
+            paste_text_from_clipboard(&mut commands, &mut events, x, y, &theme);
+        }
+    } else if command
+        && input.any_just_pressed([KeyCode::C, KeyCode::X])
+        && active_editor.entity.is_none()
+        && cfg!(not(target_arch = "wasm32"))
+    {
+        if input.just_pressed(KeyCode::X) {
+            commands.insert_resource(ClipboardRequest::Cut);
+        } else {
+            commands.insert_resource(ClipboardRequest::Copy);
+        }
+    } else if command && input.just_pressed(KeyCode::D) && active_editor.entity.is_none() {
+        commands.insert_resource(ClipboardRequest::Duplicate);
     } else if command && shift && input.just_pressed(KeyCode::S) {
         commands.insert_resource(SaveDocRequest {
             doc_id: app_state.current_document.unwrap(),
@@ -76,9 +93,17 @@ pub fn keyboard_input_system(
                     doc_id: app_state.current_document.unwrap(),
                     tab_id: active_tab.id,
                     drop_last_checkpoint: true,
+                    checkpoint: None,
                 });
             }
         }
+    } else if command && input.just_pressed(KeyCode::Z) && active_editor.entity.is_none() {
+        // text editors keep their own undo history
+        if shift {
+            commands.insert_resource(HistoryRequest::Redo);
+        } else {
+            commands.insert_resource(HistoryRequest::Undo);
+        }
     } else {
         for (editable_text, mut cosmic_edit, mut cosmit_edit_history) in
             &mut editable_text_query.iter_mut()
@@ -112,12 +137,12 @@ pub fn keyboard_input_system(
                     *ui_state = UiState::default();
                 }
                 if let Some(doc_id) = ui_state.doc_to_edit {
-                    let doc = app_state.docs.get_mut(&doc_id).unwrap();
+                    let doc = app_state.doc_mut(&doc_id).unwrap();
                     doc.name = get_cosmic_text(cosmic_edit.editor.buffer())
                 }
                 if let Some(tab_id) = ui_state.tab_to_edit {
                     if let Some(doc_id) = app_state.current_document {
-                        let doc = app_state.docs.get_mut(&doc_id).unwrap();
+                        let doc = app_state.doc_mut(&doc_id).unwrap();
                         if let Some(tab) = doc.tabs.iter_mut().find(|x| x.id == tab_id) {
                             tab.name = get_cosmic_text(cosmic_edit.editor.buffer())
                         }
@@ -136,7 +161,7 @@ pub fn insert_from_clipboard(
     y: f32,
     scale_factor: f64,
     theme: &Res<Theme>,
-) {
+) -> bool {
     use crate::JsonNode;
 
     if let Ok(mut clipboard) = arboard::Clipboard::new() {
@@ -178,6 +203,53 @@ pub fn insert_from_clipboard(
                 },
                 image: Some(image),
             });
+            return true;
+        }
+    }
+    false
+}
+
+/// Pastes items copied from a canvas, any other text becomes a new node.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn paste_text_from_clipboard(
+    commands: &mut Commands,
+    events: &mut EventWriter<AddRect<(String, Color)>>,
+    x: f32,
+    y: f32,
+    theme: &Res<Theme>,
+) {
+    use crate::format::Checkpoint;
+    use crate::resources::PasteRequest;
+    use crate::JsonNode;
+
+    let Ok(text) = arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) else {
+        return;
+    };
+    if let Ok(checkpoint) = Checkpoint::from_json(&text) {
+        if !checkpoint.nodes.is_empty() || !checkpoint.drawings.is_empty() {
+            let offset = Vec2::new(x, y) - super::fragment_center(&checkpoint);
+            commands.insert_resource(PasteRequest { checkpoint, offset });
+            return;
         }
     }
+    if text.trim().is_empty() {
+        return;
+    }
+    events.send(AddRect {
+        node: JsonNode {
+            id: Uuid::new_v4(),
+            node_type: crate::NodeType::Rect,
+            x,
+            y,
+            width: theme.node_width,
+            height: theme.node_height,
+            text: crate::JsonNodeText {
+                text,
+                pos: crate::TextPos::Center,
+            },
+            bg_color: pair_struct!(theme.node_bg),
+            z: 0.,
+        },
+        image: None,
+    });
 }
@@ -1,30 +1,27 @@
-use base64::{engine::general_purpose, Engine};
-use bevy::{
-    prelude::*,
-    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
-    window::PrimaryWindow,
-};
+use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_cosmic_edit::CosmicFont;
-use bevy_prototype_lyon::prelude::{PathBuilder, ShapeBundle, Stroke};
 
 use super::{
+    theme_color,
     ui_helpers::{
-        add_tab, spawn_sprite_node, BottomPanel, Drawing, NodeMeta, TabContainer, VeloNode,
+        add_tab, spawn_drawing, spawn_sprite_node, BottomPanel, Drawing, NodeMeta, TabContainer,
+        VeloNode,
     },
-    DeleteDoc, DeleteTab, DrawingJsonNode,
+    DeleteDoc, DeleteTab, DrawingJsonNode, JsonNode, Toasts,
 };
 use crate::{canvas::arrow::events::CreateArrow, utils::load_doc_to_memory};
 use crate::{
     canvas::{arrow::components::ArrowMeta, shadows::CustomShadowMaterial},
-    resources::{FontSystemState, LoadTabRequest},
+    format::Checkpoint,
+    resources::{FontSystemState, ImageHashes, LoadTabRequest},
     themes::Theme,
 };
 
-use crate::resources::{AppState, LoadDocRequest};
-use crate::utils::ReflectableUuid;
-use crate::{JsonNode, UiState};
-use bevy_pkv::PkvStore;
-use image::{load_from_memory_with_format, ImageFormat};
+use crate::error::VeloError;
+use crate::resources::{AppState, LoadDocRequest, UnlockDocRequest};
+use crate::store::{import_images, Storage};
+use crate::utils::{base64_to_image, ReflectableUuid};
+use crate::UiState;
 use serde_json::{Map, Value};
 
 pub fn should_load_doc(request: Option<Res<LoadDocRequest>>) -> bool {
@@ -48,7 +45,7 @@ pub fn load_doc(
     mut app_state: ResMut<AppState>,
     mut commands: Commands,
     mut bottom_panel: Query<Entity, With<BottomPanel>>,
-    mut pkv: ResMut<PkvStore>,
+    storage: Res<Storage>,
     asset_server: Res<AssetServer>,
     mut tabs_query: Query<Entity, With<TabContainer>>,
     mut delete_doc: Query<(&mut Visibility, &DeleteDoc), With<DeleteDoc>>,
@@ -56,11 +53,44 @@ pub fn load_doc(
     mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
     font_system_state: ResMut<FontSystemState>,
     windows: Query<&Window, With<PrimaryWindow>>,
+    toasts: Res<Toasts>,
 ) {
     let primary_window = windows.single();
     let scale_factor = primary_window.scale_factor() as f32;
     let bottom_panel = bottom_panel.single_mut();
-    let doc_id = request.doc_id;
+    let doc_id = match load_doc_to_memory(request.doc_id, &mut app_state, &storage) {
+        Ok(()) => request.doc_id,
+        Err(e) => {
+            toasts.error("Could not open document", e);
+            // show the most recently opened document that is still at hand instead
+            let fallback = app_state
+                .recent_docs
+                .iter()
+                .rev()
+                .find(|id| app_state.docs.contains_key(id))
+                .or_else(|| app_state.docs.keys().next())
+                .cloned();
+            match fallback {
+                Some(doc_id) if load_doc_to_memory(doc_id, &mut app_state, &storage).is_ok() => {
+                    app_state.current_document = Some(doc_id);
+                    doc_id
+                }
+                _ => {
+                    for entity in tabs_query.iter_mut() {
+                        commands.entity(entity).despawn_recursive();
+                    }
+                    for (mut visibility, _) in delete_doc.iter_mut() {
+                        *visibility = Visibility::Hidden;
+                    }
+                    return;
+                }
+            }
+        }
+    };
+    if app_state.docs[&doc_id].sealed.is_some() {
+        commands.insert_resource(UnlockDocRequest { doc_id });
+        return;
+    }
     for (mut visibility, doc) in delete_doc.iter_mut() {
         if doc.id == doc_id {
             *visibility = Visibility::Visible;
@@ -68,13 +98,12 @@ pub fn load_doc(
             *visibility = Visibility::Hidden;
         }
     }
-    load_doc_to_memory(doc_id, &mut app_state, &mut pkv);
 
     let mut tabs = vec![];
     for entity in tabs_query.iter_mut() {
         commands.entity(entity).despawn_recursive();
     }
-    for tab in app_state.docs.get_mut(&doc_id).unwrap().tabs.iter() {
+    for tab in app_state.docs[&doc_id].tabs.iter() {
         let tab_view: Entity = add_tab(
             &mut commands,
             &mut cosmic_fonts,
@@ -92,6 +121,7 @@ pub fn load_doc(
                 doc_id,
                 tab_id: tab.id,
                 drop_last_checkpoint: false,
+                checkpoint: None,
             });
         }
     }
@@ -106,13 +136,13 @@ pub fn load_tab(
     mut app_state: ResMut<AppState>,
     mut ui_state: ResMut<UiState>,
     mut commands: Commands,
-    mut res_images: ResMut<Assets<Image>>,
+    (mut res_images, mut image_hashes): (ResMut<Assets<Image>>, ResMut<ImageHashes>),
     mut create_arrow: EventWriter<CreateArrow>,
     mut delete_tab: Query<(&mut Visibility, &DeleteTab), (With<DeleteTab>, Without<ArrowMeta>)>,
     mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
-    font_system_state: ResMut<FontSystemState>,
+    (font_system_state, mut storage): (ResMut<FontSystemState>, ResMut<Storage>),
     mut windows: Query<&mut Window, With<PrimaryWindow>>,
-    theme: Res<Theme>,
+    (theme, toasts): (Res<Theme>, Res<Toasts>),
     mut local_theme: Local<Option<Map<String, Value>>>,
     mut materials_meshes: (ResMut<Assets<CustomShadowMaterial>>, ResMut<Assets<Mesh>>),
 ) {
@@ -144,126 +174,171 @@ pub fn load_tab(
             *visibility = Visibility::Hidden;
         }
     }
-    for tab in app_state.docs.get_mut(&doc_id).unwrap().tabs.iter_mut() {
-        if tab.id == request.tab_id {
-            if tab.checkpoints.is_empty() {
-                break;
-            }
+    // images of locked documents stay out of the store
+    let locked = app_state.passphrases.contains_key(&doc_id);
+    // dropping the last checkpoint changes the document, merely showing one does not
+    let doc = if request.drop_last_checkpoint {
+        app_state.doc_mut(&doc_id)
+    } else {
+        app_state.docs.get_mut(&doc_id)
+    };
+    let Some(doc) = doc else {
+        toasts.error("Could not open tab", VeloError::DocumentNotFound(doc_id));
+        return;
+    };
+    let Some(tab) = doc.tabs.iter_mut().find(|tab| tab.id == request.tab_id) else {
+        return;
+    };
+    if tab.checkpoints.is_empty() {
+        return;
+    }
+    let json = if let Some(json) = request.checkpoint.clone() {
+        json
+    } else if request.drop_last_checkpoint && tab.checkpoints.len() > 1 {
+        tab.checkpoints.pop_back().unwrap()
+    } else {
+        tab.checkpoints.back().unwrap().clone()
+    };
+    let mut checkpoint = match Checkpoint::from_json(&json) {
+        Ok(checkpoint) => checkpoint,
+        Err(e) => {
+            toasts.error("Could not open tab", e.into());
+            return;
+        }
+    };
+    if locked {
+        app_state.locked_images.extend(checkpoint.blobs.clone());
+    } else if !checkpoint.blobs.is_empty() {
+        if let Err(e) = import_images(storage.0.as_mut(), &checkpoint.blobs) {
+            toasts.error("Could not store images", e.into());
+        }
+    }
+    checkpoint.inline_images(|hash| app_state.image(storage.0.as_ref(), hash));
+    let (nodes, drawings) = themed(&checkpoint, &theme, local_theme.as_ref().unwrap());
+    for json_node in nodes {
+        let node_id = json_node.id.to_string();
+        let image: Option<Handle<Image>> = checkpoint
+            .image_data(&node_id)
+            .and_then(|image| base64_to_image(image))
+            .map(|image| {
+                let handle = res_images.add(image);
+                image_hashes
+                    .0
+                    .insert(handle.id(), checkpoint.images[&node_id].clone());
+                handle
+            });
+        let _ = spawn_sprite_node(
+            &mut commands,
+            &mut materials_meshes.0,
+            &mut materials_meshes.1,
+            &theme,
+            &mut cosmic_fonts,
+            font_system_state.0.clone().unwrap(),
+            scale_factor,
+            NodeMeta {
+                size: (json_node.width, json_node.height),
+                node_type: json_node.node_type,
+                id: ReflectableUuid(json_node.id),
+                image,
+                text: json_node.text.text.clone(),
+                pair_bg_color: json_node.bg_color,
+                position: (json_node.x, json_node.y, json_node.z),
+                text_pos: json_node.text.pos,
+                is_active: false,
+            },
+        );
+    }
 
-            let json = if request.drop_last_checkpoint && tab.checkpoints.len() > 1 {
-                tab.checkpoints.pop_back().unwrap()
-            } else {
-                tab.checkpoints.back().unwrap().clone()
-            };
-            let mut json: Value = serde_json::from_str(&json).unwrap();
-            let images = json["images"].as_object().unwrap();
-            let nodes = json["nodes"].as_array().unwrap();
-            for node in nodes.iter() {
-                let json_node: JsonNode<String> = serde_json::from_value(node.clone()).unwrap();
-                let image: Option<Handle<Image>> = match images.get(&json_node.id.to_string()) {
-                    Some(image) => {
-                        let image_bytes = general_purpose::STANDARD
-                            .decode(image.as_str().unwrap().as_bytes())
-                            .unwrap();
-                        let img =
-                            load_from_memory_with_format(&image_bytes, ImageFormat::Png).unwrap();
-                        let size: Extent3d = Extent3d {
-                            width: img.width(),
-                            height: img.height(),
-                            ..Default::default()
-                        };
-                        let image = Image::new(
-                            size,
-                            TextureDimension::D2,
-                            img.into_bytes(),
-                            TextureFormat::Rgba8UnormSrgb,
-                        );
-                        let image_handle = res_images.add(image);
-                        Some(image_handle)
-                    }
-                    None => None,
-                };
-                let theme_color = local_theme
-                    .as_ref()
-                    .unwrap()
-                    .get(json_node.bg_color.as_str())
-                    .unwrap();
-                let pair_bg_color = (
-                    json_node.bg_color,
-                    serde_json::from_value(theme_color.clone()).unwrap(),
-                );
-                let _ = spawn_sprite_node(
-                    &mut commands,
-                    &mut materials_meshes.0,
-                    &mut materials_meshes.1,
-                    &theme,
-                    &mut cosmic_fonts,
-                    font_system_state.0.clone().unwrap(),
-                    scale_factor,
-                    NodeMeta {
-                        size: (json_node.width, json_node.height),
-                        node_type: json_node.node_type,
-                        id: ReflectableUuid(json_node.id),
-                        image,
-                        text: json_node.text.text.clone(),
-                        pair_bg_color,
-                        position: (json_node.x, json_node.y, json_node.z),
-                        text_pos: json_node.text.pos,
-                        is_active: false,
-                    },
-                );
-            }
+    for arrow_meta in checkpoint.arrows.iter() {
+        create_arrow.send(CreateArrow {
+            start: arrow_meta.start,
+            end: arrow_meta.end,
+            arrow_type: arrow_meta.arrow_type,
+        });
+    }
+    for drawing in drawings {
+        spawn_drawing(
+            &mut commands,
+            DrawingJsonNode {
+                width: 2.,
+                ..drawing
+            },
+        );
+    }
+}
 
-            let arrows = json["arrows"].as_array_mut().unwrap();
-            for arrow in arrows.iter() {
-                let arrow_meta: ArrowMeta = serde_json::from_value(arrow.clone()).unwrap();
-                create_arrow.send(CreateArrow {
-                    start: arrow_meta.start,
-                    end: arrow_meta.end,
-                    arrow_type: arrow_meta.arrow_type,
-                });
-            }
-            let drawings = json["drawings"].as_array_mut().unwrap();
-            for drawing in drawings.iter() {
-                let drawing_json_node: DrawingJsonNode<String> =
-                    serde_json::from_value(drawing.clone()).unwrap();
-                let mut path_builder = PathBuilder::new();
-                let mut points_iter = drawing_json_node.points.iter();
-                let start = points_iter.next().unwrap();
-                path_builder.move_to(*start);
-                path_builder.line_to(*start);
-                for point in points_iter {
-                    path_builder.line_to(*point);
-                }
-                let path = path_builder.build();
-                let theme_color = local_theme
-                    .as_ref()
-                    .unwrap()
-                    .get(drawing_json_node.drawing_color.as_str())
-                    .unwrap();
-                let pair_color = (
-                    drawing_json_node.drawing_color,
-                    serde_json::from_value(theme_color.clone()).unwrap(),
-                );
-                commands.spawn((
-                    ShapeBundle {
-                        path,
-                        transform: Transform::from_xyz(
-                            drawing_json_node.x,
-                            drawing_json_node.y,
-                            drawing_json_node.z,
-                        ),
-                        ..Default::default()
-                    },
-                    Stroke::new(pair_color.1, 2.),
-                    Drawing {
-                        id: drawing_json_node.id,
-                        points: drawing_json_node.points.clone(),
-                        drawing_color: pair_color,
-                    },
-                ));
-            }
-            break;
+/// Nodes and drawings of `checkpoint` with their theme colors. A key the theme lacks,
+/// e.g. from another version of the app, falls back to the default node or pencil color.
+fn themed(
+    checkpoint: &Checkpoint,
+    theme: &Theme,
+    theme_colors: &Map<String, Value>,
+) -> (
+    Vec<JsonNode<(String, Color)>>,
+    Vec<DrawingJsonNode<(String, Color)>>,
+) {
+    let nodes = checkpoint
+        .nodes
+        .iter()
+        .cloned()
+        .map(|node| {
+            node.map_color(|key| {
+                theme_color(theme_colors, key).unwrap_or(pair_struct!(theme.node_bg))
+            })
+        })
+        .collect();
+    let drawings = checkpoint
+        .drawings
+        .iter()
+        .cloned()
+        .map(|drawing| {
+            drawing.map_color(|key| {
+                theme_color(theme_colors, key).unwrap_or(pair_struct!(theme.drawing_pencil_btn))
+            })
+        })
+        .collect();
+    (nodes, drawings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::themes::velo_light;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_unknown_colors_fall_back_to_defaults() {
+        let theme = velo_light();
+        let theme_colors = serde_json::to_value(&theme)
+            .unwrap()
+            .as_object()
+            .unwrap()
+            .clone();
+        let mut checkpoint = Checkpoint::default();
+        for (i, color) in ["node_bg", "no_such_color"].into_iter().enumerate() {
+            checkpoint.nodes.push(JsonNode {
+                id: Uuid::from_u128(i as u128),
+                bg_color: color.to_string(),
+                ..Default::default()
+            });
         }
+        checkpoint.drawings.push(DrawingJsonNode {
+            x: 0.,
+            y: 0.,
+            z: 1.,
+            id: ReflectableUuid::generate(),
+            points: vec![Vec2::ZERO, Vec2::ONE],
+            drawing_color: "no_such_color".to_string(),
+            width: 2.,
+        });
+        let checkpoint = Checkpoint::from_json(&checkpoint.to_json()).unwrap();
+
+        let (nodes, drawings) = themed(&checkpoint, &theme, &theme_colors);
+        assert_eq!(nodes[0].bg_color, ("node_bg".to_string(), theme.node_bg));
+        assert_eq!(nodes[1].bg_color, pair_struct!(theme.node_bg));
+        assert_eq!(
+            drawings[0].drawing_color,
+            pair_struct!(theme.drawing_pencil_btn)
+        );
     }
 }
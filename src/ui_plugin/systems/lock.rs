@@ -0,0 +1,156 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::CosmicFont;
+
+use super::ui_helpers::{spawn_modal, LockDocument};
+use super::{MainPanel, ModalAction, Toasts, UiState};
+use crate::crypto::unseal_doc;
+use crate::resources::{
+    AppState, FontSystemState, LoadDocRequest, SaveDocRequest, UnlockDocRequest,
+};
+use crate::themes::Theme;
+use crate::utils::ReflectableUuid;
+
+pub fn open_lock_modal(
+    mut commands: Commands,
+    query: Query<&Interaction, (Changed<Interaction>, With<LockDocument>)>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let window = windows.single();
+    for interaction in query.iter() {
+        if *interaction == Interaction::Pressed {
+            let id = ReflectableUuid::generate();
+            *ui_state = UiState::default();
+            ui_state.modal_id = Some(id);
+            let entity = spawn_modal(
+                &mut commands,
+                &theme,
+                &mut cosmic_fonts,
+                font_system_state.0.clone().unwrap(),
+                window,
+                id,
+                ModalAction::LockDocument,
+            );
+            commands.entity(main_panel_query.single()).add_child(entity);
+        }
+    }
+}
+
+pub fn should_unlock_doc(request: Option<Res<UnlockDocRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_unlock_doc_request(world: &mut World) {
+    world.remove_resource::<UnlockDocRequest>().unwrap();
+}
+
+/// The passphrase modal takes the id of the locked document.
+pub fn prompt_passphrase(
+    request: Res<UnlockDocRequest>,
+    mut commands: Commands,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    *ui_state = UiState::default();
+    ui_state.modal_id = Some(request.doc_id);
+    let entity = spawn_modal(
+        &mut commands,
+        &theme,
+        &mut cosmic_fonts,
+        font_system_state.0.clone().unwrap(),
+        windows.single(),
+        request.doc_id,
+        ModalAction::UnlockDocument,
+    );
+    commands.entity(main_panel_query.single()).add_child(entity);
+}
+
+/// Locks the current document with `passphrase`, an empty one removes the lock. Either
+/// way the document is saved again, sealed or in the clear.
+pub fn lock_doc(
+    app_state: &mut AppState,
+    commands: &mut Commands,
+    passphrase: &str,
+    toasts: &Toasts,
+) {
+    let Some(doc_id) = app_state.current_document else {
+        return;
+    };
+    let Some(doc) = app_state.docs.get(&doc_id) else {
+        return;
+    };
+    if doc.sealed.is_some() {
+        return;
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    let tabs = doc.tabs.clone();
+    if passphrase.is_empty() {
+        if app_state.passphrases.remove(&doc_id).is_none() {
+            return;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(search_index) = &mut app_state.search_index {
+            super::reindex_tabs(search_index, &doc_id, &tabs);
+        }
+        toasts.info("The document is no longer locked");
+    } else {
+        app_state.passphrases.insert(doc_id, passphrase.to_string());
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(search_index) = &mut app_state.search_index {
+            search_index
+                .tabs_to_delete
+                .extend(tabs.iter().map(|tab| tab.id.0));
+        }
+        toasts.info("The document is locked");
+    }
+    // the stored copy is in the other form until the save
+    app_state.mark_dirty(doc_id);
+    commands.insert_resource(SaveDocRequest { doc_id, path: None });
+}
+
+/// Opens a locked document, a wrong passphrase is asked for again.
+pub fn unlock_doc(
+    app_state: &mut AppState,
+    commands: &mut Commands,
+    doc_id: ReflectableUuid,
+    passphrase: &str,
+    toasts: &Toasts,
+) {
+    let Some(doc) = app_state.docs.get(&doc_id) else {
+        return;
+    };
+    match unseal_doc(doc, passphrase) {
+        Ok((doc, images)) => {
+            app_state.locked_images.extend(images);
+            app_state.passphrases.insert(doc_id, passphrase.to_string());
+            app_state.docs.insert(doc_id, doc);
+            commands.insert_resource(LoadDocRequest { doc_id });
+        }
+        Err(e) => {
+            toasts.error("Could not unlock document", e);
+            commands.insert_resource(UnlockDocRequest { doc_id });
+        }
+    }
+}
+
+/// Shows a document at hand instead of the locked one, if there is any.
+pub fn skip_unlock(app_state: &mut AppState, commands: &mut Commands, doc_id: ReflectableUuid) {
+    let Some(other_id) = app_state
+        .docs
+        .values()
+        .find(|doc| doc.id != doc_id && doc.sealed.is_none())
+        .map(|doc| doc.id)
+    else {
+        return;
+    };
+    app_state.current_document = Some(other_id);
+    commands.insert_resource(LoadDocRequest { doc_id: other_id });
+}
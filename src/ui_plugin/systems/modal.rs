@@ -1,19 +1,29 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fs::canonicalize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use bevy::prelude::*;
 use bevy::tasks::IoTaskPool;
 use bevy_cosmic_edit::{get_cosmic_text, ActiveEditor, CosmicEdit};
-use bevy_pkv::PkvStore;
 use cosmic_text::Edit;
 use linkify::{LinkFinder, LinkKind};
 
 use super::ui_helpers::{ModalCancel, ModalConfirm, ModalTop};
-use super::{CommChannels, EditableText, ModalAction, TabContainer};
-use crate::components::Doc;
-use crate::resources::{AppState, LoadDocRequest, LoadTabRequest, SaveDocRequest};
-use crate::utils::ReflectableUuid;
+use super::{
+    end_preview, lock_doc, move_to_folder, open_board, request_snapshot, request_template,
+    response_text, set_doc_tags, set_tag_filter, skip_unlock, unlock_doc, CommChannels,
+    EditableText, HistoryBrowser, ModalAction, RefreshDocList, TabContainer, Toasts,
+};
+use crate::components::{Doc, Tab};
+use crate::crypto::doc_at_rest;
+use crate::error::VeloError;
+use crate::format::DocFile;
+use crate::import::markdown_to_checkpoint;
+use crate::resources::{AppState, LoadDocRequest, LoadTabRequest, SaveDocRequest, SaveTabRequest};
+use crate::share::board_data;
+use crate::store::{import_images, move_to_trash, Storage, StoreError, TrashedItem};
+use crate::themes::Theme;
+use crate::utils::{get_timestamp, ReflectableUuid};
 use crate::UiState;
 
 pub fn cancel_modal(
@@ -24,11 +34,25 @@ pub fn cancel_modal(
     >,
     mut state: ResMut<UiState>,
     query: Query<(Entity, &ModalTop), With<ModalTop>>,
+    mut storage: ResMut<Storage>,
+    mut app_state: ResMut<AppState>,
+    mut history_browser: ResMut<HistoryBrowser>,
 ) {
     for (interaction, path_modal_cancel) in interaction_query.iter_mut() {
         if let Interaction::Pressed = interaction {
             for (entity, path_modal_top) in query.iter() {
                 if path_modal_cancel.id == path_modal_top.id {
+                    if path_modal_top.action == ModalAction::RestoreJournal {
+                        if let Err(e) = storage.remove_journal() {
+                            error!("Error discarding recovery journal: {}", e);
+                        }
+                    }
+                    if path_modal_top.action == ModalAction::TabHistory {
+                        end_preview(&mut history_browser, &app_state, &mut commands);
+                    }
+                    if path_modal_top.action == ModalAction::UnlockDocument {
+                        skip_unlock(&mut app_state, &mut commands, path_modal_top.id);
+                    }
                     commands.entity(entity).despawn_recursive();
                     state.modal_id = None;
                 }
@@ -40,14 +64,27 @@ pub fn cancel_modal(
 fn delete_doc(
     app_state: &mut ResMut<AppState>,
     commands: &mut Commands,
-    pkv: &mut ResMut<PkvStore>,
+    storage: &mut Storage,
+    toasts: &Toasts,
 ) {
-    let current_document = app_state.current_document.unwrap();
-    let id_to_remove = current_document;
-    app_state.docs.remove(&current_document);
-    remove_from_storage(pkv, id_to_remove, app_state.current_document.unwrap());
+    let id_to_remove = app_state.current_document.unwrap();
+    let Some(doc) = app_state.docs.remove(&id_to_remove) else {
+        return;
+    };
+    app_state.dirty_docs.remove(&id_to_remove);
     app_state.current_document = app_state.docs.keys().next().cloned();
     app_state.doc_list_ui.remove(&id_to_remove);
+    let result = doc_at_rest(&doc, &app_state.passphrases, |hash| {
+        app_state.image(storage.0.as_ref(), hash)
+    })
+    .and_then(|doc| {
+        remove_from_storage(storage, doc, app_state.current_document.unwrap())
+            .map_err(VeloError::from)
+    });
+    if let Err(e) = result {
+        toasts.error("Could not move document to trash", e);
+    }
+    app_state.passphrases.remove(&id_to_remove);
     commands.insert_resource(LoadDocRequest {
         doc_id: app_state.current_document.unwrap(),
     });
@@ -69,6 +106,8 @@ fn delete_tab(
     app_state: &mut ResMut<AppState>,
     commands: &mut Commands,
     query_container: &mut Query<(Entity, &TabContainer), With<TabContainer>>,
+    storage: &mut Storage,
+    toasts: &Toasts,
 ) {
     let current_document = app_state.current_document.unwrap();
     let tab_id = app_state
@@ -92,23 +131,26 @@ fn delete_tab(
             break;
         }
     }
-    let index = app_state
-        .docs
-        .get_mut(&current_document)
-        .unwrap()
+    let index = app_state.docs[&current_document]
         .tabs
         .iter()
         .position(|x| x.is_active)
         .unwrap();
-    app_state
-        .docs
-        .get_mut(&current_document)
-        .unwrap()
-        .tabs
-        .remove(index);
+    let locked = app_state.passphrases.contains_key(&current_document);
+    let doc = app_state.doc_mut(&current_document).unwrap();
+    let item = TrashedItem::Tab {
+        doc_id: doc.id,
+        doc_name: doc.name.clone(),
+        tab: doc.tabs.remove(index),
+    };
+    // the trash keeps tabs in the clear, those of locked documents are deleted for good
+    if !locked {
+        if let Err(e) = move_to_trash(storage.0.as_mut(), item, get_timestamp()) {
+            toasts.error("Could not move tab to trash", e.into());
+        }
+    }
     let last_tab = app_state
-        .docs
-        .get_mut(&current_document)
+        .doc_mut(&current_document)
         .unwrap()
         .tabs
         .last_mut()
@@ -118,33 +160,126 @@ fn delete_tab(
         doc_id: current_document,
         tab_id: last_tab.id,
         drop_last_checkpoint: false,
+        checkpoint: None,
     });
 }
 
+/// Opens a velo json file, a markdown file is added as a tab of the current document.
+fn open_file(
+    text: &str,
+    app_state: &mut ResMut<AppState>,
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    comm_channels: &CommChannels,
+) -> Result<(), VeloError> {
+    let path = canonicalize(text).map_err(|e| VeloError::Io(PathBuf::from(text), e))?;
+    if path.extension().map_or(false, |ext| ext == "md") {
+        return import_markdown(&path, app_state, commands, theme);
+    }
+    let json = std::fs::read_to_string(&path).map_err(|e| VeloError::Io(path, e))?;
+    comm_channels.send(json)
+}
+
+/// Fetches a velo json document, `load_doc_handler` opens it once it arrives.
+fn open_url(text: &str, comm_channels: &CommChannels, toasts: &Toasts) {
+    if let Some(data) = board_data(text.trim()) {
+        if let Err(e) = open_board(comm_channels, data) {
+            toasts.error("Could not open shared board", e);
+        }
+        return;
+    }
+    let mut finder = LinkFinder::new();
+    finder.kinds(&[LinkKind::Url]);
+    let links: Vec<_> = finder.links(text).collect();
+    if links.len() != 1 {
+        toasts.error(
+            "Could not open document",
+            VeloError::Network(format!("{} is not a url", text)),
+        );
+        return;
+    }
+    let url = links[0].as_str().to_owned();
+    let comm_channels = comm_channels.clone();
+    let toasts = toasts.clone();
+    let task = IoTaskPool::get().spawn(async move {
+        let request = ehttp::Request::get(url);
+        ehttp::fetch(request, move |result| {
+            if let Err(e) = response_text(result).and_then(|json| comm_channels.send(json)) {
+                toasts.error("Could not open document", e);
+            }
+        });
+    });
+    task.detach();
+}
+
+/// Adds the outline of a markdown file as a new tab of the current document.
+fn import_markdown(
+    path: &Path,
+    app_state: &mut ResMut<AppState>,
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+) -> Result<(), VeloError> {
+    let text = std::fs::read_to_string(path).map_err(|e| VeloError::Io(path.to_path_buf(), e))?;
+    let checkpoint = markdown_to_checkpoint(&text, Vec2::new(theme.node_width, theme.node_height))
+        .map_err(VeloError::InvalidDocument)?;
+    let current_document = app_state.current_document.unwrap();
+    let tabs = &mut app_state.doc_mut(&current_document).unwrap().tabs;
+    for tab in tabs.iter_mut() {
+        if tab.is_active {
+            commands.insert_resource(SaveTabRequest {
+                tab_id: tab.id,
+                doc_id: current_document,
+            });
+        }
+        tab.is_active = false;
+    }
+    let name = path.file_stem().map_or("Markdown".to_string(), |stem| {
+        stem.to_string_lossy().to_string()
+    });
+    tabs.push(Tab {
+        id: ReflectableUuid::generate(),
+        name,
+        checkpoints: VecDeque::from([checkpoint.to_json()]),
+        is_active: true,
+        z_index: 1.,
+        history: vec![],
+    });
+    commands.insert_resource(LoadDocRequest {
+        doc_id: current_document,
+    });
+    Ok(())
+}
+
 pub fn load_doc_handler(
     mut commands: Commands,
     mut app_state: ResMut<AppState>,
     comm_channels: Res<CommChannels>,
-    pkv: Res<PkvStore>,
+    mut storage: ResMut<Storage>,
+    toasts: Res<Toasts>,
 ) {
-    if comm_channels.rx.is_empty() {
+    let Ok(r) = comm_channels.rx.try_recv() else {
         return;
-    }
-    let r = comm_channels
-        .rx
-        .try_recv()
-        .expect("Failed to receive document string");
-    let import_document: Doc = serde_json::from_str(&r).expect("Failed to deserialize document");
-    if let Ok(docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-        if docs.contains_key(&import_document.id) {
+    };
+    let doc_file = match DocFile::from_json(&r) {
+        Ok(file) => file,
+        Err(e) => {
+            toasts.error("Could not open document", e.into());
+            return;
+        }
+    };
+    if let Ok(index) = storage.index() {
+        if index.contains_key(&doc_file.doc.id) {
             return;
         }
     }
+    if let Err(e) = import_images(storage.0.as_mut(), &doc_file.images) {
+        toasts.error("Could not open document", e.into());
+        return;
+    }
+    let import_document = doc_file.doc;
     app_state.current_document = Some(import_document.id);
     app_state.doc_list_ui.insert(import_document.id);
-    app_state
-        .docs
-        .insert(import_document.id, import_document.clone());
+    app_state.insert_doc(import_document.clone());
     commands.insert_resource(LoadDocRequest {
         doc_id: import_document.id,
     });
@@ -160,10 +295,14 @@ pub fn confirm_modal(
     mut ui_state: ResMut<UiState>,
     query_top: Query<(Entity, &ModalTop), With<ModalTop>>,
     mut tab_query_container: Query<(Entity, &TabContainer), With<TabContainer>>,
-    mut pkv: ResMut<PkvStore>,
+    mut storage: ResMut<Storage>,
     input: Res<Input<KeyCode>>,
     mut query_path: Query<(&CosmicEdit, &EditableText), With<EditableText>>,
     comm_channels: Res<CommChannels>,
+    theme: Res<Theme>,
+    toasts: Res<Toasts>,
+    mut history_browser: ResMut<HistoryBrowser>,
+    mut refresh_doc_list: EventWriter<RefreshDocList>,
 ) {
     for (interaction, path_modal_confirm) in interaction_query.iter_mut() {
         if let Interaction::Pressed = interaction {
@@ -181,34 +320,62 @@ pub fn confirm_modal(
                                     break;
                                 }
                                 ModalAction::LoadFromFile => {
-                                    if let Ok(path) = canonicalize(PathBuf::from(text.trim())) {
-                                        let json = std::fs::read_to_string(path)
-                                            .expect("Error reading document from file");
-                                        let cc = comm_channels.tx.clone();
-                                        cc.try_send(json).unwrap()
+                                    if let Err(e) = open_file(
+                                        text.trim(),
+                                        &mut app_state,
+                                        &mut commands,
+                                        &theme,
+                                        &comm_channels,
+                                    ) {
+                                        toasts.error("Could not open document", e);
                                     }
                                 }
                                 ModalAction::LoadFromUrl => {
-                                    let pool = IoTaskPool::get();
-                                    let url = text.trim();
-                                    let mut finder = LinkFinder::new();
-                                    finder.kinds(&[LinkKind::Url]);
-                                    let links: Vec<_> = finder.links(url).collect();
-                                    if links.len() == 1 {
-                                        let url = links.first().unwrap().as_str().to_owned();
-                                        let cc = comm_channels.tx.clone();
-                                        let task = pool.spawn(async move {
-                                            let request = ehttp::Request::get(url);
-                                            ehttp::fetch(request, move |result| {
-                                                let json_string = result.unwrap().text().unwrap();
-                                                cc.try_send(json_string).unwrap();
-                                            });
-                                        });
-                                        task.detach();
-                                    }
+                                    open_url(text.trim(), &comm_channels, &toasts);
                                 }
                                 ModalAction::DeleteDocument => {}
                                 ModalAction::DeleteTab => {}
+                                ModalAction::RestoreJournal => {}
+                                ModalAction::Trash => {}
+                                ModalAction::SaveSnapshot => {
+                                    request_snapshot(&app_state, &mut commands, &text);
+                                }
+                                ModalAction::TabHistory => {}
+                                ModalAction::LockDocument => {
+                                    lock_doc(&mut app_state, &mut commands, text.trim(), &toasts);
+                                }
+                                ModalAction::UnlockDocument => {
+                                    unlock_doc(
+                                        &mut app_state,
+                                        &mut commands,
+                                        path_modal_top.id,
+                                        text.trim(),
+                                        &toasts,
+                                    );
+                                }
+                                ModalAction::EditTags => {
+                                    set_doc_tags(&mut app_state, &mut commands, &text);
+                                }
+                                ModalAction::FilterTags => {
+                                    set_tag_filter(&mut app_state, &text);
+                                }
+                                ModalAction::MoveToFolder => {
+                                    move_to_folder(
+                                        &app_state,
+                                        &mut storage,
+                                        &mut commands,
+                                        &mut refresh_doc_list,
+                                        &text,
+                                        &toasts,
+                                    );
+                                }
+                                ModalAction::NewDocument => {}
+                                ModalAction::SaveDocTemplate => {
+                                    request_template(&app_state, &mut commands, &text, false);
+                                }
+                                ModalAction::SaveTabTemplate => {
+                                    request_template(&app_state, &mut commands, &text, true);
+                                }
                             }
                         }
                     }
@@ -217,11 +384,33 @@ pub fn confirm_modal(
                         ModalAction::LoadFromFile => {}
                         ModalAction::LoadFromUrl => {}
                         ModalAction::DeleteDocument => {
-                            delete_doc(&mut app_state, &mut commands, &mut pkv);
+                            delete_doc(&mut app_state, &mut commands, &mut storage, &toasts);
                         }
                         ModalAction::DeleteTab => {
-                            delete_tab(&mut app_state, &mut commands, &mut tab_query_container);
+                            delete_tab(
+                                &mut app_state,
+                                &mut commands,
+                                &mut tab_query_container,
+                                &mut storage,
+                                &toasts,
+                            );
+                        }
+                        ModalAction::RestoreJournal => {
+                            restore_journal(&mut app_state, &mut commands, &storage);
                         }
+                        ModalAction::Trash => {}
+                        ModalAction::SaveSnapshot => {}
+                        ModalAction::TabHistory => {
+                            end_preview(&mut history_browser, &app_state, &mut commands);
+                        }
+                        ModalAction::LockDocument => {}
+                        ModalAction::UnlockDocument => {}
+                        ModalAction::EditTags => {}
+                        ModalAction::FilterTags => {}
+                        ModalAction::MoveToFolder => {}
+                        ModalAction::NewDocument => {}
+                        ModalAction::SaveDocTemplate => {}
+                        ModalAction::SaveTabTemplate => {}
                     }
                 }
                 commands.entity(entity).despawn_recursive();
@@ -245,34 +434,62 @@ pub fn confirm_modal(
                                 break;
                             }
                             ModalAction::LoadFromFile => {
-                                if let Ok(path) = canonicalize(PathBuf::from(text.trim())) {
-                                    let json = std::fs::read_to_string(path)
-                                        .expect("Error reading document from file");
-                                    let cc = comm_channels.tx.clone();
-                                    cc.try_send(json).unwrap()
+                                if let Err(e) = open_file(
+                                    text.trim(),
+                                    &mut app_state,
+                                    &mut commands,
+                                    &theme,
+                                    &comm_channels,
+                                ) {
+                                    toasts.error("Could not open document", e);
                                 }
                             }
                             ModalAction::LoadFromUrl => {
-                                let pool = IoTaskPool::get();
-                                let url = text.trim();
-                                let mut finder = LinkFinder::new();
-                                finder.kinds(&[LinkKind::Url]);
-                                let links: Vec<_> = finder.links(url).collect();
-                                if links.len() == 1 {
-                                    let url = links.first().unwrap().as_str().to_owned();
-                                    let cc = comm_channels.tx.clone();
-                                    let task = pool.spawn(async move {
-                                        let request = ehttp::Request::get(url);
-                                        ehttp::fetch(request, move |result| {
-                                            let json_string = result.unwrap().text().unwrap();
-                                            cc.try_send(json_string).unwrap();
-                                        });
-                                    });
-                                    task.detach();
-                                }
+                                open_url(text.trim(), &comm_channels, &toasts);
                             }
                             ModalAction::DeleteDocument => {}
                             ModalAction::DeleteTab => {}
+                            ModalAction::RestoreJournal => {}
+                            ModalAction::Trash => {}
+                            ModalAction::SaveSnapshot => {
+                                request_snapshot(&app_state, &mut commands, &text);
+                            }
+                            ModalAction::TabHistory => {}
+                            ModalAction::LockDocument => {
+                                lock_doc(&mut app_state, &mut commands, text.trim(), &toasts);
+                            }
+                            ModalAction::UnlockDocument => {
+                                unlock_doc(
+                                    &mut app_state,
+                                    &mut commands,
+                                    path_modal_top.id,
+                                    text.trim(),
+                                    &toasts,
+                                );
+                            }
+                            ModalAction::EditTags => {
+                                set_doc_tags(&mut app_state, &mut commands, &text);
+                            }
+                            ModalAction::FilterTags => {
+                                set_tag_filter(&mut app_state, &text);
+                            }
+                            ModalAction::MoveToFolder => {
+                                move_to_folder(
+                                    &app_state,
+                                    &mut storage,
+                                    &mut commands,
+                                    &mut refresh_doc_list,
+                                    &text,
+                                    &toasts,
+                                );
+                            }
+                            ModalAction::NewDocument => {}
+                            ModalAction::SaveDocTemplate => {
+                                request_template(&app_state, &mut commands, &text, false);
+                            }
+                            ModalAction::SaveTabTemplate => {
+                                request_template(&app_state, &mut commands, &text, true);
+                            }
                         }
                     }
                 }
@@ -281,11 +498,31 @@ pub fn confirm_modal(
                     ModalAction::LoadFromFile => {}
                     ModalAction::LoadFromUrl => {}
                     ModalAction::DeleteDocument => {
-                        delete_doc(&mut app_state, &mut commands, &mut pkv);
+                        delete_doc(&mut app_state, &mut commands, &mut storage, &toasts);
+                    }
+                    ModalAction::DeleteTab => delete_tab(
+                        &mut app_state,
+                        &mut commands,
+                        &mut tab_query_container,
+                        &mut storage,
+                        &toasts,
+                    ),
+                    ModalAction::RestoreJournal => {
+                        restore_journal(&mut app_state, &mut commands, &storage)
                     }
-                    ModalAction::DeleteTab => {
-                        delete_tab(&mut app_state, &mut commands, &mut tab_query_container)
+                    ModalAction::Trash => {}
+                    ModalAction::SaveSnapshot => {}
+                    ModalAction::TabHistory => {
+                        end_preview(&mut history_browser, &app_state, &mut commands)
                     }
+                    ModalAction::LockDocument => {}
+                    ModalAction::UnlockDocument => {}
+                    ModalAction::EditTags => {}
+                    ModalAction::FilterTags => {}
+                    ModalAction::MoveToFolder => {}
+                    ModalAction::NewDocument => {}
+                    ModalAction::SaveDocTemplate => {}
+                    ModalAction::SaveTabTemplate => {}
                 }
             }
             commands.entity(entity).despawn_recursive();
@@ -295,29 +532,34 @@ pub fn confirm_modal(
     }
 }
 
+/// Opens the autosaved copy of the document in place of the stored one.
+fn restore_journal(app_state: &mut ResMut<AppState>, commands: &mut Commands, storage: &Storage) {
+    let Ok(doc) = storage.journal() else {
+        return;
+    };
+    app_state.current_document = Some(doc.id);
+    app_state.doc_list_ui.insert(doc.id);
+    commands.insert_resource(LoadDocRequest { doc_id: doc.id });
+    app_state.insert_doc(doc);
+}
+
+/// Moves the document to the trash, `new_id` takes over as the last saved document.
 fn remove_from_storage(
-    pkv: &mut ResMut<PkvStore>,
-    id_to_remove: ReflectableUuid,
+    storage: &mut Storage,
+    doc: Doc,
     new_id: ReflectableUuid,
-) {
-    if let Ok(mut docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-        if docs.remove(&id_to_remove).is_some() {
-            pkv.set("docs", &docs).unwrap();
-        }
-    }
-    if let Ok(mut tags) = pkv.get::<HashMap<ReflectableUuid, Vec<String>>>("tags") {
-        if tags.remove(&id_to_remove).is_some() {
-            pkv.set("tags", &tags).unwrap();
-        }
-    }
-    if let Ok(mut names) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
-        if names.remove(&id_to_remove).is_some() {
-            pkv.set("names", &names).unwrap();
+) -> Result<(), StoreError> {
+    let id_to_remove = doc.id;
+    move_to_trash(storage.0.as_mut(), TrashedItem::Doc(doc), get_timestamp())?;
+    if let Ok(last_saved) = storage.last_saved() {
+        if last_saved == id_to_remove {
+            storage.set_last_saved(&new_id)?;
         }
     }
-    if let Ok(last_saved) = pkv.get::<ReflectableUuid>("last_saved") {
-        if last_saved == id_to_remove {
-            pkv.set("last_saved", &new_id).unwrap();
+    if let Ok(journal) = storage.journal() {
+        if journal.id == id_to_remove {
+            storage.remove_journal()?;
         }
     }
+    Ok(())
 }
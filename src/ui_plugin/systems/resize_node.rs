@@ -1,6 +1,6 @@
 use super::{
     ui_helpers::{ResizeMarker, VeloShape},
-    NodeInteraction, NodeType, RawText, RedrawArrow, VeloNode,
+    FrameResize, NodeBounds, NodeInteraction, NodeType, RawText, RedrawArrow, VeloNode,
 };
 use crate::{
     canvas::{arrow::components::ArrowConnect, shadows::systems::Shadow},
@@ -82,14 +82,37 @@ pub fn resize_entity_run(
     >,
     mut border_query: Query<(&Parent, &VeloShape, &mut Path), With<VeloShape>>,
     mut velo_node_query: Query<
-        (&mut Transform, &Children),
+        (&mut Transform, &Children, &VeloNode),
         (With<VeloNode>, Without<ResizeMarker>, Without<ArrowConnect>),
     >,
     camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     mut shadows_q: Query<(&mut Sprite, &Shadow), (With<Shadow>, Without<RawText>)>,
+    mut frame_resize: Local<FrameResize>,
 ) {
     let (camera, camera_transform) = camera_q.single();
 
+    if frame_resize.id != ui_state.entity_to_resize {
+        *frame_resize = match ui_state.entity_to_resize {
+            Some(id) => {
+                let nodes: Vec<NodeBounds> = raw_text_query
+                    .iter()
+                    .filter_map(|(parent, raw_text, cosmic_edit, _)| {
+                        let (border_parent, velo_shape, _) = border_query.get(parent.get()).ok()?;
+                        let (transform, _, _) = velo_node_query.get(border_parent.get()).ok()?;
+                        Some(NodeBounds {
+                            id: raw_text.id,
+                            node_type: velo_shape.node_type.clone(),
+                            center: transform.translation,
+                            size: Vec2::new(cosmic_edit.width, cosmic_edit.height),
+                        })
+                    })
+                    .collect();
+                FrameResize::new(id, &nodes)
+            }
+            None => FrameResize::default(),
+        };
+    }
+
     if let Some(id) = ui_state.entity_to_resize {
         for (raw_text_parent, raw_text, mut cosmic_edit, mut sprite) in
             &mut raw_text_query.iter_mut()
@@ -103,7 +126,7 @@ pub fn resize_entity_run(
             {
                 let (border_parent, velo_border, mut path) =
                     border_query.get_mut(raw_text_parent.get()).unwrap();
-                let (velo_transform, children) =
+                let (velo_transform, children, _) =
                     velo_node_query.get_mut(border_parent.get()).unwrap();
                 let pos = velo_transform.translation.truncate();
                 let mut width = f32::max(((cursor_pos.x - pos.x).abs() * 2.).round(), 1.);
@@ -191,12 +214,14 @@ pub fn resize_entity_run(
                             radius: 10.,
                         },
                     ),
-                    NodeType::Paper => bevy_prototype_lyon::prelude::GeometryBuilder::build_as(
-                        &bevy_prototype_lyon::shapes::Polygon {
-                            points: points.into_iter().collect(),
-                            closed: true,
-                        },
-                    ),
+                    NodeType::Paper | NodeType::Frame => {
+                        bevy_prototype_lyon::prelude::GeometryBuilder::build_as(
+                            &bevy_prototype_lyon::shapes::Polygon {
+                                points: points.into_iter().collect(),
+                                closed: true,
+                            },
+                        )
+                    }
                     NodeType::Circle => bevy_prototype_lyon::prelude::GeometryBuilder::build_as(
                         &bevy_prototype_lyon::shapes::Circle {
                             radius: width / 2.,
@@ -206,6 +231,17 @@ pub fn resize_entity_run(
                 };
                 *path = new_path;
                 events.send(RedrawArrow { id: raw_text.id });
+
+                // nodes of a frame keep their relative place in it
+                for (framed_id, position) in frame_resize.positions(pos, Vec2::new(width, height)) {
+                    for (mut transform, _, velo_node) in velo_node_query.iter_mut() {
+                        if velo_node.id == framed_id {
+                            transform.translation.x = position.x;
+                            transform.translation.y = position.y;
+                            events.send(RedrawArrow { id: framed_id });
+                        }
+                    }
+                }
             }
         }
     }
@@ -25,6 +25,7 @@ pub fn resize_notificator(
                     doc_id: current_doc.id,
                     tab_id: active_tab.id,
                     drop_last_checkpoint: false,
+                    checkpoint: None,
                 });
             }
         }
@@ -1,22 +1,26 @@
-use base64::{engine::general_purpose, Engine};
+use std::collections::BTreeMap;
+
 use bevy::prelude::*;
 
 use bevy_cosmic_edit::CosmicEdit;
-use bevy_pkv::PkvStore;
 use bevy_prototype_lyon::prelude::Stroke;
-use image::*;
-
-use serde_json::json;
-use std::{collections::HashMap, io::Cursor};
 
 use super::ui_helpers::{Drawing, VeloNode, VeloShape};
-use super::{DrawingJsonNode, RawText, SaveStore};
+use super::{
+    push_checkpoint, DrawingJsonNode, HistoryBrowser, RawText, RefreshDocList, SaveStore, Toasts,
+};
 use crate::canvas::arrow::components::ArrowMeta;
 use crate::components::Doc;
+use crate::crypto::doc_at_rest;
+use crate::error::VeloError;
+use crate::format::{Checkpoint, DocFile};
 use crate::resources::SaveDocRequest;
-use crate::resources::{AppState, SaveTabRequest};
-use crate::utils::{load_doc_to_memory, ReflectableUuid};
-use crate::{JsonNode, JsonNodeText, MAX_CHECKPOINTS};
+use crate::resources::{AppState, ImageHashes, SaveTabRequest};
+use crate::store::{
+    doc_images, import_images, put_image, release_images, update_index_entry, Storage, StoreError,
+};
+use crate::utils::{get_timestamp, image_hash, image_to_base64, load_doc_to_memory};
+use crate::{JsonNode, JsonNodeText};
 
 pub fn should_save_doc(request: Option<Res<SaveDocRequest>>) -> bool {
     request.is_some()
@@ -37,15 +41,19 @@ pub fn remove_save_tab_request(world: &mut World) {
 pub fn save_doc(
     request: Res<SaveDocRequest>,
     mut app_state: ResMut<AppState>,
-    mut pkv: ResMut<PkvStore>,
+    storage: Res<Storage>,
     mut commands: Commands,
     mut events: EventWriter<SaveStore>,
+    toasts: Res<Toasts>,
 ) {
     let doc_id = request.doc_id;
 
-    load_doc_to_memory(doc_id, &mut app_state, &mut pkv);
+    if let Err(e) = load_doc_to_memory(doc_id, &mut app_state, &storage) {
+        toasts.error("Could not save document", e);
+        return;
+    }
 
-    for tab in app_state.docs.get_mut(&doc_id).unwrap().tabs.iter() {
+    for tab in app_state.docs[&doc_id].tabs.iter() {
         if tab.is_active {
             commands.insert_resource(SaveTabRequest {
                 doc_id,
@@ -61,50 +69,76 @@ pub fn save_doc(
 }
 
 pub fn save_to_store(
-    mut pkv: ResMut<PkvStore>,
+    mut storage: ResMut<Storage>,
     mut app_state: ResMut<AppState>,
     mut events: EventReader<SaveStore>,
+    mut refresh_doc_list: EventWriter<RefreshDocList>,
+    toasts: Res<Toasts>,
 ) {
+    let app_state = &mut *app_state;
     for event in events.iter() {
-        let doc_id = event.doc_id;
-        if let Ok(mut docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-            docs.insert(doc_id, app_state.docs.get(&doc_id).unwrap().clone());
-            pkv.set("docs", &docs).unwrap();
+        let Some(doc) = app_state.docs.get(&event.doc_id) else {
+            continue;
+        };
+        let locked = app_state.passphrases.contains_key(&doc.id);
+        let images = doc_images(doc);
+        if locked {
+            // a document locked just now still has its images in the store
+            for hash in images.iter() {
+                if !app_state.locked_images.contains_key(hash) {
+                    if let Ok(data) = storage.image(hash) {
+                        app_state.locked_images.insert(hash.clone(), data);
+                    }
+                }
+            }
         } else {
-            let mut docs = HashMap::new();
-            docs.insert(doc_id, app_state.docs.get(&doc_id).unwrap().clone());
-            pkv.set("docs", &docs).unwrap();
+            // images of a document that is no longer locked go back to the store
+            let unlocked: BTreeMap<String, String> = images
+                .iter()
+                .filter_map(|hash| {
+                    let data = app_state.locked_images.get(hash)?;
+                    Some((hash.clone(), data.clone()))
+                })
+                .collect();
+            if !unlocked.is_empty() {
+                if let Err(e) = import_images(storage.0.as_mut(), &unlocked) {
+                    toasts.error("Could not store images", e.into());
+                }
+            }
         }
-        if let Ok(mut tags) = pkv.get::<HashMap<ReflectableUuid, Vec<String>>>("tags") {
-            let doc = app_state.docs.get(&doc_id).unwrap();
-            if let Some(tags) = tags.get_mut(&doc_id) {
-                tags.append(&mut doc.tags.clone());
-            } else {
-                tags.insert(doc.id, doc.tags.clone());
+        let doc = match doc_at_rest(doc, &app_state.passphrases, |hash| {
+            app_state.image(storage.0.as_ref(), hash)
+        }) {
+            Ok(doc) => doc,
+            Err(e) => {
+                toasts.error("Could not save document", e);
+                continue;
             }
-            pkv.set("tags", &tags).unwrap();
-        } else {
-            let doc = app_state.docs.get(&doc_id).unwrap();
-            let mut tags = HashMap::new();
-            tags.insert(doc.id, doc.tags.clone());
-            pkv.set("tags", &tags).unwrap();
+        };
+        match store_doc(&mut storage, &doc) {
+            Ok(()) => {
+                app_state.dirty_docs.remove(&event.doc_id);
+                refresh_doc_list.send(RefreshDocList);
+            }
+            Err(e) => toasts.error("Could not save document", e.into()),
         }
-        if let Ok(mut names) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
-            let doc = app_state.docs.get(&doc_id).unwrap();
-            names.insert(doc.id, doc.name.clone());
-            pkv.set("names", &names).unwrap();
-        } else {
-            let doc = app_state.docs.get(&doc_id).unwrap();
-            let mut names = HashMap::new();
-            names.insert(doc.id, doc.name.clone());
-            pkv.set("names", &names).unwrap();
+        // sealed, the document carries its images and the store must not keep them
+        if locked {
+            if let Err(e) = release_images(storage.0.as_mut(), &images) {
+                toasts.error("Could not remove images of locked document", e.into());
+            }
         }
-        pkv.set("last_saved", &doc_id).unwrap();
-
-        if let Some(path) = event.path.clone() {
-            let current_doc = app_state.docs.get(&doc_id).unwrap().clone();
-            std::fs::write(path, serde_json::to_string_pretty(&current_doc).unwrap())
-                .expect("Error saving current document to file")
+        if let Some(path) = &event.path {
+            match std::fs::write(
+                path,
+                DocFile::new(doc.clone(), |hash| storage.image(hash).ok()).to_json_pretty(),
+            ) {
+                Ok(()) => toasts.info(format!("Saved to {}", path.display())),
+                Err(e) => toasts.error(
+                    "Could not save document to file",
+                    VeloError::Io(path.clone(), e),
+                ),
+            }
         }
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -125,6 +159,23 @@ pub fn save_to_store(
     }
 }
 
+/// Writes the document and its index entry, its journal is dropped once it is saved.
+fn store_doc(storage: &mut Storage, doc: &Doc) -> Result<(), StoreError> {
+    let changed = storage.doc(&doc.id).map_or(true, |stored| stored != *doc);
+    storage.set_doc(doc)?;
+    let mut index = storage.index().unwrap_or_default();
+    update_index_entry(&mut index, doc, changed.then(get_timestamp));
+    storage.set_index(&index)?;
+    storage.set_last_saved(&doc.id)?;
+    if storage
+        .journal()
+        .map_or(false, |journal| journal.id == doc.id)
+    {
+        storage.remove_journal()?;
+    }
+    Ok(())
+}
+
 pub fn save_tab(
     images: Res<Assets<Image>>,
     arrows: Query<&ArrowMeta, With<ArrowMeta>>,
@@ -137,32 +188,53 @@ pub fn save_tab(
         (&Transform, &Drawing<(String, Color)>, &Stroke),
         With<Drawing<(String, Color)>>,
     >,
+    history_browser: Option<Res<HistoryBrowser>>,
+    mut storage: ResMut<Storage>,
+    mut image_hashes: ResMut<ImageHashes>,
 ) {
+    // the canvas shows a past state, the tab keeps its latest one
+    if history_browser.map_or(false, |browser| browser.previewing) {
+        return;
+    }
+    // locked documents are kept out of the search index and the image store
+    let locked = app_state.passphrases.contains_key(&request.doc_id);
     #[cfg(not(target_arch = "wasm32"))]
     if let Some(index) = &mut app_state.search_index {
         index.tabs_to_delete.insert(request.tab_id.0);
     }
-    let mut json = json!({
-        "images": {},
-        "nodes": [],
-        "arrows": [],
-        "drawings": []
-    });
-    let json_images = json["images"].as_object_mut().unwrap();
+    let mut checkpoint = Checkpoint::default();
     for (raw_text, cosmic_edit, _) in raw_text_query.iter() {
-        if let Some(handle) = cosmic_edit.bg_image.clone() {
-            let image = images.get(&handle).unwrap();
-            if let Ok(img) = image.clone().try_into_dynamic() {
-                let mut image_data: Vec<u8> = Vec::new();
-                img.write_to(&mut Cursor::new(&mut image_data), ImageOutputFormat::Png)
-                    .unwrap();
-                let res_base64 = general_purpose::STANDARD.encode(image_data);
-                json_images.insert(raw_text.id.0.to_string(), json!(res_base64));
+        let Some(handle) = cosmic_edit.bg_image.clone() else {
+            continue;
+        };
+        let hash = match image_hashes.0.get(&handle.id()) {
+            Some(hash) => hash.clone(),
+            None => {
+                let Some(data) = images.get(&handle).and_then(image_to_base64) else {
+                    continue;
+                };
+                let stored = if locked {
+                    let hash = image_hash(&data);
+                    app_state.locked_images.insert(hash.clone(), data);
+                    Ok(hash)
+                } else {
+                    put_image(storage.0.as_mut(), &data)
+                };
+                match stored {
+                    Ok(hash) => {
+                        image_hashes.0.insert(handle.id(), hash.clone());
+                        hash
+                    }
+                    Err(e) => {
+                        error!("Error storing image: {}", e);
+                        continue;
+                    }
+                }
             }
-        }
+        };
+        checkpoint.images.insert(raw_text.id.0.to_string(), hash);
     }
 
-    let json_nodes = json["nodes"].as_array_mut().unwrap();
     for (raw_text, cosmic_edit, parent) in raw_text_query.iter() {
         let (border_parent, border) = border_query.get(parent.get()).unwrap();
         let top = velo_node_query.get(border_parent.get()).unwrap();
@@ -170,7 +242,7 @@ pub fn save_tab(
         let y = top.translation.y;
         let z = top.translation.z;
         let (width, height) = (cosmic_edit.width, cosmic_edit.height);
-        json_nodes.push(json!(JsonNode {
+        checkpoint.nodes.push(JsonNode {
             node_type: border.node_type.clone(),
             id: raw_text.id.0,
             x,
@@ -181,11 +253,11 @@ pub fn save_tab(
             bg_color: border.pair_color.0.clone(),
             text: JsonNodeText {
                 text: raw_text.last_text.clone(),
-                pos: cosmic_edit.text_pos.clone().into()
+                pos: cosmic_edit.text_pos.clone().into(),
             },
-        }));
+        });
         #[cfg(not(target_arch = "wasm32"))]
-        if let Some(index) = &mut app_state.search_index {
+        if let Some(index) = app_state.search_index.as_mut().filter(|_| !locked) {
             index.node_updates.insert(
                 super::NodeSearchLocation {
                     doc_id: request.doc_id.0,
@@ -197,49 +269,48 @@ pub fn save_tab(
         }
     }
 
-    let json_arrows = json["arrows"].as_array_mut().unwrap();
     for arrow_meta in arrows.iter() {
-        json_arrows.push(json!(arrow_meta));
+        checkpoint.arrows.push(*arrow_meta);
     }
 
-    let json_drawing = json["drawings"].as_array_mut().unwrap();
     for (transform, drawing, stroke) in drawing_query.iter() {
-        json_drawing.push(json!(DrawingJsonNode {
+        checkpoint.drawings.push(DrawingJsonNode {
             x: transform.translation.x,
             y: transform.translation.y,
             z: transform.translation.z,
             width: stroke.options.line_width,
             id: drawing.id,
             points: drawing.points.clone(),
-            drawing_color: drawing.drawing_color.0.clone()
-        }));
+            drawing_color: drawing.drawing_color.0.clone(),
+        });
     }
+    let json = checkpoint.to_json();
 
     let doc_id = request.doc_id;
 
-    for tab in &mut app_state.docs.get_mut(&doc_id).unwrap().tabs {
-        if request.tab_id == tab.id {
-            if (tab.checkpoints.len() as i32) > MAX_CHECKPOINTS {
-                tab.checkpoints.pop_front();
-            }
-            if let Some(last) = tab.checkpoints.back() {
-                if last == &json.to_string() {
-                    break;
-                }
-            }
-            tab.checkpoints.push_back(json.to_string());
-            break;
-        }
+    let Some(tab) = app_state
+        .docs
+        .get_mut(&doc_id)
+        .and_then(|doc| doc.tabs.iter_mut().find(|tab| tab.id == request.tab_id))
+    else {
+        return;
+    };
+    if tab.checkpoints.back() != Some(&json) {
+        push_checkpoint(tab, json, get_timestamp());
+        app_state.mark_dirty(doc_id);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::{DocEntry, DocumentStore, MemoryStore};
+    use crate::utils::ReflectableUuid;
+    use std::collections::HashMap;
     use tempfile::tempdir;
 
     #[test]
-    /// No PKV with tags
+    /// No stored tags
     fn test_save_doc1() {
         // Setup
         let mut app = App::new();
@@ -261,7 +332,9 @@ mod tests {
                     name: "Test tab".to_string(),
                     checkpoints: std::collections::VecDeque::new(),
                     z_index: 1.,
+                    history: vec![],
                 }],
+                sealed: None,
             },
         );
         let request = SaveDocRequest {
@@ -270,26 +343,23 @@ mod tests {
         };
         app.insert_resource(request);
         app.add_event::<SaveStore>();
-        PkvStore::new("test", "test").clear().unwrap();
-        app.insert_resource(PkvStore::new("test", "test"));
+        app.add_event::<RefreshDocList>();
+        app.init_resource::<Toasts>();
+        app.insert_resource(Storage(Box::new(MemoryStore::default())));
         app.insert_resource(app_state);
 
         // Run systems
         app.update();
 
         // Assertions
-        let pkv = app.world.resource::<PkvStore>();
-        let saved_docs: HashMap<ReflectableUuid, Doc> = pkv.get("docs").unwrap();
-        assert_eq!(saved_docs.get(&doc_id).unwrap().name, "test_doc");
-        assert!(saved_docs.get(&doc_id).unwrap().tabs[0].is_active);
-        let saved_tags: HashMap<ReflectableUuid, Vec<String>> = pkv.get("tags").unwrap();
-        assert_eq!(
-            saved_tags.get(&doc_id).unwrap(),
-            &vec!["test_tag".to_string()]
-        );
-        let saved_names: HashMap<ReflectableUuid, String> = pkv.get("names").unwrap();
-        assert_eq!(saved_names.get(&doc_id).unwrap(), "test_doc");
-        assert_eq!(pkv.get::<ReflectableUuid>("last_saved").unwrap(), doc_id);
+        let store = app.world.resource::<Storage>();
+        let saved_doc = store.doc(&doc_id).unwrap();
+        assert_eq!(saved_doc.name, "test_doc");
+        assert!(saved_doc.tabs[0].is_active);
+        let index = store.index().unwrap();
+        assert_eq!(&index[&doc_id].tags, &vec!["test_tag".to_string()]);
+        assert_eq!(index[&doc_id].name, "test_doc");
+        assert_eq!(store.last_saved().unwrap(), doc_id);
         let file_contents = std::fs::read_to_string(temp_file_path).unwrap();
         let saved_doc: Doc = serde_json::from_str(&file_contents).unwrap();
         assert_eq!(saved_doc.name, "test_doc");
@@ -297,7 +367,7 @@ mod tests {
     }
 
     #[test]
-    ///the PKV store has tags, but not for the document being saved:
+    ///the store has tags, but not for the document being saved:
     fn test_save_doc2() {
         // Setup
         let mut app = App::new();
@@ -317,9 +387,11 @@ mod tests {
                     id: tab_id,
                     is_active: true,
                     z_index: 1.,
+                    history: vec![],
                     name: "Test tab".to_string(),
                     checkpoints: std::collections::VecDeque::new(),
                 }],
+                sealed: None,
             },
         );
         let request = SaveDocRequest {
@@ -327,31 +399,35 @@ mod tests {
             path: Some(temp_file_path.clone()),
         };
         app.insert_resource(request);
-        PkvStore::new("test", "test1").clear().unwrap();
-        let mut pkv = PkvStore::new("test", "test1");
-        let mut tags = HashMap::new();
-        tags.insert(ReflectableUuid::generate(), vec!["test_tag_2".to_string()]);
-        pkv.set("tags", &tags).unwrap();
+        let mut store = MemoryStore::default();
+        let mut index = HashMap::new();
+        index.insert(
+            ReflectableUuid::generate(),
+            DocEntry {
+                name: "other_doc".to_string(),
+                tags: vec!["test_tag_2".to_string()],
+                ..Default::default()
+            },
+        );
+        store.set_index(&index).unwrap();
         app.add_event::<SaveStore>();
-        app.insert_resource(pkv);
+        app.add_event::<RefreshDocList>();
+        app.init_resource::<Toasts>();
+        app.insert_resource(Storage(Box::new(store)));
         app.insert_resource(app_state);
 
         // Run systems
         app.update();
 
         // Assertions
-        let pkv = app.world.resource::<PkvStore>();
-        let saved_docs: HashMap<ReflectableUuid, Doc> = pkv.get("docs").unwrap();
-        assert_eq!(saved_docs.get(&doc_id).unwrap().name, "test_doc");
-        assert!(saved_docs.get(&doc_id).unwrap().tabs[0].is_active);
-        let saved_tags: HashMap<ReflectableUuid, Vec<String>> = pkv.get("tags").unwrap();
-        assert_eq!(
-            saved_tags.get(&doc_id).unwrap(),
-            &vec!["test_tag_1".to_string()]
-        );
-        let saved_names: HashMap<ReflectableUuid, String> = pkv.get("names").unwrap();
-        assert_eq!(saved_names.get(&doc_id).unwrap(), "test_doc");
-        assert_eq!(pkv.get::<ReflectableUuid>("last_saved").unwrap(), doc_id);
+        let store = app.world.resource::<Storage>();
+        let saved_doc = store.doc(&doc_id).unwrap();
+        assert_eq!(saved_doc.name, "test_doc");
+        assert!(saved_doc.tabs[0].is_active);
+        let index = store.index().unwrap();
+        assert_eq!(&index[&doc_id].tags, &vec!["test_tag_1".to_string()]);
+        assert_eq!(index[&doc_id].name, "test_doc");
+        assert_eq!(store.last_saved().unwrap(), doc_id);
         let file_contents = std::fs::read_to_string(temp_file_path).unwrap();
         let saved_doc: Doc = serde_json::from_str(&file_contents).unwrap();
         assert_eq!(saved_doc.name, "test_doc");
@@ -359,7 +435,7 @@ mod tests {
     }
 
     #[test]
-    /// the PKV store already has tags for the document being saved.
+    /// the store already has tags for the document being saved, the document's replace them.
     fn test_save_doc3() {
         // Setup
         let mut app = App::new();
@@ -369,20 +445,21 @@ mod tests {
         let doc_id = ReflectableUuid::generate();
         let tab_id = ReflectableUuid::generate();
         let mut app_state = AppState::default();
-        let existing_tags = vec!["test_tag_2".to_string(), "test_tag_1".to_string()];
         app_state.docs.insert(
             doc_id,
             Doc {
                 id: doc_id,
                 name: "test_doc".to_string(),
-                tags: vec!["test_tag_1".to_string()],
+                tags: vec!["test_tag_1".to_string(), "test_tag_1".to_string()],
                 tabs: vec![crate::components::Tab {
                     id: tab_id,
                     is_active: true,
                     z_index: 1.,
+                    history: vec![],
                     name: "Test tab".to_string(),
                     checkpoints: std::collections::VecDeque::new(),
                 }],
+                sealed: None,
             },
         );
         let request = SaveDocRequest {
@@ -390,37 +467,147 @@ mod tests {
             path: Some(temp_file_path.clone()),
         };
         app.insert_resource(request);
-        PkvStore::new("test", "test3").clear().unwrap();
-        let mut pkv = PkvStore::new("test", "test3");
-        let mut tags = HashMap::new();
-        tags.insert(doc_id, vec!["test_tag_2".to_string()]);
-        pkv.set("tags", &tags).unwrap();
+        let mut store = MemoryStore::default();
+        let mut index = HashMap::new();
+        index.insert(
+            doc_id,
+            DocEntry {
+                name: "test_doc".to_string(),
+                tags: vec!["test_tag_2".to_string(), "test_tag_1".to_string()],
+                ..Default::default()
+            },
+        );
+        store.set_index(&index).unwrap();
         app.add_event::<SaveStore>();
-        app.insert_resource(pkv);
+        app.add_event::<RefreshDocList>();
+        app.init_resource::<Toasts>();
+        app.insert_resource(Storage(Box::new(store)));
         app.insert_resource(app_state);
 
         // Run systems
         app.update();
 
         // Assertions
-        // Check that the document was saved to the PKV store
-        let pkv = app.world.resource::<PkvStore>();
-        let saved_docs: HashMap<ReflectableUuid, Doc> = pkv.get("docs").unwrap();
-        assert_eq!(saved_docs.get(&doc_id).unwrap().name, "test_doc");
-        assert!(saved_docs.get(&doc_id).unwrap().tabs[0].is_active);
-        // Check that the tags were saved to the PKV store
-        let saved_tags: HashMap<ReflectableUuid, Vec<String>> = pkv.get("tags").unwrap();
-        let expected_tags = existing_tags;
-        assert_eq!(saved_tags.get(&doc_id).unwrap(), &expected_tags);
-        // Check that the name was saved to the PKV store
-        let saved_names: HashMap<ReflectableUuid, String> = pkv.get("names").unwrap();
-        assert_eq!(saved_names.get(&doc_id).unwrap(), "test_doc");
-        // Check that the last_saved field was updated in the PKV store
-        assert_eq!(pkv.get::<ReflectableUuid>("last_saved").unwrap(), doc_id);
+        // Check that the document was saved to the store
+        let store = app.world.resource::<Storage>();
+        let saved_doc = store.doc(&doc_id).unwrap();
+        assert_eq!(saved_doc.name, "test_doc");
+        assert!(saved_doc.tabs[0].is_active);
+        let index = store.index().unwrap();
+        // Check that the tags were saved to the index
+        assert_eq!(index[&doc_id].tags, vec!["test_tag_1".to_string()]);
+        // Check that the name was saved to the index
+        assert_eq!(index[&doc_id].name, "test_doc");
+        // Check that the last_saved field was updated in the store
+        assert_eq!(store.last_saved().unwrap(), doc_id);
         // Check that the file was saved to the correct path
         let file_contents = std::fs::read_to_string(temp_file_path).unwrap();
         let saved_doc: Doc = serde_json::from_str(&file_contents).unwrap();
         assert_eq!(saved_doc.name, "test_doc");
         assert!(saved_doc.tabs[0].is_active);
     }
+
+    #[test]
+    /// a file that cannot be written is reported, the store still gets the document.
+    fn test_save_doc_to_unwritable_file() {
+        let mut app = App::new();
+        app.add_systems(Update, (save_doc, save_to_store.after(save_doc)));
+        let temp_dir = tempdir().unwrap();
+        let temp_file_path = temp_dir.path().join("missing").join("test_doc.json");
+        let doc_id = ReflectableUuid::generate();
+        let mut app_state = AppState::default();
+        app_state.docs.insert(
+            doc_id,
+            Doc {
+                id: doc_id,
+                name: "test_doc".to_string(),
+                tags: vec![],
+                tabs: vec![],
+                sealed: None,
+            },
+        );
+        app.insert_resource(SaveDocRequest {
+            doc_id,
+            path: Some(temp_file_path),
+        });
+        app.add_event::<SaveStore>();
+        app.add_event::<RefreshDocList>();
+        app.init_resource::<Toasts>();
+        app.insert_resource(Storage(Box::new(MemoryStore::default())));
+        app.insert_resource(app_state);
+
+        app.update();
+
+        let store = app.world.resource::<Storage>();
+        assert_eq!(store.doc(&doc_id).unwrap().name, "test_doc");
+        let messages = app.world.resource::<Toasts>().drain();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0]
+            .text
+            .starts_with("Could not save document to file: "));
+    }
+
+    #[test]
+    /// a locked document takes its images out of the store, they are only kept sealed.
+    fn test_locked_doc_images_stay_out_of_store() {
+        let mut app = App::new();
+        app.add_systems(Update, (save_doc, save_to_store.after(save_doc)));
+        let mut store = MemoryStore::default();
+        let hash = put_image(&mut store, "aW1n").unwrap();
+        let mut checkpoint = Checkpoint::default();
+        checkpoint.images.insert("node".to_string(), hash.clone());
+        let doc = Doc::test("secret", &[checkpoint.to_json().as_str()]);
+        let doc_id = doc.id;
+        let mut app_state = AppState::default();
+        app_state
+            .passphrases
+            .insert(doc_id, "passphrase".to_string());
+        app_state.docs.insert(doc_id, doc);
+        app.insert_resource(SaveDocRequest { doc_id, path: None });
+        app.add_event::<SaveStore>();
+        app.add_event::<RefreshDocList>();
+        app.init_resource::<Toasts>();
+        app.insert_resource(Storage(Box::new(store)));
+        app.insert_resource(app_state);
+
+        app.update();
+
+        let store = app.world.resource::<Storage>();
+        assert!(store.image(&hash).is_err());
+        assert!(!store.image_hashes().unwrap().contains(&hash));
+        let sealed = store.doc(&doc_id).unwrap();
+        let (_, images) = crate::crypto::unseal_doc(&sealed, "passphrase").unwrap();
+        assert_eq!(images[&hash], "aW1n");
+        assert!(app.world.resource::<Toasts>().drain().is_empty());
+    }
+
+    #[test]
+    /// once saved, a locked document may leave memory although its stored copy is sealed.
+    fn test_saved_locked_doc_can_be_evicted() {
+        let mut app = App::new();
+        app.add_systems(Update, (save_doc, save_to_store.after(save_doc)));
+        let doc = Doc::test("secret", &["{}"]);
+        let doc_id = doc.id;
+        let mut app_state = AppState::default();
+        app_state
+            .passphrases
+            .insert(doc_id, "passphrase".to_string());
+        app_state.insert_doc(doc);
+        app.insert_resource(SaveDocRequest { doc_id, path: None });
+        app.add_event::<SaveStore>();
+        app.add_event::<RefreshDocList>();
+        app.init_resource::<Toasts>();
+        app.insert_resource(Storage(Box::new(MemoryStore::default())));
+        app.insert_resource(app_state);
+
+        app.update();
+
+        let mut app_state = app.world.resource_mut::<AppState>();
+        assert!(app_state.dirty_docs.is_empty());
+        let other = ReflectableUuid::generate();
+        assert_eq!(
+            crate::utils::evict_docs(&mut app_state, other, 0, usize::MAX),
+            vec![doc_id]
+        );
+    }
 }
@@ -4,7 +4,6 @@ use bevy::window::PrimaryWindow;
 use bevy_cosmic_edit::get_cosmic_text;
 use bevy_cosmic_edit::ActiveEditor;
 use bevy_cosmic_edit::CosmicEdit;
-use bevy_pkv::PkvStore;
 use bevy_prototype_lyon::prelude::Stroke;
 use cosmic_text::Edit;
 use std::collections::HashMap;
@@ -21,7 +20,10 @@ use tantivy::schema::*;
 use tantivy::Index;
 use uuid::Uuid;
 
+use crate::components::Tab;
+use crate::format::Checkpoint;
 use crate::resources::AppState;
+use crate::store::Storage;
 use crate::themes::Theme;
 use crate::utils::ReflectableUuid;
 use crate::APP_NAME;
@@ -87,7 +89,7 @@ pub fn search_box_text_changed(
     mut velo_border: Query<(&mut Stroke, &VeloShape), With<VeloShape>>,
     mut previous_search_text: Local<String>,
     mut app_state: ResMut<AppState>,
-    pkv: Res<PkvStore>,
+    storage: Res<Storage>,
     theme: Res<Theme>,
 ) {
     let str = get_cosmic_text(text_query.single().editor.buffer());
@@ -116,9 +118,9 @@ pub fn search_box_text_changed(
                     Err(e) => info!("Error searching index {:?}", e),
                 }
             }
-        } else if let Ok(names) = pkv.get::<HashMap<ReflectableUuid, String>>("names") {
+        } else if let Ok(index) = storage.index() {
             highlight_search_match_nodes(&HashSet::new(), &mut velo_border, &theme);
-            let keys_in_storage: Vec<_> = names.keys().collect();
+            let keys_in_storage: Vec<_> = index.keys().collect();
             let keys_in_memory: Vec<_> = app_state.docs.keys().cloned().collect();
             let mut combined_keys = keys_in_memory;
             combined_keys.extend(keys_in_storage);
@@ -129,19 +131,22 @@ pub fn search_box_text_changed(
 }
 
 pub fn init_search_index(mut app_state: ResMut<AppState>) {
-    let dirs = directories::ProjectDirs::from("", ORG_NAME, APP_NAME);
-    let path = match dirs.as_ref() {
-        Some(dirs) => dirs.data_dir(),
-        None => Path::new("."),
-    }
-    .to_path_buf();
     app_state.search_index = Some(SearchIndexState {
-        index: initialize_search_index(path),
+        index: initialize_search_index(search_index_dir()),
         node_updates: HashMap::new(),
         tabs_to_delete: HashSet::new(),
     });
 }
 
+pub fn search_index_dir() -> PathBuf {
+    let dirs = directories::ProjectDirs::from("", ORG_NAME, APP_NAME);
+    match dirs.as_ref() {
+        Some(dirs) => dirs.data_dir(),
+        None => Path::new("."),
+    }
+    .to_path_buf()
+}
+
 pub fn initialize_search_index(dir: PathBuf) -> tantivy::Index {
     Index::open_in_dir(dir.clone()).unwrap_or_else(|_| {
         let mut schema_builder = Schema::builder();
@@ -192,6 +197,37 @@ pub fn update_search_index(
     Ok(())
 }
 
+/// Puts the nodes of restored tabs back in the index, as of their latest checkpoint.
+pub fn reindex_tabs(search_index: &mut SearchIndexState, doc_id: &ReflectableUuid, tabs: &[Tab]) {
+    let mut node_updates = HashMap::new();
+    for tab in tabs {
+        search_index.tabs_to_delete.remove(&tab.id.0);
+        let Some(checkpoint) = tab
+            .checkpoints
+            .back()
+            .and_then(|json| Checkpoint::from_json(json).ok())
+        else {
+            continue;
+        };
+        for node in checkpoint.nodes {
+            node_updates.insert(
+                NodeSearchLocation {
+                    doc_id: doc_id.0,
+                    tab_id: tab.id.0,
+                    node_id: node.id,
+                },
+                node.text.text,
+            );
+        }
+    }
+    let index = std::sync::Arc::new(search_index.index.clone());
+    bevy::tasks::IoTaskPool::get()
+        .spawn(async move {
+            let _ = update_search_index(&index, &node_updates);
+        })
+        .detach();
+}
+
 const MAX_SEARCH_RESULTS: usize = 1000;
 
 pub fn clear_tabs_index(index: &Index, tab_ids: &HashSet<Uuid>) -> tantivy::Result<()> {
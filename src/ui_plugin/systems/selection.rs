@@ -0,0 +1,173 @@
+use std::collections::HashSet;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::CosmicEdit;
+use bevy_prototype_lyon::prelude::{Fill, GeometryBuilder, Path, ShapeBundle, Stroke};
+
+use super::{
+    ui_helpers::{Drawing, RawText, VeloShape},
+    NodeType, UiState,
+};
+use crate::{components::MainCamera, themes::Theme, utils::ReflectableUuid};
+
+/// Rubber band drawn while selecting nodes on the canvas.
+#[derive(Component)]
+pub struct Marquee;
+
+/// Drags shorter than this are clicks on the canvas, not a selection.
+const MIN_MARQUEE_SIZE: f32 = 2.;
+/// Above the nodes, which are pushed up by `ButtonTypes::Front` in small steps.
+const MARQUEE_Z: f32 = 900.;
+
+pub fn marquee_selection(
+    mut commands: Commands,
+    mut ui_state: ResMut<UiState>,
+    buttons: Res<Input<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut marquee_query: Query<(Entity, &mut Path), With<Marquee>>,
+    raw_text_query: Query<(&RawText, &CosmicEdit, &GlobalTransform), With<RawText>>,
+    drawings: Query<(&Drawing<(String, Color)>, &Transform), With<Drawing<(String, Color)>>>,
+    theme: Res<Theme>,
+) {
+    let Some(start) = ui_state.marquee_start else {
+        // the selection was interrupted, e.g. by loading another tab
+        for (entity, _) in marquee_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    };
+    let (camera, camera_transform) = camera_q.single();
+    let end = windows
+        .single()
+        .cursor_position()
+        .and_then(|pos| camera.viewport_to_world_2d(camera_transform, pos))
+        .unwrap_or(start);
+    let (min, max) = (start.min(end), start.max(end));
+
+    if buttons.pressed(MouseButton::Left) {
+        let points = [
+            Vec2::new(min.x, min.y),
+            Vec2::new(min.x, max.y),
+            Vec2::new(max.x, max.y),
+            Vec2::new(max.x, min.y),
+        ];
+        let path = GeometryBuilder::build_as(&bevy_prototype_lyon::shapes::Polygon {
+            points: points.into_iter().collect(),
+            closed: true,
+        });
+        if let Ok((_, mut marquee_path)) = marquee_query.get_single_mut() {
+            *marquee_path = path;
+        } else {
+            commands.spawn((
+                ShapeBundle {
+                    path,
+                    transform: Transform::from_xyz(0., 0., MARQUEE_Z),
+                    ..Default::default()
+                },
+                Fill::color(theme.selected_node_border.with_a(0.1)),
+                Stroke::new(theme.selected_node_border, 1.),
+                Marquee,
+            ));
+        }
+        return;
+    }
+
+    ui_state.marquee_start = None;
+    for (entity, _) in marquee_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    if (max - min).max_element() < MIN_MARQUEE_SIZE {
+        return;
+    }
+    for (raw_text, cosmic_edit, global_transform) in raw_text_query.iter() {
+        let center = global_transform.affine().translation.truncate();
+        let half = Vec2::new(cosmic_edit.width, cosmic_edit.height) / 2.;
+        if is_inside(center - half, center + half, min, max) {
+            ui_state.selected.insert(raw_text.id);
+        }
+    }
+    for (drawing, transform) in drawings.iter() {
+        let offset = transform.translation.truncate();
+        let points = drawing.points.iter().map(|point| *point + offset);
+        let (drawing_min, drawing_max) = points.fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), point| (min.min(point), max.max(point)),
+        );
+        if !drawing.points.is_empty() && is_inside(drawing_min, drawing_max, min, max) {
+            ui_state.selected.insert(drawing.id);
+        }
+    }
+}
+
+/// Whether the box from `node_min` to `node_max` lies within the marquee.
+fn is_inside(node_min: Vec2, node_max: Vec2, min: Vec2, max: Vec2) -> bool {
+    node_min.cmpge(min).all() && node_max.cmple(max).all()
+}
+
+/// Keeps the border of selected nodes highlighted like the node in editing mode,
+/// selected drawings are highlighted in the same color.
+pub fn highlight_selection(
+    ui_state: Res<UiState>,
+    theme: Res<Theme>,
+    mut last_selection: Local<HashSet<ReflectableUuid>>,
+    mut velo_border: Query<(&mut Stroke, &VeloShape), With<VeloShape>>,
+    mut drawings: Query<(&mut Stroke, &Drawing<(String, Color)>), Without<VeloShape>>,
+) {
+    if !ui_state.is_changed() {
+        return;
+    }
+    let selection = ui_state.selected_nodes();
+    for (mut stroke, velo_border) in velo_border.iter_mut() {
+        if selection.contains(&velo_border.id) {
+            if stroke.color != theme.selected_node_border || stroke.options.line_width != 2. {
+                stroke.color = theme.selected_node_border;
+                stroke.options.line_width = 2.;
+            }
+        } else if last_selection.contains(&velo_border.id) {
+            stroke.color = if velo_border.node_type != NodeType::Paper {
+                theme.node_border
+            } else {
+                Color::NONE
+            };
+            stroke.options.line_width = 1.;
+        }
+    }
+    for (mut stroke, drawing) in drawings.iter_mut() {
+        if selection.contains(&drawing.id) {
+            stroke.color = theme.selected_node_border;
+        } else if last_selection.contains(&drawing.id) {
+            stroke.color = drawing.drawing_color.1;
+        }
+    }
+    *last_selection = selection;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marquee_contains_whole_node() {
+        let (min, max) = (Vec2::new(-100., -100.), Vec2::new(100., 50.));
+
+        assert!(is_inside(
+            Vec2::new(-50., -50.),
+            Vec2::new(50., 50.),
+            min,
+            max
+        ));
+        assert!(!is_inside(
+            Vec2::new(-50., -50.),
+            Vec2::new(50., 51.),
+            min,
+            max
+        ));
+        assert!(!is_inside(
+            Vec2::new(-150., 0.),
+            Vec2::new(-120., 10.),
+            min,
+            max
+        ));
+    }
+}
@@ -7,6 +7,7 @@ pub fn set_focused_entity(
     mut node_interaction_events: EventReader<NodeInteraction>,
     mut ui_state: ResMut<UiState>,
     velo: Query<&RawText, With<RawText>>,
+    input: Res<Input<KeyCode>>,
 ) {
     let mut primary_window = windows.single_mut();
 
@@ -25,7 +26,19 @@ pub fn set_focused_entity(
                         primary_window.cursor.icon = CursorIcon::Text;
                     }
                 }
-                crate::ui_plugin::NodeInteractionType::LeftClick => {}
+                crate::ui_plugin::NodeInteractionType::LeftClick => {
+                    if input.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                        // the node in editing mode joins the selection
+                        if let Some(entity_to_edit) = ui_state.entity_to_edit.take() {
+                            ui_state.selected.insert(entity_to_edit);
+                        }
+                        if !ui_state.selected.remove(&velo_node.id) {
+                            ui_state.selected.insert(velo_node.id);
+                        }
+                    } else if !ui_state.selected.contains(&velo_node.id) {
+                        ui_state.selected.clear();
+                    }
+                }
                 crate::ui_plugin::NodeInteractionType::LeftDoubleClick => {
                     *ui_state = UiState::default();
                     ui_state.entity_to_edit = Some(velo_node.id);
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use async_channel::{Receiver, Sender};
+use bevy::prelude::*;
+
+use super::ui_helpers::{CopyShareLink, ShareDoc};
+use super::{response_text, CommChannels, Toasts};
+use crate::crypto::doc_at_rest;
+use crate::error::VeloError;
+use crate::format::{Checkpoint, DocFile};
+use crate::resources::{AppState, SaveTabRequest, ShareLinkRequest};
+use crate::share::{board_link, doc_from_board, encode_board, share_request, Share, ShareBackend};
+use crate::store::{or_default, Storage};
+use crate::utils::{load_doc_to_memory, ReflectableUuid};
+
+/// Where documents are shared, `share_backend` in `~/.velo.toml`. Without a backend the
+/// share button is not shown.
+#[derive(Resource)]
+pub struct Sharing {
+    pub backend: Option<Arc<dyn ShareBackend>>,
+    /// Shares reported by the backend, recorded by `record_shares`.
+    tx: Sender<(ReflectableUuid, Share)>,
+    rx: Receiver<(ReflectableUuid, Share)>,
+}
+
+impl Default for Sharing {
+    fn default() -> Self {
+        let (tx, rx) = async_channel::unbounded();
+        Self {
+            backend: None,
+            tx,
+            rx,
+        }
+    }
+}
+
+pub fn shared_doc_handler(
+    mut app_state: ResMut<AppState>,
+    mut query: Query<&Interaction, (Changed<Interaction>, With<ShareDoc>)>,
+    storage: Res<Storage>,
+    sharing: Res<Sharing>,
+    toasts: Res<Toasts>,
+) {
+    for interaction in &mut query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                if let Some(doc_id) = app_state.current_document {
+                    if let Err(e) = share_doc(doc_id, &mut app_state, &storage, &sharing, &toasts) {
+                        toasts.error("Could not share document", e);
+                    }
+                }
+            }
+            Interaction::Hovered => {}
+            Interaction::None => {}
+        }
+    }
+}
+
+/// Uploads the document, or updates it if it was shared before. The link opening it in
+/// velo is copied to the clipboard once the backend has it.
+fn share_doc(
+    doc_id: ReflectableUuid,
+    app_state: &mut ResMut<AppState>,
+    storage: &Storage,
+    sharing: &Sharing,
+    toasts: &Toasts,
+) -> Result<(), VeloError> {
+    load_doc_to_memory(doc_id, app_state, storage)?;
+    let Some(backend) = sharing.backend.clone() else {
+        return Err(VeloError::Share(
+            "share_backend is not set up in ~/.velo.toml".to_string(),
+        ));
+    };
+    let current_doc = doc_at_rest(&app_state.docs[&doc_id], &app_state.passphrases, |hash| {
+        app_state.image(storage.0.as_ref(), hash)
+    })?;
+    let contents = DocFile::new(current_doc, |hash| storage.image(hash).ok()).to_json_pretty();
+    let previous = or_default(storage.shares())?.remove(&doc_id);
+    let request = share_request(backend.as_ref(), &doc_id, previous.as_ref(), &contents);
+    let tx = sharing.tx.clone();
+    let toasts = toasts.clone();
+    ehttp::fetch(request, move |result| {
+        match response_text(result).and_then(|text| backend.share(&doc_id, &text)) {
+            Ok(share) => {
+                let _ = tx.try_send((doc_id, share));
+            }
+            Err(e) => toasts.error("Could not share document", e),
+        }
+    });
+    Ok(())
+}
+
+/// Keeps each share to update it next time and hands out its link.
+pub fn record_shares(sharing: Res<Sharing>, mut storage: ResMut<Storage>, toasts: Res<Toasts>) {
+    while let Ok((doc_id, share)) = sharing.rx.try_recv() {
+        copy_link(share.link(), &toasts);
+        let result = or_default(storage.shares()).and_then(|mut shares| {
+            shares.insert(doc_id, share);
+            storage.set_shares(&shares)
+        });
+        if let Err(e) = result {
+            toasts.error("Could not keep track of shared document", e.into());
+        }
+    }
+}
+
+pub fn copy_share_link_handler(
+    mut commands: Commands,
+    query: Query<&Interaction, (Changed<Interaction>, With<CopyShareLink>)>,
+    app_state: Res<AppState>,
+) {
+    for interaction in query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        let Some(doc_id) = app_state.current_document else {
+            continue;
+        };
+        let Some(tab) = app_state
+            .docs
+            .get(&doc_id)
+            .and_then(|doc| doc.tabs.iter().find(|tab| tab.is_active))
+        else {
+            continue;
+        };
+        commands.insert_resource(SaveTabRequest {
+            doc_id,
+            tab_id: tab.id,
+        });
+        commands.insert_resource(ShareLinkRequest {
+            doc_id,
+            tab_id: tab.id,
+        });
+    }
+}
+
+pub fn should_copy_share_link(request: Option<Res<ShareLinkRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_share_link_request(world: &mut World) {
+    world.remove_resource::<ShareLinkRequest>().unwrap();
+}
+
+/// Runs after `save_tab` so the link has the tab as it is on the canvas. A locked
+/// document stays out of links, they are not encrypted.
+pub fn copy_share_link(
+    request: Res<ShareLinkRequest>,
+    app_state: Res<AppState>,
+    toasts: Res<Toasts>,
+) {
+    if app_state.passphrases.contains_key(&request.doc_id) {
+        toasts.error(
+            "Could not create share link",
+            VeloError::Share("locked documents are not shared by link".to_string()),
+        );
+        return;
+    }
+    let Some(json) = app_state
+        .docs
+        .get(&request.doc_id)
+        .and_then(|doc| doc.tabs.iter().find(|tab| tab.id == request.tab_id))
+        .and_then(|tab| tab.checkpoints.back())
+    else {
+        return;
+    };
+    let result = Checkpoint::from_json(json)
+        .map_err(VeloError::from)
+        .and_then(|checkpoint| Ok((encode_board(&checkpoint)?, checkpoint.images.len())));
+    match result {
+        Ok((data, images)) => {
+            copy_link(board_link(&data), &toasts);
+            if images > 0 {
+                toasts.info("Images are left out of share links");
+            }
+        }
+        Err(e) => toasts.error("Could not create share link", e),
+    }
+}
+
+/// Loads the board carried by a share link like any opened document file.
+pub fn open_board(comm_channels: &CommChannels, data: &str) -> Result<(), VeloError> {
+    let doc = doc_from_board(data)?;
+    comm_channels.send(DocFile::new(doc, |_| None).to_json_pretty())
+}
+
+fn copy_link(link: String, toasts: &Toasts) {
+    #[cfg(not(target_arch = "wasm32"))]
+    let result = arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(link.clone()))
+        .map_err(|e| e.to_string());
+    #[cfg(target_arch = "wasm32")]
+    let result = write_clipboard(&link).map_err(|e| format!("{:?}", e));
+    match result {
+        Ok(()) => toasts.info("Link copied to clipboard"),
+        Err(e) => {
+            error!("Could not copy link: {}", e);
+            toasts.info(format!("Share link: {}", link));
+        }
+    }
+}
+
+/// `navigator.clipboard` is not in the stable `web_sys` api yet.
+#[cfg(target_arch = "wasm32")]
+fn write_clipboard(text: &str) -> Result<(), wasm_bindgen::JsValue> {
+    use wasm_bindgen::JsCast;
+
+    let window = wasm_bindgen::JsValue::from(web_sys::window().unwrap());
+    let navigator = js_sys::Reflect::get(&window, &"navigator".into())?;
+    let clipboard = js_sys::Reflect::get(&navigator, &"clipboard".into())?;
+    let write_text: js_sys::Function =
+        js_sys::Reflect::get(&clipboard, &"writeText".into())?.dyn_into()?;
+    write_text.call1(&clipboard, &text.into())?;
+    Ok(())
+}
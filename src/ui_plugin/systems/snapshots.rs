@@ -0,0 +1,555 @@
+use std::collections::HashSet;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::CosmicFont;
+use bevy_prototype_lyon::prelude::{GeometryBuilder, ShapeBundle, Stroke};
+
+use super::ui_helpers::{
+    add_rectangle_txt, spawn_modal, GenericButton, HistoryList, OpenTabHistory, SaveSnapshot,
+    SnapshotAction, SnapshotButton,
+};
+use super::{MainPanel, ModalAction, Toasts, UiState};
+use crate::components::{Snapshot, Tab};
+use crate::diff::{diff_checkpoints, Change, ChangeKind};
+use crate::format::Checkpoint;
+use crate::resources::{
+    AppState, FontSystemState, LoadTabRequest, SaveDocRequest, SaveTabRequest, SnapshotRequest,
+};
+use crate::themes::Theme;
+use crate::utils::{get_timestamp, ReflectableUuid};
+use crate::MAX_CHECKPOINTS;
+
+const MINUTE_MILLIS: f64 = 60. * 1000.;
+const HOUR_MILLIS: f64 = 60. * MINUTE_MILLIS;
+const DAY_MILLIS: f64 = 24. * HOUR_MILLIS;
+
+#[derive(Resource, Default)]
+pub struct HistoryBrowser {
+    /// Set while the canvas shows a past state of the tab instead of its latest checkpoint.
+    pub previewing: bool,
+    /// Changes from a past state to the latest one, highlighted on the canvas.
+    pub diff: Vec<Change>,
+}
+
+/// Outline around an element in the diff, above everything else on the canvas.
+#[derive(Component)]
+pub struct DiffOverlay;
+
+const DIFF_OVERLAY_Z: f32 = 950.;
+/// Space between an element and its outline, so neighbouring outlines stay apart.
+const DIFF_OVERLAY_PADDING: f32 = 6.;
+
+/// Makes `json` the latest state of the tab, the oldest checkpoint moves to the history.
+pub fn push_checkpoint(tab: &mut Tab, json: String, now: f64) {
+    if tab.checkpoints.back() == Some(&json) {
+        return;
+    }
+    if (tab.checkpoints.len() as i32) > MAX_CHECKPOINTS {
+        let checkpoint = tab.checkpoints.pop_front().unwrap();
+        tab.history.push(Snapshot {
+            name: None,
+            timestamp: now,
+            checkpoint,
+        });
+        compact_history(&mut tab.history, now);
+    }
+    tab.checkpoints.push_back(json);
+}
+
+/// Thins out unnamed states: the last hour is kept as is, then the newest state of each
+/// hour for the last day, then the newest state of each day. Named snapshots always stay.
+pub fn compact_history(history: &mut Vec<Snapshot>, now: f64) {
+    let mut buckets = HashSet::new();
+    let mut kept: Vec<Snapshot> = history
+        .drain(..)
+        .rev()
+        .filter(|snapshot| {
+            let age = now - snapshot.timestamp;
+            if snapshot.name.is_some() || age < HOUR_MILLIS {
+                return true;
+            }
+            let bucket = if age < DAY_MILLIS {
+                (false, (snapshot.timestamp / HOUR_MILLIS).floor() as i64)
+            } else {
+                (true, (snapshot.timestamp / DAY_MILLIS).floor() as i64)
+            };
+            buckets.insert(bucket)
+        })
+        .collect();
+    kept.reverse();
+    *history = kept;
+}
+
+pub fn should_save_snapshot(request: Option<Res<SnapshotRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_snapshot_request(world: &mut World) {
+    world.remove_resource::<SnapshotRequest>().unwrap();
+}
+
+/// Names the latest state of the tab, it runs after `save_tab` so unsaved edits are included.
+pub fn save_snapshot(
+    mut commands: Commands,
+    request: Res<SnapshotRequest>,
+    mut app_state: ResMut<AppState>,
+) {
+    let Some(tab) = app_state
+        .doc_mut(&request.doc_id)
+        .and_then(|doc| doc.tabs.iter_mut().find(|tab| tab.id == request.tab_id))
+    else {
+        return;
+    };
+    let Some(checkpoint) = tab.checkpoints.back().cloned() else {
+        return;
+    };
+    tab.history.push(Snapshot {
+        name: Some(request.name.clone()),
+        timestamp: get_timestamp(),
+        checkpoint,
+    });
+    commands.insert_resource(SaveDocRequest {
+        doc_id: request.doc_id,
+        path: None,
+    });
+}
+
+/// Asks to snapshot the active tab of the current document as `name`.
+pub fn request_snapshot(app_state: &AppState, commands: &mut Commands, name: &str) {
+    let Some((doc_id, tab_id)) = active_tab(app_state) else {
+        return;
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return;
+    }
+    commands.insert_resource(SaveTabRequest { doc_id, tab_id });
+    commands.insert_resource(SnapshotRequest {
+        doc_id,
+        tab_id,
+        name: name.to_string(),
+    });
+}
+
+/// Shows the latest state again once the history modal is closed.
+pub fn end_preview(
+    history_browser: &mut ResMut<HistoryBrowser>,
+    app_state: &AppState,
+    commands: &mut Commands,
+) {
+    if !history_browser.diff.is_empty() {
+        history_browser.diff.clear();
+    }
+    if !history_browser.previewing {
+        return;
+    }
+    history_browser.previewing = false;
+    if let Some((doc_id, tab_id)) = active_tab(app_state) {
+        commands.insert_resource(LoadTabRequest {
+            doc_id,
+            tab_id,
+            drop_last_checkpoint: false,
+            checkpoint: None,
+        });
+    }
+}
+
+fn active_tab(app_state: &AppState) -> Option<(ReflectableUuid, ReflectableUuid)> {
+    let doc_id = app_state.current_document?;
+    let tab = app_state
+        .docs
+        .get(&doc_id)?
+        .tabs
+        .iter()
+        .find(|tab| tab.is_active)?;
+    Some((doc_id, tab.id))
+}
+
+pub fn open_snapshot_modal(
+    mut commands: Commands,
+    query: Query<&Interaction, (Changed<Interaction>, With<SaveSnapshot>)>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let window = windows.single();
+    for interaction in query.iter() {
+        if *interaction == Interaction::Pressed {
+            let id = ReflectableUuid::generate();
+            *ui_state = UiState::default();
+            ui_state.modal_id = Some(id);
+            let entity = spawn_modal(
+                &mut commands,
+                &theme,
+                &mut cosmic_fonts,
+                font_system_state.0.clone().unwrap(),
+                window,
+                id,
+                ModalAction::SaveSnapshot,
+            );
+            commands.entity(main_panel_query.single()).add_child(entity);
+        }
+    }
+}
+
+pub fn open_tab_history(
+    mut commands: Commands,
+    query: Query<&Interaction, (Changed<Interaction>, With<OpenTabHistory>)>,
+    mut ui_state: ResMut<UiState>,
+    app_state: Res<AppState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let window = windows.single();
+    for interaction in query.iter() {
+        if *interaction == Interaction::Pressed {
+            // the history should start from what is on the canvas
+            if let Some((doc_id, tab_id)) = active_tab(&app_state) {
+                commands.insert_resource(SaveTabRequest { doc_id, tab_id });
+            }
+            let id = ReflectableUuid::generate();
+            *ui_state = UiState::default();
+            commands.insert_resource(bevy_cosmic_edit::ActiveEditor { entity: None });
+            ui_state.modal_id = Some(id);
+            let entity = spawn_modal(
+                &mut commands,
+                &theme,
+                &mut cosmic_fonts,
+                font_system_state.0.clone().unwrap(),
+                window,
+                id,
+                ModalAction::TabHistory,
+            );
+            commands.entity(main_panel_query.single()).add_child(entity);
+        }
+    }
+}
+
+/// Lists past states of the active tab, newest first, in a history modal that was just
+/// spawned. It runs after `save_tab` so the latest state is not offered as a past one.
+pub fn fill_history_list(
+    mut commands: Commands,
+    lists: Query<Entity, Added<HistoryList>>,
+    app_state: Res<AppState>,
+    theme: Res<Theme>,
+) {
+    for list in lists.iter() {
+        let Some((doc_id, tab_id)) = active_tab(&app_state) else {
+            continue;
+        };
+        let tab = app_state.docs[&doc_id]
+            .tabs
+            .iter()
+            .find(|tab| tab.id == tab_id)
+            .unwrap();
+        let now = get_timestamp();
+        let recent = tab
+            .checkpoints
+            .iter()
+            .rev()
+            .skip(1)
+            .map(|checkpoint| ("Recent change".to_string(), checkpoint));
+        let older = tab.history.iter().rev().map(|snapshot| {
+            let name = snapshot.name.as_deref().unwrap_or("Autosaved");
+            let label = format!("{}, {}", name, saved_ago(snapshot.timestamp, now));
+            (label, &snapshot.checkpoint)
+        });
+        let mut is_empty = true;
+        for (label, checkpoint) in recent.chain(older) {
+            is_empty = false;
+            let row = spawn_history_row(
+                &mut commands,
+                &theme,
+                label,
+                SnapshotButton {
+                    doc_id,
+                    tab_id,
+                    checkpoint: checkpoint.clone(),
+                    action: SnapshotAction::Preview,
+                },
+            );
+            commands.entity(list).add_child(row);
+        }
+        if is_empty {
+            let label = commands
+                .spawn(add_rectangle_txt(&theme, "No history yet".to_string()))
+                .id();
+            commands.entity(list).add_child(label);
+        }
+    }
+}
+
+fn spawn_history_row(
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    label: String,
+    preview: SnapshotButton,
+) -> Entity {
+    let row = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::SpaceBetween,
+                margin: UiRect::bottom(Val::Px(5.)),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let label = commands.spawn(add_rectangle_txt(theme, label)).id();
+    commands.entity(row).add_child(label);
+    let compare = SnapshotButton {
+        checkpoint: preview.checkpoint.clone(),
+        action: SnapshotAction::Compare,
+        ..preview
+    };
+    let restore = SnapshotButton {
+        checkpoint: preview.checkpoint.clone(),
+        action: SnapshotAction::Restore,
+        ..preview
+    };
+    for (button, text) in [
+        (preview, "Preview"),
+        (compare, "Diff"),
+        (restore, "Restore"),
+    ] {
+        let button = commands
+            .spawn((
+                ButtonBundle {
+                    border_color: theme.btn_border.into(),
+                    background_color: theme.ok_cancel_bg.into(),
+                    style: Style {
+                        border: UiRect::all(Val::Px(1.)),
+                        padding: UiRect::all(Val::Px(3.)),
+                        margin: UiRect::left(Val::Px(5.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                GenericButton,
+                button,
+            ))
+            .with_children(|builder| {
+                builder.spawn(TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size: 14.,
+                        color: theme.font,
+                        ..default()
+                    },
+                ));
+            })
+            .id();
+        commands.entity(row).add_child(button);
+    }
+    row
+}
+
+fn saved_ago(timestamp: f64, now: f64) -> String {
+    let age = now - timestamp;
+    if age < MINUTE_MILLIS {
+        "just now".to_string()
+    } else if age < HOUR_MILLIS {
+        format!("{} min ago", (age / MINUTE_MILLIS).floor())
+    } else if age < DAY_MILLIS {
+        format!("{} h ago", (age / HOUR_MILLIS).floor())
+    } else {
+        format!("{} days ago", (age / DAY_MILLIS).floor())
+    }
+}
+
+/// Preview only changes the canvas, diff shows the latest state with what changed since
+/// the past one highlighted, restore makes the past state the latest one of the tab.
+pub fn snapshot_button_handler(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &SnapshotButton), Changed<Interaction>>,
+    mut app_state: ResMut<AppState>,
+    mut history_browser: ResMut<HistoryBrowser>,
+    toasts: Res<Toasts>,
+) {
+    for (interaction, button) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        history_browser.diff.clear();
+        let checkpoint = match button.action {
+            SnapshotAction::Preview => {
+                history_browser.previewing = true;
+                Some(button.checkpoint.clone())
+            }
+            SnapshotAction::Compare => {
+                let Some(latest) = app_state
+                    .docs
+                    .get(&button.doc_id)
+                    .and_then(|doc| doc.tabs.iter().find(|tab| tab.id == button.tab_id))
+                    .and_then(|tab| tab.checkpoints.back())
+                else {
+                    continue;
+                };
+                let diff = Checkpoint::from_json(&button.checkpoint).and_then(|old| {
+                    Checkpoint::from_json(latest).map(|new| diff_checkpoints(&old, &new))
+                });
+                match diff {
+                    Ok(diff) if diff.is_empty() => toasts.info("Nothing changed since then"),
+                    Ok(diff) => history_browser.diff = diff,
+                    Err(e) => toasts.error("Could not compare states", e.into()),
+                }
+                if !history_browser.previewing {
+                    continue;
+                }
+                history_browser.previewing = false;
+                None
+            }
+            SnapshotAction::Restore => {
+                let Some(tab) = app_state
+                    .doc_mut(&button.doc_id)
+                    .and_then(|doc| doc.tabs.iter_mut().find(|tab| tab.id == button.tab_id))
+                else {
+                    continue;
+                };
+                push_checkpoint(tab, button.checkpoint.clone(), get_timestamp());
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    let tab = tab.clone();
+                    let locked = app_state.passphrases.contains_key(&button.doc_id);
+                    if let Some(search_index) = app_state.search_index.as_mut().filter(|_| !locked)
+                    {
+                        super::reindex_tabs(search_index, &button.doc_id, &[tab]);
+                    }
+                }
+                history_browser.previewing = false;
+                None
+            }
+        };
+        commands.insert_resource(LoadTabRequest {
+            doc_id: button.doc_id,
+            tab_id: button.tab_id,
+            drop_last_checkpoint: false,
+            checkpoint,
+        });
+    }
+}
+
+/// Outlines the elements in the diff, colored by what happened to them.
+pub fn draw_diff_overlay(
+    mut commands: Commands,
+    history_browser: Res<HistoryBrowser>,
+    overlays: Query<Entity, With<DiffOverlay>>,
+    theme: Res<Theme>,
+) {
+    if !history_browser.is_changed() {
+        return;
+    }
+    for entity in overlays.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for change in history_browser.diff.iter() {
+        let color = match change.kind {
+            ChangeKind::Added => theme.diff_added,
+            ChangeKind::Removed => theme.diff_removed,
+            _ => theme.diff_changed,
+        };
+        let (min, max) = (
+            change.min - DIFF_OVERLAY_PADDING,
+            change.max + DIFF_OVERLAY_PADDING,
+        );
+        let points = [
+            Vec2::new(min.x, min.y),
+            Vec2::new(min.x, max.y),
+            Vec2::new(max.x, max.y),
+            Vec2::new(max.x, min.y),
+        ];
+        let path = GeometryBuilder::build_as(&bevy_prototype_lyon::shapes::Polygon {
+            points: points.into_iter().collect(),
+            closed: true,
+        });
+        commands.spawn((
+            ShapeBundle {
+                path,
+                transform: Transform::from_xyz(0., 0., DIFF_OVERLAY_Z),
+                ..Default::default()
+            },
+            Stroke::new(color, 3.),
+            DiffOverlay,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(name: Option<&str>, timestamp: f64) -> Snapshot {
+        Snapshot {
+            name: name.map(|name| name.to_string()),
+            timestamp,
+            checkpoint: timestamp.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_compact_history() {
+        let now = 10. * DAY_MILLIS;
+        let mut history = vec![
+            snapshot(None, 2. * DAY_MILLIS),
+            snapshot(Some("before retro"), 2. * DAY_MILLIS + 1.),
+            snapshot(None, 2. * DAY_MILLIS + 2.),
+            snapshot(None, now - 2. * HOUR_MILLIS),
+            snapshot(None, now - 2. * HOUR_MILLIS + 1.),
+            snapshot(None, now - 1000.),
+            snapshot(None, now - 500.),
+        ];
+        compact_history(&mut history, now);
+        let timestamps: Vec<f64> = history.iter().map(|s| s.timestamp).collect();
+        assert_eq!(
+            timestamps,
+            vec![
+                2. * DAY_MILLIS + 1.,
+                2. * DAY_MILLIS + 2.,
+                now - 2. * HOUR_MILLIS + 1.,
+                now - 1000.,
+                now - 500.,
+            ]
+        );
+        assert_eq!(history[0].name.as_deref(), Some("before retro"));
+    }
+
+    #[test]
+    fn test_push_checkpoint_keeps_evicted_states() {
+        let mut tab = Tab {
+            id: ReflectableUuid::generate(),
+            name: "Tab 1".to_string(),
+            checkpoints: Default::default(),
+            is_active: true,
+            z_index: 1.,
+            history: vec![],
+        };
+        for i in 0..=MAX_CHECKPOINTS + 1 {
+            push_checkpoint(&mut tab, i.to_string(), 0.);
+            push_checkpoint(&mut tab, i.to_string(), 0.);
+        }
+        assert_eq!(tab.checkpoints.len() as i32, MAX_CHECKPOINTS + 1);
+        assert_eq!(tab.history.len(), 1);
+        assert_eq!(tab.history[0].checkpoint, "0");
+        assert_eq!(
+            tab.checkpoints.back().unwrap(),
+            &(MAX_CHECKPOINTS + 1).to_string()
+        );
+    }
+
+    #[test]
+    fn test_saved_ago() {
+        let now = 100. * DAY_MILLIS;
+        assert_eq!(saved_ago(now - 1000., now), "just now");
+        assert_eq!(saved_ago(now - 5. * MINUTE_MILLIS, now), "5 min ago");
+        assert_eq!(saved_ago(now - 3.5 * HOUR_MILLIS, now), "3 h ago");
+        assert_eq!(saved_ago(now - 2. * DAY_MILLIS, now), "2 days ago");
+    }
+}
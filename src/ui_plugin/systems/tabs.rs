@@ -26,13 +26,7 @@ pub fn select_tab_handler(
         match *interaction {
             Interaction::Pressed => {
                 let current_document = state.current_document.unwrap();
-                for tab in state
-                    .docs
-                    .get_mut(&current_document)
-                    .unwrap()
-                    .tabs
-                    .iter_mut()
-                {
+                for tab in state.docs[&current_document].tabs.iter() {
                     if tab.is_active && tab.id == selected_tab.id {
                         return;
                     }
@@ -43,13 +37,7 @@ pub fn select_tab_handler(
                         });
                     }
                 }
-                for tab in state
-                    .docs
-                    .get_mut(&current_document)
-                    .unwrap()
-                    .tabs
-                    .iter_mut()
-                {
+                for tab in state.doc_mut(&current_document).unwrap().tabs.iter_mut() {
                     tab.is_active = tab.id == selected_tab.id;
                 }
 
@@ -57,6 +45,7 @@ pub fn select_tab_handler(
                     doc_id: current_document,
                     tab_id: selected_tab.id,
                     drop_last_checkpoint: false,
+                    checkpoint: None,
                 });
             }
             Interaction::Hovered => {}
@@ -75,7 +64,7 @@ pub fn add_tab_handler(
             Interaction::Pressed => {
                 let tab_id = ReflectableUuid::generate();
                 let current_document = app_state.current_document.unwrap();
-                let tabs = &mut app_state.docs.get_mut(&current_document).unwrap().tabs;
+                let tabs = &mut app_state.doc_mut(&current_document).unwrap().tabs;
                 for tab in tabs.iter_mut() {
                     if tab.is_active {
                         commands.insert_resource(SaveTabRequest {
@@ -92,6 +81,7 @@ pub fn add_tab_handler(
                     checkpoints: VecDeque::new(),
                     is_active: true,
                     z_index: 1.,
+                    history: vec![],
                 });
                 commands.insert_resource(LoadDocRequest {
                     doc_id: app_state.current_document.unwrap(),
@@ -135,10 +125,7 @@ pub fn rename_tab_handler(
                     );
                     cosmic_edit.editor.set_cursor(new_cursor);
                     let current_document = app_state.current_document.unwrap();
-                    let tab = app_state
-                        .docs
-                        .get_mut(&current_document)
-                        .unwrap()
+                    let tab = app_state.docs[&current_document]
                         .tabs
                         .iter()
                         .find(|x| x.is_active)
@@ -174,12 +161,7 @@ pub fn delete_tab_handler(
                 *ui_state = UiState::default();
                 commands.insert_resource(bevy_cosmic_edit::ActiveEditor { entity: None });
                 let current_document = app_state.current_document.unwrap();
-                let tabs_len = app_state
-                    .docs
-                    .get_mut(&current_document)
-                    .unwrap()
-                    .tabs
-                    .len();
+                let tabs_len = app_state.docs[&current_document].tabs.len();
                 if tabs_len < 2 {
                     return;
                 }
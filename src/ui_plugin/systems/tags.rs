@@ -0,0 +1,90 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::CosmicFont;
+
+use super::ui_helpers::{spawn_modal_with_value, EditTags, FilterTags, TagChip};
+use super::{MainPanel, ModalAction, UiState};
+use crate::resources::{AppState, FontSystemState, SaveDocRequest};
+use crate::themes::Theme;
+use crate::utils::{parse_tags, ReflectableUuid};
+
+/// The tags modal starts with the tags of the current document, the filter modal with
+/// the tags the list is filtered by.
+pub fn open_tags_modal(
+    mut commands: Commands,
+    edit_query: Query<&Interaction, (Changed<Interaction>, With<EditTags>)>,
+    filter_query: Query<&Interaction, (Changed<Interaction>, With<FilterTags>)>,
+    app_state: Res<AppState>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let action = if edit_query.iter().any(|i| *i == Interaction::Pressed) {
+        ModalAction::EditTags
+    } else if filter_query.iter().any(|i| *i == Interaction::Pressed) {
+        ModalAction::FilterTags
+    } else {
+        return;
+    };
+    let value = match action {
+        ModalAction::EditTags => app_state
+            .current_document
+            .and_then(|id| app_state.docs.get(&id))
+            .map_or(String::new(), |doc| doc.tags.join(", ")),
+        _ => app_state
+            .tag_filter
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(", "),
+    };
+    let id = ReflectableUuid::generate();
+    *ui_state = UiState::default();
+    ui_state.modal_id = Some(id);
+    let entity = spawn_modal_with_value(
+        &mut commands,
+        &theme,
+        &mut cosmic_fonts,
+        font_system_state.0.clone().unwrap(),
+        windows.single(),
+        id,
+        action,
+        value,
+    );
+    commands.entity(main_panel_query.single()).add_child(entity);
+}
+
+/// Replaces the tags of the current document with the comma separated `text`.
+pub fn set_doc_tags(app_state: &mut AppState, commands: &mut Commands, text: &str) {
+    let Some(doc_id) = app_state.current_document else {
+        return;
+    };
+    let tags = parse_tags(text);
+    if app_state
+        .docs
+        .get(&doc_id)
+        .map_or(true, |doc| doc.tags == tags)
+    {
+        return;
+    }
+    app_state.doc_mut(&doc_id).unwrap().tags = tags;
+    commands.insert_resource(SaveDocRequest { doc_id, path: None });
+}
+
+/// Narrows the document list to the comma separated tags in `text`, none shows all.
+pub fn set_tag_filter(app_state: &mut AppState, text: &str) {
+    app_state.tag_filter = parse_tags(text).into_iter().collect();
+}
+
+pub fn tag_chip_click(
+    query: Query<(&Interaction, &TagChip), Changed<Interaction>>,
+    mut app_state: ResMut<AppState>,
+) {
+    for (interaction, chip) in query.iter() {
+        if *interaction == Interaction::Pressed && !app_state.tag_filter.remove(&chip.tag) {
+            app_state.tag_filter.insert(chip.tag.clone());
+        }
+    }
+}
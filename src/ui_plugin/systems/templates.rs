@@ -0,0 +1,579 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::CosmicFont;
+use uuid::Uuid;
+
+use super::ui_helpers::{
+    add_rectangle_txt, spawn_modal, spawn_modal_with_value, GenericButton, ModalTop, NewDoc,
+    SaveDocTemplate, SaveTabTemplate, TemplateAction, TemplateButton, TemplateList,
+};
+use super::{with_fresh_ids, MainPanel, ModalAction, Toasts, UiState};
+use crate::canvas::arrow::components::{ArrowConnect, ArrowConnectPos, ArrowMeta, ArrowType};
+use crate::components::{Doc, Tab};
+use crate::format::Checkpoint;
+use crate::resources::{
+    AppState, FontSystemState, LoadDocRequest, SaveDocRequest, SaveTabRequest, TemplateRequest,
+};
+use crate::store::{
+    add_template, or_default, remove_template, Storage, StoreError, Template, TemplateTab,
+};
+use crate::themes::Theme;
+use crate::utils::ReflectableUuid;
+use crate::{JsonNode, JsonNodeText, NodeType, TextPos};
+
+/// Space between frames of a built-in template.
+const GAP: f32 = 40.;
+
+/// Ready made layouts for retros, planning and brainstorming. Their ids never change so
+/// the picker can refer to them.
+pub fn builtin_templates(node_size: Vec2) -> Vec<Template> {
+    let builtins = [
+        (
+            "Kanban",
+            columns(
+                &[
+                    ("To do", "First task"),
+                    ("Doing", "Task in progress"),
+                    ("Done", "Finished task"),
+                ],
+                node_size,
+            ),
+        ),
+        (
+            "Retrospective",
+            columns(
+                &[
+                    ("Went well", "What helped us?"),
+                    ("To improve", "What slowed us down?"),
+                    ("Action items", "What will we change?"),
+                ],
+                node_size,
+            ),
+        ),
+        ("Mind map", mind_map(node_size)),
+        ("2x2 matrix", matrix(node_size)),
+    ];
+    builtins
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, checkpoint))| Template {
+            id: ReflectableUuid(Uuid::from_u128(i as u128 + 1)),
+            name: name.to_string(),
+            tabs: vec![TemplateTab {
+                name: name.to_string(),
+                checkpoint: checkpoint.to_json(),
+            }],
+        })
+        .collect()
+}
+
+fn add_node(
+    checkpoint: &mut Checkpoint,
+    node_type: NodeType,
+    text: &str,
+    position: Vec2,
+    size: Vec2,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    let (z, pos, bg_color) = match node_type {
+        NodeType::Frame => (0.5, TextPos::TopLeft, "frame_bg"),
+        NodeType::Paper => (1., TextPos::Center, "paper_node_bg"),
+        _ => (1., TextPos::Center, "node_bg"),
+    };
+    checkpoint.nodes.push(JsonNode {
+        id,
+        node_type,
+        x: position.x,
+        y: position.y,
+        z: z + checkpoint.nodes.len() as f32 * 0.01,
+        width: size.x,
+        height: size.y,
+        text: JsonNodeText {
+            text: text.to_string(),
+            pos,
+        },
+        bg_color: bg_color.to_string(),
+    });
+    id
+}
+
+/// A frame per column with a paper note at its top.
+fn columns(titles: &[(&str, &str)], node_size: Vec2) -> Checkpoint {
+    let mut checkpoint = Checkpoint::default();
+    let size = Vec2::new(1.5 * node_size.x, 5. * node_size.y);
+    for (i, (title, note)) in titles.iter().enumerate() {
+        let x = i as f32 * (size.x + GAP);
+        add_node(
+            &mut checkpoint,
+            NodeType::Frame,
+            title,
+            Vec2::new(x, 0.),
+            size,
+        );
+        let y = size.y / 2. - 1.5 * node_size.y;
+        add_node(
+            &mut checkpoint,
+            NodeType::Paper,
+            note,
+            Vec2::new(x, y),
+            node_size,
+        );
+    }
+    checkpoint
+}
+
+/// A topic in the middle with an arrow to an idea on each side.
+fn mind_map(node_size: Vec2) -> Checkpoint {
+    let mut checkpoint = Checkpoint::default();
+    let center = add_node(
+        &mut checkpoint,
+        NodeType::Circle,
+        "Topic",
+        Vec2::ZERO,
+        node_size,
+    );
+    let offset = node_size + Vec2::splat(2. * GAP);
+    for (i, (direction, start, end)) in [
+        (Vec2::X, ArrowConnectPos::Right, ArrowConnectPos::Left),
+        (Vec2::NEG_X, ArrowConnectPos::Left, ArrowConnectPos::Right),
+        (Vec2::Y, ArrowConnectPos::Top, ArrowConnectPos::Bottom),
+        (Vec2::NEG_Y, ArrowConnectPos::Bottom, ArrowConnectPos::Top),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let idea = add_node(
+            &mut checkpoint,
+            NodeType::Rect,
+            &format!("Idea {}", i + 1),
+            direction * offset,
+            node_size,
+        );
+        checkpoint.arrows.push(ArrowMeta {
+            arrow_type: ArrowType::ParallelArrow,
+            start: ArrowConnect {
+                id: ReflectableUuid(center),
+                pos: start,
+            },
+            end: ArrowConnect {
+                id: ReflectableUuid(idea),
+                pos: end,
+            },
+        });
+    }
+    checkpoint
+}
+
+/// Four frames in a grid, urgency across and importance down.
+fn matrix(node_size: Vec2) -> Checkpoint {
+    let mut checkpoint = Checkpoint::default();
+    let size = 3. * node_size;
+    let offset = (size + Vec2::splat(GAP)) / 2.;
+    for (title, sign) in [
+        ("Urgent and important", Vec2::new(-1., 1.)),
+        ("Important, not urgent", Vec2::new(1., 1.)),
+        ("Urgent, not important", Vec2::new(-1., -1.)),
+        ("Neither urgent nor important", Vec2::new(1., -1.)),
+    ] {
+        add_node(&mut checkpoint, NodeType::Frame, title, sign * offset, size);
+    }
+    checkpoint
+}
+
+/// A new document laid out like the template, with ids of its own so that several
+/// documents can start from the same template.
+pub fn doc_from_template(template: &Template) -> Result<Doc, serde_json::Error> {
+    let mut tabs = vec![];
+    for (i, template_tab) in template.tabs.iter().enumerate() {
+        let checkpoint = with_fresh_ids(&Checkpoint::from_json(&template_tab.checkpoint)?);
+        let z_index = checkpoint
+            .nodes
+            .iter()
+            .map(|node| node.z)
+            .fold(1., f32::max);
+        tabs.push(Tab {
+            id: ReflectableUuid::generate(),
+            name: template_tab.name.clone(),
+            checkpoints: VecDeque::from([checkpoint.to_json()]),
+            is_active: i == 0,
+            z_index,
+            history: vec![],
+        });
+    }
+    Ok(Doc {
+        id: ReflectableUuid::generate(),
+        name: template.name.clone(),
+        tags: vec![],
+        tabs,
+        sealed: None,
+    })
+}
+
+fn empty_doc() -> Doc {
+    let mut checkpoints = VecDeque::new();
+    checkpoints.push_back(Checkpoint::default().to_json());
+    Doc {
+        id: ReflectableUuid::generate(),
+        name: "Untitled".to_string(),
+        tags: vec![],
+        tabs: vec![Tab {
+            id: ReflectableUuid::generate(),
+            name: "Tab 1".to_string(),
+            checkpoints,
+            is_active: true,
+            z_index: 1.,
+            history: vec![],
+        }],
+        sealed: None,
+    }
+}
+
+pub fn open_new_doc_modal(
+    mut commands: Commands,
+    query: Query<&Interaction, (Changed<Interaction>, With<NewDoc>)>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let window = windows.single();
+    for interaction in query.iter() {
+        if *interaction == Interaction::Pressed {
+            let id = ReflectableUuid::generate();
+            *ui_state = UiState::default();
+            commands.insert_resource(bevy_cosmic_edit::ActiveEditor { entity: None });
+            ui_state.modal_id = Some(id);
+            let entity = spawn_modal(
+                &mut commands,
+                &theme,
+                &mut cosmic_fonts,
+                font_system_state.0.clone().unwrap(),
+                window,
+                id,
+                ModalAction::NewDocument,
+            );
+            commands.entity(main_panel_query.single()).add_child(entity);
+        }
+    }
+}
+
+/// Offers the empty document, the built-in templates and the saved ones, in a modal
+/// that was just spawned.
+pub fn fill_template_list(
+    mut commands: Commands,
+    lists: Query<Entity, Added<TemplateList>>,
+    storage: Res<Storage>,
+    theme: Res<Theme>,
+) {
+    for list in lists.iter() {
+        let row = spawn_template_row(&mut commands, &theme, None, "Empty document", false);
+        commands.entity(list).add_child(row);
+        let node_size = Vec2::new(theme.node_width, theme.node_height);
+        for template in builtin_templates(node_size) {
+            let row = spawn_template_row(
+                &mut commands,
+                &theme,
+                Some(template.id),
+                &template.name,
+                false,
+            );
+            commands.entity(list).add_child(row);
+        }
+        for template in or_default(storage.templates()).unwrap_or_default() {
+            let row = spawn_template_row(
+                &mut commands,
+                &theme,
+                Some(template.id),
+                &template.name,
+                true,
+            );
+            commands.entity(list).add_child(row);
+        }
+    }
+}
+
+fn spawn_template_row(
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    id: Option<ReflectableUuid>,
+    name: &str,
+    deletable: bool,
+) -> Entity {
+    let row = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::SpaceBetween,
+                margin: UiRect::bottom(Val::Px(5.)),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let label = commands
+        .spawn(add_rectangle_txt(theme, name.to_string()))
+        .id();
+    commands.entity(row).add_child(label);
+    let mut actions = vec![(TemplateAction::Use, "Use")];
+    if deletable {
+        actions.push((TemplateAction::Delete, "Delete"));
+    }
+    for (action, text) in actions {
+        let button = commands
+            .spawn((
+                ButtonBundle {
+                    border_color: theme.btn_border.into(),
+                    background_color: theme.ok_cancel_bg.into(),
+                    style: Style {
+                        border: UiRect::all(Val::Px(1.)),
+                        padding: UiRect::all(Val::Px(3.)),
+                        margin: UiRect::left(Val::Px(5.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                GenericButton,
+                TemplateButton { id, action },
+            ))
+            .with_children(|builder| {
+                builder.spawn(TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size: 14.,
+                        color: theme.font,
+                        ..default()
+                    },
+                ));
+            })
+            .id();
+        commands.entity(row).add_child(button);
+    }
+    row
+}
+
+pub fn template_button_handler(
+    mut commands: Commands,
+    interaction_query: Query<
+        (&Interaction, &TemplateButton, &Parent),
+        (Changed<Interaction>, With<TemplateButton>),
+    >,
+    modal_query: Query<(Entity, &ModalTop)>,
+    mut app_state: ResMut<AppState>,
+    mut ui_state: ResMut<UiState>,
+    mut storage: ResMut<Storage>,
+    theme: Res<Theme>,
+    toasts: Res<Toasts>,
+) {
+    for (interaction, button, row) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if button.action == TemplateAction::Delete {
+            let Some(id) = button.id else {
+                continue;
+            };
+            match remove_template(storage.0.as_mut(), &id) {
+                Ok(()) => commands.entity(row.get()).despawn_recursive(),
+                Err(e) => toasts.error("Could not delete template", e.into()),
+            }
+            continue;
+        }
+        let doc = match button.id {
+            None => empty_doc(),
+            Some(id) => {
+                let node_size = Vec2::new(theme.node_width, theme.node_height);
+                let saved = or_default(storage.templates()).unwrap_or_default();
+                let Some(template) = builtin_templates(node_size)
+                    .into_iter()
+                    .chain(saved)
+                    .find(|template| template.id == id)
+                else {
+                    continue;
+                };
+                match doc_from_template(&template) {
+                    Ok(doc) => doc,
+                    Err(e) => {
+                        toasts.error("Could not use template", e.into());
+                        continue;
+                    }
+                }
+            }
+        };
+        open_new_doc(&mut app_state, &mut commands, doc);
+        for (entity, modal) in modal_query.iter() {
+            if modal.action == ModalAction::NewDocument {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        ui_state.modal_id = None;
+    }
+}
+
+/// Saves the current document and switches to `doc`.
+fn open_new_doc(app_state: &mut AppState, commands: &mut Commands, doc: Doc) {
+    let doc_id = doc.id;
+    app_state.insert_doc(doc);
+    if let Some(current_document) = app_state.current_document {
+        commands.insert_resource(SaveDocRequest {
+            doc_id: current_document,
+            path: None,
+        });
+    }
+    app_state.current_document = Some(doc_id);
+    commands.insert_resource(LoadDocRequest { doc_id });
+    app_state.doc_list_ui.insert(doc_id);
+}
+
+/// The template name starts as the name of the document or of the active tab.
+pub fn open_template_modal(
+    mut commands: Commands,
+    doc_query: Query<&Interaction, (Changed<Interaction>, With<SaveDocTemplate>)>,
+    tab_query: Query<&Interaction, (Changed<Interaction>, With<SaveTabTemplate>)>,
+    app_state: Res<AppState>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let action = if doc_query.iter().any(|i| *i == Interaction::Pressed) {
+        ModalAction::SaveDocTemplate
+    } else if tab_query.iter().any(|i| *i == Interaction::Pressed) {
+        ModalAction::SaveTabTemplate
+    } else {
+        return;
+    };
+    let doc = app_state
+        .current_document
+        .and_then(|id| app_state.docs.get(&id));
+    let value = match action {
+        ModalAction::SaveDocTemplate => doc.map(|doc| doc.name.clone()),
+        _ => doc
+            .and_then(|doc| doc.tabs.iter().find(|tab| tab.is_active))
+            .map(|tab| tab.name.clone()),
+    };
+    let id = ReflectableUuid::generate();
+    *ui_state = UiState::default();
+    ui_state.modal_id = Some(id);
+    let entity = spawn_modal_with_value(
+        &mut commands,
+        &theme,
+        &mut cosmic_fonts,
+        font_system_state.0.clone().unwrap(),
+        windows.single(),
+        id,
+        action,
+        value.unwrap_or_default(),
+    );
+    commands.entity(main_panel_query.single()).add_child(entity);
+}
+
+/// Asks to save the current document, or only its active tab, as template `name`.
+pub fn request_template(app_state: &AppState, commands: &mut Commands, name: &str, tab: bool) {
+    let Some(doc_id) = app_state.current_document else {
+        return;
+    };
+    let Some(active_tab) = app_state
+        .docs
+        .get(&doc_id)
+        .and_then(|doc| doc.tabs.iter().find(|tab| tab.is_active))
+    else {
+        return;
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        return;
+    }
+    commands.insert_resource(SaveTabRequest {
+        doc_id,
+        tab_id: active_tab.id,
+    });
+    commands.insert_resource(TemplateRequest {
+        doc_id,
+        tab_id: tab.then_some(active_tab.id),
+        name: name.to_string(),
+    });
+}
+
+pub fn should_save_template(request: Option<Res<TemplateRequest>>) -> bool {
+    request.is_some()
+}
+
+pub fn remove_template_request(world: &mut World) {
+    world.remove_resource::<TemplateRequest>().unwrap();
+}
+
+/// Runs after `save_tab` so unsaved edits go into the template. Templates are kept in
+/// the clear, so a locked document is not saved as one.
+pub fn save_template(
+    request: Res<TemplateRequest>,
+    app_state: Res<AppState>,
+    mut storage: ResMut<Storage>,
+    toasts: Res<Toasts>,
+) {
+    let Some(doc) = app_state.docs.get(&request.doc_id) else {
+        return;
+    };
+    if app_state.passphrases.contains_key(&doc.id) {
+        toasts.error(
+            "Could not save template",
+            StoreError::Locked(doc.name.clone()).into(),
+        );
+        return;
+    }
+    let lookup = |hash: &str| storage.image(hash).ok();
+    let template = match request.tab_id {
+        Some(tab_id) => match doc.tabs.iter().find(|tab| tab.id == tab_id) {
+            Some(tab) => Template::from_tab(tab, request.name.clone(), lookup),
+            None => return,
+        },
+        None => Template::from_doc(doc, request.name.clone(), lookup),
+    };
+    match template.and_then(|template| add_template(storage.0.as_mut(), template)) {
+        Ok(()) => toasts.info(format!("Saved template {}", request.name)),
+        Err(e) => toasts.error("Could not save template", e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_templates() {
+        let templates = builtin_templates(Vec2::new(100., 50.));
+        let names: Vec<_> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["Kanban", "Retrospective", "Mind map", "2x2 matrix"]);
+        // ids stay the same between calls
+        let ids: Vec<_> = templates.iter().map(|t| t.id).collect();
+        let again: Vec<_> = builtin_templates(Vec2::ONE).iter().map(|t| t.id).collect();
+        assert_eq!(ids, again);
+        let mind_map = Checkpoint::from_json(&templates[2].tabs[0].checkpoint).unwrap();
+        assert_eq!(mind_map.nodes.len(), 5);
+        assert_eq!(mind_map.arrows.len(), 4);
+    }
+
+    #[test]
+    fn test_doc_from_template() {
+        let template = &builtin_templates(Vec2::new(100., 50.))[2];
+        let first = doc_from_template(template).unwrap();
+        let second = doc_from_template(template).unwrap();
+        assert_eq!(first.name, "Mind map");
+        assert_ne!(first.id, second.id);
+        assert!(first.tabs[0].is_active);
+        let first = Checkpoint::from_json(first.tabs[0].checkpoints.back().unwrap()).unwrap();
+        let second = Checkpoint::from_json(second.tabs[0].checkpoints.back().unwrap()).unwrap();
+        assert_ne!(first.nodes[0].id, second.nodes[0].id);
+        // arrows follow the nodes to their new ids
+        assert!(first
+            .arrows
+            .iter()
+            .all(|arrow| first.nodes.iter().any(|node| node.id == arrow.start.id.0)));
+        assert_eq!(first.nodes[1].text.text, second.nodes[1].text.text);
+    }
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use async_channel::{Receiver, Sender};
+use bevy::prelude::*;
+
+use crate::error::VeloError;
+use crate::themes::Theme;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Error,
+}
+
+#[derive(Clone, Debug)]
+pub struct ToastMessage {
+    pub level: ToastLevel,
+    pub text: String,
+}
+
+/// Notifications waiting to be shown. Clones share the queue, so a clone can be moved
+/// into callbacks that run outside of the app, like http responses.
+#[derive(Resource, Clone)]
+pub struct Toasts {
+    tx: Sender<ToastMessage>,
+    rx: Receiver<ToastMessage>,
+}
+
+impl Default for Toasts {
+    fn default() -> Self {
+        let (tx, rx) = async_channel::unbounded();
+        Self { tx, rx }
+    }
+}
+
+impl Toasts {
+    pub fn info(&self, text: impl Into<String>) {
+        self.push(ToastLevel::Info, text.into());
+    }
+
+    /// Logs the failure and tells the user what could not be done, e.g.
+    /// `toasts.error("Could not save document", e)`.
+    pub fn error(&self, action: &str, error: VeloError) {
+        let text = format!("{}: {}", action, error);
+        error!("{}", text);
+        self.push(ToastLevel::Error, text);
+    }
+
+    fn push(&self, level: ToastLevel, text: String) {
+        // unbounded and the receiver lives as long as the sender
+        let _ = self.tx.try_send(ToastMessage { level, text });
+    }
+
+    pub fn drain(&self) -> Vec<ToastMessage> {
+        let mut messages = vec![];
+        while let Ok(message) = self.rx.try_recv() {
+            messages.push(message);
+        }
+        messages
+    }
+}
+
+#[derive(Component)]
+pub struct ToastList;
+
+#[derive(Component)]
+pub struct Toast {
+    expires_at: Duration,
+}
+
+const INFO_TIMEOUT: Duration = Duration::from_secs(4);
+const ERROR_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn init_toasts(mut commands: Commands) {
+    commands.spawn((
+        NodeBundle {
+            z_index: ZIndex::Global(2),
+            style: Style {
+                position_type: PositionType::Absolute,
+                right: Val::Px(10.),
+                bottom: Val::Px(50.),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::End,
+                ..default()
+            },
+            ..default()
+        },
+        ToastList,
+    ));
+}
+
+pub fn show_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    toasts: Res<Toasts>,
+    theme: Res<Theme>,
+    toast_list: Query<Entity, With<ToastList>>,
+) {
+    let Ok(toast_list) = toast_list.get_single() else {
+        return;
+    };
+    for message in toasts.drain() {
+        let (border_color, timeout) = match message.level {
+            ToastLevel::Info => (theme.btn_border, INFO_TIMEOUT),
+            ToastLevel::Error => (theme.del_button, ERROR_TIMEOUT),
+        };
+        let toast = commands
+            .spawn((
+                ButtonBundle {
+                    border_color: border_color.into(),
+                    background_color: theme.modal_bg.into(),
+                    style: Style {
+                        border: UiRect::all(Val::Px(1.)),
+                        padding: UiRect::all(Val::Px(8.)),
+                        margin: UiRect::top(Val::Px(5.)),
+                        max_width: Val::Px(400.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                Toast {
+                    expires_at: time.elapsed() + timeout,
+                },
+            ))
+            .with_children(|builder| {
+                builder.spawn(TextBundle::from_section(
+                    message.text,
+                    TextStyle {
+                        font_size: 16.,
+                        color: theme.font,
+                        ..default()
+                    },
+                ));
+            })
+            .id();
+        commands.entity(toast_list).add_child(toast);
+    }
+}
+
+/// Toasts go away on their own or when clicked.
+pub fn dismiss_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    toasts: Query<(Entity, &Toast, &Interaction)>,
+) {
+    for (entity, toast, interaction) in toasts.iter() {
+        if *interaction == Interaction::Pressed || time.elapsed() >= toast.expires_at {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toasts_from_clones_keep_order() {
+        let toasts = Toasts::default();
+        let callback_toasts = toasts.clone();
+        toasts.error(
+            "Could not open document",
+            VeloError::InvalidDocument("expected value".to_string()),
+        );
+        callback_toasts.info("Link copied to clipboard");
+
+        let messages = toasts.drain();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].level, ToastLevel::Error);
+        assert_eq!(
+            messages[0].text,
+            "Could not open document: invalid document: expected value"
+        );
+        assert_eq!(messages[1].level, ToastLevel::Info);
+        assert!(toasts.drain().is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_cosmic_edit::CosmicFont;
+
+use super::ui_helpers::{
+    add_rectangle_txt, spawn_modal, GenericButton, OpenTrash, TrashAction, TrashItemButton,
+    TrashList,
+};
+use super::{MainPanel, ModalAction, Toasts, UiState};
+use crate::components::Tab;
+use crate::resources::{AppState, FontSystemState, LoadDocRequest, SaveTabRequest};
+use crate::store::{
+    collect_garbage, purge_from_trash, purge_trash_before, restore_from_trash, Storage, TrashEntry,
+    TrashedItem,
+};
+use crate::themes::Theme;
+use crate::utils::{get_timestamp, ReflectableUuid};
+
+const DAY_MILLIS: f64 = 24. * 60. * 60. * 1000.;
+
+/// How long deleted documents and tabs are kept, `trash_retention_days` in `~/.velo.toml`.
+#[derive(Resource)]
+pub struct Trash {
+    pub retention_days: f64,
+}
+
+impl Default for Trash {
+    fn default() -> Self {
+        Self {
+            retention_days: 30.,
+        }
+    }
+}
+
+pub fn purge_expired_trash(trash: Res<Trash>, mut storage: ResMut<Storage>) {
+    let deleted_before = get_timestamp() - trash.retention_days * DAY_MILLIS;
+    match purge_trash_before(storage.0.as_mut(), deleted_before) {
+        Ok(0) => {}
+        Ok(count) => info!("Purged {} items from the trash", count),
+        Err(e) => error!("Error purging trash: {}", e),
+    }
+}
+
+/// Runs after the trash is purged, so images of purged items go as well.
+pub fn collect_image_garbage(mut storage: ResMut<Storage>) {
+    match collect_garbage(storage.0.as_mut()) {
+        Ok(0) => {}
+        Ok(count) => info!("Removed {} unused images", count),
+        Err(e) => error!("Error removing unused images: {}", e),
+    }
+}
+
+pub fn open_trash(
+    mut commands: Commands,
+    mut query: Query<&Interaction, (Changed<Interaction>, With<OpenTrash>)>,
+    mut ui_state: ResMut<UiState>,
+    main_panel_query: Query<Entity, With<MainPanel>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut cosmic_fonts: ResMut<Assets<CosmicFont>>,
+    font_system_state: ResMut<FontSystemState>,
+    theme: Res<Theme>,
+) {
+    let window = windows.single();
+    for interaction in &mut query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => {
+                let id = ReflectableUuid::generate();
+                *ui_state = UiState::default();
+                commands.insert_resource(bevy_cosmic_edit::ActiveEditor { entity: None });
+                ui_state.modal_id = Some(id);
+                let entity = spawn_modal(
+                    &mut commands,
+                    &theme,
+                    &mut cosmic_fonts,
+                    font_system_state.0.clone().unwrap(),
+                    window,
+                    id,
+                    ModalAction::Trash,
+                );
+                commands.entity(main_panel_query.single()).add_child(entity);
+            }
+            Interaction::Hovered => {}
+            Interaction::None => {}
+        }
+    }
+}
+
+/// Lists the trash, newest first, in a trash modal that was just spawned.
+pub fn fill_trash_list(
+    mut commands: Commands,
+    lists: Query<Entity, Added<TrashList>>,
+    storage: Res<Storage>,
+    theme: Res<Theme>,
+) {
+    for list in lists.iter() {
+        let trash = storage.trash().unwrap_or_default();
+        if trash.is_empty() {
+            let label = commands
+                .spawn(add_rectangle_txt(&theme, "The trash is empty".to_string()))
+                .id();
+            commands.entity(list).add_child(label);
+            continue;
+        }
+        let now = get_timestamp();
+        for entry in trash.iter().rev() {
+            let row = spawn_trash_row(&mut commands, &theme, entry, now);
+            commands.entity(list).add_child(row);
+        }
+    }
+}
+
+fn spawn_trash_row(
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    entry: &TrashEntry,
+    now: f64,
+) -> Entity {
+    let row = commands
+        .spawn(NodeBundle {
+            style: Style {
+                width: Val::Percent(100.),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::SpaceBetween,
+                margin: UiRect::bottom(Val::Px(5.)),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    let label = format!("{}, {}", entry.name(), deleted_ago(entry.deleted_at, now));
+    let label = commands.spawn(add_rectangle_txt(theme, label)).id();
+    commands.entity(row).add_child(label);
+    for (action, text) in [
+        (TrashAction::Restore, "Restore"),
+        (TrashAction::Purge, "Purge"),
+    ] {
+        let button = commands
+            .spawn((
+                ButtonBundle {
+                    border_color: theme.btn_border.into(),
+                    background_color: theme.ok_cancel_bg.into(),
+                    style: Style {
+                        border: UiRect::all(Val::Px(1.)),
+                        padding: UiRect::all(Val::Px(3.)),
+                        margin: UiRect::left(Val::Px(5.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                GenericButton,
+                TrashItemButton {
+                    id: entry.id,
+                    action,
+                },
+            ))
+            .with_children(|builder| {
+                builder.spawn(TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        font_size: 14.,
+                        color: theme.font,
+                        ..default()
+                    },
+                ));
+            })
+            .id();
+        commands.entity(row).add_child(button);
+    }
+    row
+}
+
+fn deleted_ago(deleted_at: f64, now: f64) -> String {
+    match ((now - deleted_at) / DAY_MILLIS).floor() as i64 {
+        i64::MIN..=0 => "deleted today".to_string(),
+        1 => "deleted yesterday".to_string(),
+        days => format!("deleted {} days ago", days),
+    }
+}
+
+pub fn trash_item_handler(
+    mut commands: Commands,
+    interaction_query: Query<
+        (&Interaction, &TrashItemButton, &Parent),
+        (Changed<Interaction>, With<TrashItemButton>),
+    >,
+    mut app_state: ResMut<AppState>,
+    mut storage: ResMut<Storage>,
+    toasts: Res<Toasts>,
+) {
+    for (interaction, button, row) in interaction_query.iter() {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match button.action {
+            TrashAction::Restore => match restore_from_trash(storage.0.as_mut(), &button.id) {
+                Ok(item) => restore_item(item, &mut app_state, &mut commands),
+                Err(e) => {
+                    toasts.error("Could not restore from trash", e.into());
+                    continue;
+                }
+            },
+            TrashAction::Purge => {
+                if let Err(e) = purge_from_trash(storage.0.as_mut(), &button.id) {
+                    toasts.error("Could not purge from trash", e.into());
+                    continue;
+                }
+            }
+        }
+        commands.entity(row.get()).despawn_recursive();
+    }
+}
+
+/// The store already has the item back, this updates what is shown and searchable.
+fn restore_item(item: TrashedItem, app_state: &mut ResMut<AppState>, commands: &mut Commands) {
+    let (doc_id, tabs) = match item {
+        TrashedItem::Doc(doc) => {
+            app_state.doc_list_ui.insert(doc.id);
+            (doc.id, doc.tabs)
+        }
+        TrashedItem::Tab { doc_id, tab, .. } => {
+            let is_current = app_state.current_document == Some(doc_id);
+            if let Some(doc) = app_state.doc_mut(&doc_id) {
+                if doc.tabs.iter().all(|other| other.id != tab.id) {
+                    doc.tabs.push(Tab {
+                        is_active: false,
+                        ..tab.clone()
+                    });
+                }
+                if is_current {
+                    if let Some(active_tab) = doc.tabs.iter().find(|tab| tab.is_active) {
+                        commands.insert_resource(SaveTabRequest {
+                            doc_id,
+                            tab_id: active_tab.id,
+                        });
+                    }
+                    commands.insert_resource(LoadDocRequest { doc_id });
+                }
+            }
+            (doc_id, vec![tab])
+        }
+    };
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(search_index) = &mut app_state.search_index {
+        super::reindex_tabs(search_index, &doc_id, &tabs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deleted_ago() {
+        let now = 100. * DAY_MILLIS;
+        assert_eq!(deleted_ago(now - 1000., now), "deleted today");
+        assert_eq!(
+            deleted_ago(now - 1.5 * DAY_MILLIS, now),
+            "deleted yesterday"
+        );
+        assert_eq!(
+            deleted_ago(now - 30. * DAY_MILLIS, now),
+            "deleted 30 days ago"
+        );
+    }
+}
@@ -1,23 +1,53 @@
 use bevy::prelude::*;
+use bevy_cosmic_edit::CosmicEdit;
 
-use crate::{canvas::arrow::events::RedrawArrow, components::MainCamera};
+use crate::{canvas::arrow::events::RedrawArrow, components::MainCamera, utils::ReflectableUuid};
 
 use super::{
+    dragged_nodes,
     ui_helpers::{RawText, VeloNode, VeloShape},
-    UiState,
+    NodeBounds, UiState,
 };
 
 pub fn update_rectangle_position(
     mut cursor_moved_events: EventReader<CursorMoved>,
-    raw_text_query: Query<(&RawText, &Parent), With<RawText>>,
-    border_query: Query<&Parent, With<VeloShape>>,
-    mut velo_node_query: Query<&mut Transform, With<VeloNode>>,
+    raw_text_query: Query<(&RawText, &CosmicEdit, &Parent), With<RawText>>,
+    border_query: Query<(&Parent, &VeloShape), With<VeloShape>>,
+    mut velo_node_query: Query<(&VeloNode, &mut Transform), With<VeloNode>>,
     mut events: EventWriter<RedrawArrow>,
     camera_q: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
     ui_state: Res<UiState>,
+    mut followers: Local<(Option<ReflectableUuid>, Vec<ReflectableUuid>)>,
 ) {
+    if followers.0 != ui_state.hold_entity {
+        // who follows the drag is decided once, so nodes passed over are not picked up
+        followers.0 = ui_state.hold_entity;
+        followers.1 = match ui_state.hold_entity {
+            Some(id) => {
+                let nodes: Vec<NodeBounds> = raw_text_query
+                    .iter()
+                    .filter_map(|(raw_text, cosmic_edit, parent)| {
+                        let (border_parent, velo_shape) = border_query.get(parent.get()).ok()?;
+                        let (_, transform) = velo_node_query.get(border_parent.get()).ok()?;
+                        Some(NodeBounds {
+                            id: raw_text.id,
+                            node_type: velo_shape.node_type.clone(),
+                            center: transform.translation,
+                            size: Vec2::new(cosmic_edit.width, cosmic_edit.height),
+                        })
+                    })
+                    .collect();
+                dragged_nodes(id, &ui_state.selected, &nodes)
+                    .into_iter()
+                    .skip(1)
+                    .collect()
+            }
+            None => vec![],
+        };
+    }
+
     let (camera, camera_transform) = camera_q.single();
-    for (raw_text, parent) in &mut raw_text_query.iter() {
+    for (raw_text, _, parent) in &mut raw_text_query.iter() {
         if !ui_state.drawing_mode
             && ui_state.modal_id.is_none()
             && Some(raw_text.id) == ui_state.hold_entity
@@ -27,11 +57,21 @@ pub fn update_rectangle_position(
             if let Some(pos) = event
                 .and_then(|event| camera.viewport_to_world_2d(camera_transform, event.position))
             {
-                let border = border_query.get(parent.get()).unwrap();
-                let mut top = velo_node_query.get_mut(border.get()).unwrap();
+                let (border, _) = border_query.get(parent.get()).unwrap();
+                let (_, mut top) = velo_node_query.get_mut(border.get()).unwrap();
+                let delta = pos.round() - top.translation.truncate();
                 top.translation.x = pos.x.round();
                 top.translation.y = pos.y.round();
                 events.send(RedrawArrow { id: raw_text.id });
+
+                // the rest of the selection and the nodes of dragged frames follow
+                for (velo_node, mut transform) in velo_node_query.iter_mut() {
+                    if followers.1.contains(&velo_node.id) {
+                        transform.translation.x += delta.x;
+                        transform.translation.y += delta.y;
+                        events.send(RedrawArrow { id: velo_node.id });
+                    }
+                }
             }
         }
     }
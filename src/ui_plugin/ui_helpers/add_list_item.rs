@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 use bevy::{
     a11y::{
         accesskit::{NodeBuilder, Role},
@@ -16,7 +18,14 @@ use crate::{
     utils::{bevy_color_to_cosmic, ReflectableUuid},
 };
 
-use super::{DeleteDoc, DocListItemButton, DocListItemContainer, EditableText, GenericButton};
+use super::{
+    DeleteDoc, DocListFolder, DocListItemButton, DocListItemContainer, EditableText, GenericButton,
+    PinDoc, TagChip,
+};
+use crate::store::DocEntry;
+
+/// Indentation of the document list per folder level.
+const FOLDER_INDENT: f32 = 12.;
 
 pub fn add_list_item(
     commands: &mut Commands,
@@ -25,7 +34,9 @@ pub fn add_list_item(
     theme: &Res<Theme>,
     asset_server: &Res<AssetServer>,
     id: ReflectableUuid,
-    name: String,
+    entry: &DocEntry,
+    depth: usize,
+    tag_filter: &BTreeSet<String>,
     scale_factor: f32,
 ) -> Entity {
     let icon_font = asset_server.load("fonts/MaterialIcons-Regular.ttf");
@@ -39,6 +50,7 @@ pub fn add_list_item(
                     height: Val::Percent(100.),
                     justify_content: JustifyContent::Center,
                     border: UiRect::all(Val::Px(1.)),
+                    padding: UiRect::left(Val::Px(depth as f32 * FOLDER_INDENT)),
                     ..default()
                 },
                 ..default()
@@ -52,7 +64,7 @@ pub fn add_list_item(
     attrs = attrs.family(cosmic_text::Family::Name(theme.font_name.as_str()));
     attrs = attrs.color(bevy_color_to_cosmic(theme.font));
     let cosmic_edit_meta = CosmicEditMeta {
-        text: CosmicText::OneStyle(name),
+        text: CosmicText::OneStyle(entry.name.clone()),
         attrs: AttrsOwned::new(attrs),
         font_system_handle: cosmic_font_handle,
         text_pos: TextPos::Center.into(),
@@ -75,6 +87,38 @@ pub fn add_list_item(
         .insert(GenericButton)
         .insert(DocListItemButton { id });
 
+    let pin_icon = if entry.pinned { "\u{e838}" } else { "\u{e83a}" };
+    let pin_button = commands
+        .spawn((
+            ButtonBundle {
+                background_color: theme.doc_list_bg.into(),
+                style: Style {
+                    width: Val::Percent(10.),
+                    height: Val::Percent(100.),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            PinDoc { id },
+            GenericButton,
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    pin_icon,
+                    TextStyle {
+                        font_size: 18.,
+                        color: theme.font,
+                        font: icon_font.clone(),
+                    },
+                ),
+                Label,
+            ));
+        })
+        .id();
+
     let del_button = commands
         .spawn((
             ButtonBundle {
@@ -120,6 +164,133 @@ pub fn add_list_item(
         .id();
     commands.entity(del_button).add_child(del_label);
     commands.entity(root).add_child(cosmic_edit);
+    if !entry.tags.is_empty() {
+        let chips = add_tag_chips(commands, theme, &entry.tags, tag_filter);
+        commands.entity(root).add_child(chips);
+    }
+    commands.entity(root).add_child(pin_button);
     commands.entity(root).add_child(del_button);
     root
 }
+
+/// Header row of a folder, its documents and subfolders follow it one level deeper.
+pub fn add_folder_item(
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    asset_server: &Res<AssetServer>,
+    path: String,
+    name: String,
+    depth: usize,
+    collapsed: bool,
+) -> Entity {
+    let icon_font = asset_server.load("fonts/MaterialIcons-Regular.ttf");
+    let icon = if collapsed { "\u{e5cc}" } else { "\u{e5cf}" };
+    commands
+        .spawn((
+            ButtonBundle {
+                border_color: theme.btn_border.into(),
+                background_color: theme.doc_list_bg.into(),
+                style: Style {
+                    width: Val::Percent(100.),
+                    align_items: AlignItems::Center,
+                    border: UiRect::all(Val::Px(1.)),
+                    padding: UiRect {
+                        left: Val::Px(depth as f32 * FOLDER_INDENT + 5.),
+                        top: Val::Px(3.),
+                        bottom: Val::Px(3.),
+                        ..default()
+                    },
+                    ..default()
+                },
+                ..default()
+            },
+            GenericButton,
+            DocListFolder { path },
+            AccessibilityNode(NodeBuilder::new(Role::ListItem)),
+        ))
+        .with_children(|builder| {
+            builder.spawn((
+                TextBundle::from_section(
+                    icon,
+                    TextStyle {
+                        font_size: 20.,
+                        color: theme.font,
+                        font: icon_font,
+                    },
+                ),
+                Label,
+            ));
+            builder.spawn((
+                TextBundle::from_section(
+                    name,
+                    TextStyle {
+                        font_size: theme.font_size,
+                        color: theme.font,
+                        ..default()
+                    },
+                ),
+                Label,
+            ));
+        })
+        .id()
+}
+
+/// Tags of a document list row, those the list is filtered by are highlighted.
+fn add_tag_chips(
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    tags: &[String],
+    tag_filter: &BTreeSet<String>,
+) -> Entity {
+    let chips = commands
+        .spawn(NodeBundle {
+            style: Style {
+                flex_wrap: FlexWrap::Wrap,
+                align_items: AlignItems::Center,
+                align_content: AlignContent::Center,
+                max_width: Val::Percent(40.),
+                ..default()
+            },
+            ..default()
+        })
+        .id();
+    for tag in tags {
+        let background = if tag_filter.contains(tag) {
+            theme.node_found_color
+        } else {
+            theme.doc_list_bg
+        };
+        let chip = commands
+            .spawn((
+                ButtonBundle {
+                    background_color: background.into(),
+                    border_color: theme.btn_border.into(),
+                    style: Style {
+                        border: UiRect::all(Val::Px(1.)),
+                        margin: UiRect::all(Val::Px(2.)),
+                        padding: UiRect::horizontal(Val::Px(4.)),
+                        ..default()
+                    },
+                    ..default()
+                },
+                TagChip { tag: tag.clone() },
+                GenericButton,
+            ))
+            .with_children(|builder| {
+                builder.spawn((
+                    TextBundle::from_section(
+                        tag.clone(),
+                        TextStyle {
+                            font_size: 12.,
+                            color: theme.font,
+                            ..default()
+                        },
+                    ),
+                    Label,
+                ));
+            })
+            .id();
+        commands.entity(chips).add_child(chip);
+    }
+    chips
+}
@@ -54,9 +54,94 @@ pub struct ImportFromUrl;
 #[derive(Component, Clone)]
 pub struct ShareDoc;
 
+#[derive(Component, Clone)]
+pub struct CopyShareLink;
+
 #[derive(Component, Clone)]
 pub struct ChangeTheme;
 
+#[derive(Component, Clone)]
+pub struct OpenTrash;
+
+/// Rows of the trash modal are added to it once it is spawned.
+#[derive(Component)]
+pub struct TrashList;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrashAction {
+    Restore,
+    Purge,
+}
+
+#[derive(Component)]
+pub struct TrashItemButton {
+    pub id: ReflectableUuid,
+    pub action: TrashAction,
+}
+
+#[derive(Component, Clone)]
+pub struct SaveSnapshot;
+
+#[derive(Component, Clone)]
+pub struct OpenTabHistory;
+
+#[derive(Component, Clone)]
+pub struct LockDocument;
+
+#[derive(Component, Clone)]
+pub struct EditTags;
+
+#[derive(Component, Clone)]
+pub struct FilterTags;
+
+#[derive(Component, Clone)]
+pub struct SortDocs;
+
+#[derive(Component, Clone)]
+pub struct MoveToFolder;
+
+#[derive(Component, Clone)]
+pub struct SaveDocTemplate;
+
+#[derive(Component, Clone)]
+pub struct SaveTabTemplate;
+
+/// Templates to start a new document from are added to it once the modal is spawned.
+#[derive(Component)]
+pub struct TemplateList;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TemplateAction {
+    Use,
+    Delete,
+}
+
+/// `id` is `None` for the empty document.
+#[derive(Component)]
+pub struct TemplateButton {
+    pub id: Option<ReflectableUuid>,
+    pub action: TemplateAction,
+}
+
+/// Past states of the active tab are added to it once the history modal is spawned.
+#[derive(Component)]
+pub struct HistoryList;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotAction {
+    Preview,
+    Compare,
+    Restore,
+}
+
+#[derive(Component)]
+pub struct SnapshotButton {
+    pub doc_id: ReflectableUuid,
+    pub tab_id: ReflectableUuid,
+    pub checkpoint: String,
+    pub action: SnapshotAction,
+}
+
 #[derive(Component)]
 pub struct DeleteDoc {
     pub id: ReflectableUuid,
@@ -96,6 +181,23 @@ pub struct DocListItemButton {
     pub id: ReflectableUuid,
 }
 
+#[derive(Component)]
+pub struct PinDoc {
+    pub id: ReflectableUuid,
+}
+
+/// Header row of a folder in the document list, clicking it collapses the folder.
+#[derive(Component)]
+pub struct DocListFolder {
+    pub path: String,
+}
+
+/// A tag of a document list row, clicking it toggles the tag in the list filter.
+#[derive(Component)]
+pub struct TagChip {
+    pub tag: String,
+}
+
 #[derive(Component)]
 pub struct ChangeColor {
     pub pair_color: (String, Color),
@@ -138,6 +240,7 @@ pub enum ButtonTypes {
     AddRec,
     AddCircle,
     AddPaper,
+    AddFrame,
     Del,
     Front,
     Back,
@@ -187,6 +290,18 @@ pub enum ModalAction {
     LoadFromUrl,
     DeleteDocument,
     DeleteTab,
+    RestoreJournal,
+    Trash,
+    SaveSnapshot,
+    TabHistory,
+    LockDocument,
+    UnlockDocument,
+    EditTags,
+    FilterTags,
+    MoveToFolder,
+    NewDocument,
+    SaveDocTemplate,
+    SaveTabTemplate,
 }
 
 impl std::fmt::Display for ModalAction {
@@ -197,6 +312,20 @@ impl std::fmt::Display for ModalAction {
             ModalAction::LoadFromFile => write!(f, "Load from file:"),
             ModalAction::LoadFromUrl => write!(f, "Load from URL:"),
             ModalAction::SaveToFile => write!(f, "Save to file:"),
+            ModalAction::RestoreJournal => {
+                write!(f, "Restore unsaved changes from the last session?")
+            }
+            ModalAction::Trash => write!(f, "Trash"),
+            ModalAction::SaveSnapshot => write!(f, "Snapshot name:"),
+            ModalAction::TabHistory => write!(f, "Tab history"),
+            ModalAction::LockDocument => write!(f, "Lock with passphrase:"),
+            ModalAction::UnlockDocument => write!(f, "Passphrase:"),
+            ModalAction::EditTags => write!(f, "Tags, comma separated:"),
+            ModalAction::FilterTags => write!(f, "Show documents tagged:"),
+            ModalAction::MoveToFolder => write!(f, "Move to folder:"),
+            ModalAction::NewDocument => write!(f, "New document from"),
+            ModalAction::SaveDocTemplate => write!(f, "Save document as template:"),
+            ModalAction::SaveTabTemplate => write!(f, "Save tab as template:"),
         }
     }
 }
@@ -0,0 +1,41 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::{PathBuilder, ShapeBundle, Stroke};
+
+use crate::ui_plugin::DrawingJsonNode;
+
+use super::Drawing;
+
+pub fn spawn_drawing(
+    commands: &mut Commands,
+    drawing_json_node: DrawingJsonNode<(String, Color)>,
+) -> Entity {
+    let mut path_builder = PathBuilder::new();
+    let mut points_iter = drawing_json_node.points.iter();
+    if let Some(start) = points_iter.next() {
+        path_builder.move_to(*start);
+        path_builder.line_to(*start);
+    }
+    for point in points_iter {
+        path_builder.line_to(*point);
+    }
+    let path = path_builder.build();
+    commands
+        .spawn((
+            ShapeBundle {
+                path,
+                transform: Transform::from_xyz(
+                    drawing_json_node.x,
+                    drawing_json_node.y,
+                    drawing_json_node.z,
+                ),
+                ..Default::default()
+            },
+            Stroke::new(drawing_json_node.drawing_color.1, drawing_json_node.width),
+            Drawing {
+                id: drawing_json_node.id,
+                points: drawing_json_node.points,
+                drawing_color: drawing_json_node.drawing_color,
+            },
+        ))
+        .id()
+}
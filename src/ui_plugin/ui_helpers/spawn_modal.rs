@@ -7,8 +7,8 @@ use bevy::prelude::*;
 use cosmic_text::AttrsOwned;
 
 use super::{
-    add_rectangle_txt, EditableText, GenericButton, ModalAction, ModalCancel, ModalConfirm,
-    ModalTop,
+    add_rectangle_txt, EditableText, GenericButton, HistoryList, ModalAction, ModalCancel,
+    ModalConfirm, ModalTop, TemplateList, TrashList,
 };
 use crate::{
     themes::Theme,
@@ -25,14 +25,40 @@ pub fn spawn_modal(
     id: ReflectableUuid,
     modal_action: ModalAction,
 ) -> Entity {
-    let width = 350.;
-    let height = 250.;
     let default_value = match modal_action {
         ModalAction::SaveToFile => "./velo.json".to_string(),
         ModalAction::LoadFromFile => "./velo.json".to_string(),
         ModalAction::LoadFromUrl => "https://gist..".to_string(),
         _ => "".to_string(),
     };
+    spawn_modal_with_value(
+        commands,
+        theme,
+        cosmic_fonts,
+        cosmic_font_handle,
+        window,
+        id,
+        modal_action,
+        default_value,
+    )
+}
+
+/// A modal whose text input starts with `default_value`.
+pub fn spawn_modal_with_value(
+    commands: &mut Commands,
+    theme: &Res<Theme>,
+    cosmic_fonts: &mut ResMut<Assets<CosmicFont>>,
+    cosmic_font_handle: Handle<CosmicFont>,
+    window: &Window,
+    id: ReflectableUuid,
+    modal_action: ModalAction,
+    default_value: String,
+) -> Entity {
+    let width = 350.;
+    let height = match modal_action {
+        ModalAction::Trash | ModalAction::TabHistory | ModalAction::NewDocument => 400.,
+        _ => 250.,
+    };
     let top = commands
         .spawn((
             NodeBundle {
@@ -140,7 +166,17 @@ pub fn spawn_modal(
     commands.entity(modal_static).add_child(cancel_button);
 
     let modal_dynamic = match modal_action {
-        ModalAction::SaveToFile | ModalAction::LoadFromFile | ModalAction::LoadFromUrl => {
+        ModalAction::SaveToFile
+        | ModalAction::LoadFromFile
+        | ModalAction::LoadFromUrl
+        | ModalAction::SaveSnapshot
+        | ModalAction::LockDocument
+        | ModalAction::UnlockDocument
+        | ModalAction::EditTags
+        | ModalAction::FilterTags
+        | ModalAction::MoveToFolder
+        | ModalAction::SaveDocTemplate
+        | ModalAction::SaveTabTemplate => {
             let top = commands
                 .spawn(NodeBundle {
                     style: Style {
@@ -214,7 +250,7 @@ pub fn spawn_modal(
             commands.entity(top).add_child(button);
             top
         }
-        ModalAction::DeleteDocument | ModalAction::DeleteTab => {
+        ModalAction::DeleteDocument | ModalAction::DeleteTab | ModalAction::RestoreJournal => {
             let top = commands
                 .spawn(NodeBundle {
                     style: Style {
@@ -237,16 +273,42 @@ pub fn spawn_modal(
                     ..default()
                 })
                 .id();
-            let node_label = commands
-                .spawn(add_rectangle_txt(
-                    theme,
-                    format!("Are you sure you want to {}?", modal_action),
-                ))
-                .id();
+            let label = match modal_action {
+                ModalAction::RestoreJournal => modal_action.to_string(),
+                _ => format!("Are you sure you want to {}?", modal_action),
+            };
+            let node_label = commands.spawn(add_rectangle_txt(theme, label)).id();
             commands.entity(node).add_child(node_label);
             commands.entity(top).add_child(node);
             top
         }
+        ModalAction::Trash | ModalAction::TabHistory | ModalAction::NewDocument => {
+            let list = commands
+                .spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(10.)),
+                        width: Val::Percent(100.),
+                        height: Val::Percent(70.),
+                        overflow: Overflow::clip(),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .id();
+            match modal_action {
+                ModalAction::Trash => {
+                    commands.entity(list).insert(TrashList);
+                }
+                ModalAction::NewDocument => {
+                    commands.entity(list).insert(TemplateList);
+                }
+                _ => {
+                    commands.entity(list).insert(HistoryList);
+                }
+            }
+            list
+        }
     };
     let modal = commands
         .spawn((NodeBundle {
@@ -77,12 +77,14 @@ pub fn spawn_sprite_node(
                 radius: 10.,
             },
         ),
-        NodeType::Paper => bevy_prototype_lyon::prelude::GeometryBuilder::build_as(
-            &bevy_prototype_lyon::shapes::Polygon {
-                points: points.into_iter().collect(),
-                closed: true,
-            },
-        ),
+        NodeType::Paper | NodeType::Frame => {
+            bevy_prototype_lyon::prelude::GeometryBuilder::build_as(
+                &bevy_prototype_lyon::shapes::Polygon {
+                    points: points.into_iter().collect(),
+                    closed: true,
+                },
+            )
+        }
         NodeType::Circle => bevy_prototype_lyon::prelude::GeometryBuilder::build_as(
             &bevy_prototype_lyon::shapes::Circle {
                 radius: width / 2.,
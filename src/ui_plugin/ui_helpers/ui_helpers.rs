@@ -7,6 +7,9 @@ pub use components::*;
 #[path = "spawn_node.rs"]
 mod spawn_node;
 pub use spawn_node::*;
+#[path = "spawn_drawing.rs"]
+mod spawn_drawing;
+pub use spawn_drawing::*;
 #[path = "spawn_modal.rs"]
 mod spawn_modal;
 pub use spawn_modal::*;
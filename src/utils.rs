@@ -1,18 +1,26 @@
-use bevy::prelude::*;
+use base64::{engine::general_purpose, Engine};
+use bevy::{
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
 
 use bevy_cosmic_edit::CosmicTextPos;
+use image::{load_from_memory_with_format, ImageFormat, ImageOutputFormat};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 use crate::resources::AppState;
 use crate::ui_plugin::TextPos;
 
-use std::collections::HashMap;
+use std::collections::BTreeSet;
+use std::io::Cursor;
 use std::{fs, path::PathBuf};
 use uuid::Uuid;
 
-use bevy_pkv::PkvStore;
-
-use crate::{components::Doc, ui_plugin::MAX_SAVED_DOCS_IN_MEMORY};
+use crate::components::Doc;
+use crate::error::VeloError;
+use crate::store::{Storage, StoreError};
+use crate::ui_plugin::{MAX_DOCS_MEMORY_BYTES, MAX_SAVED_DOCS_IN_MEMORY};
 
 #[derive(Clone, Reflect, Default, Debug, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
 #[reflect_value]
@@ -43,32 +51,158 @@ pub fn get_timestamp() -> f64 {
     duration.as_millis() as f64
 }
 
+/// Encodes a node background as base64 png, as kept in the image store.
+pub fn image_to_base64(image: &Image) -> Option<String> {
+    let img = image.clone().try_into_dynamic().ok()?;
+    let mut image_data: Vec<u8> = Vec::new();
+    img.write_to(&mut Cursor::new(&mut image_data), ImageOutputFormat::Png)
+        .ok()?;
+    Some(general_purpose::STANDARD.encode(image_data))
+}
+
+/// Key of a node background in the image store: sha256 of its base64 png, in hex.
+pub fn image_hash(data: &str) -> String {
+    Sha256::digest(data.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Decodes a base64 png from the image store.
+pub fn base64_to_image(data: &str) -> Option<Image> {
+    let image_bytes = general_purpose::STANDARD.decode(data.as_bytes()).ok()?;
+    let img = load_from_memory_with_format(&image_bytes, ImageFormat::Png).ok()?;
+    let size: Extent3d = Extent3d {
+        width: img.width(),
+        height: img.height(),
+        ..Default::default()
+    };
+    Some(Image::new(
+        size,
+        TextureDimension::D2,
+        img.into_rgba8().into_raw(),
+        TextureFormat::Rgba8UnormSrgb,
+    ))
+}
+
 pub fn load_doc_to_memory(
     doc_id: ReflectableUuid,
-    app_state: &mut ResMut<AppState>,
-    pkv: &mut ResMut<PkvStore>,
-) {
-    if app_state.docs.contains_key(&doc_id) {
-        return;
+    app_state: &mut AppState,
+    storage: &Storage,
+) -> Result<(), VeloError> {
+    if !app_state.docs.contains_key(&doc_id) {
+        let doc = storage.doc(&doc_id).map_err(|e| match e {
+            StoreError::NotFound(_) => VeloError::DocumentNotFound(doc_id),
+            e => VeloError::Store(e),
+        })?;
+        app_state.docs.insert(doc_id, doc);
     }
-    if let Ok(docs) = pkv.get::<HashMap<ReflectableUuid, Doc>>("docs") {
-        if docs.contains_key(&doc_id) {
-            let keys = app_state.docs.keys().cloned().collect::<Vec<_>>();
-            while (app_state.docs.len() as i32) >= MAX_SAVED_DOCS_IN_MEMORY {
-                app_state.docs.remove(&keys[0]);
-            }
-            app_state
-                .docs
-                .insert(doc_id, docs.get(&doc_id).unwrap().clone());
-        } else {
-            panic!("Document not found in pkv");
+    app_state.recent_docs.retain(|id| *id != doc_id);
+    app_state.recent_docs.push_back(doc_id);
+    evict_docs(
+        app_state,
+        doc_id,
+        MAX_SAVED_DOCS_IN_MEMORY,
+        MAX_DOCS_MEMORY_BYTES,
+    );
+    Ok(())
+}
+
+/// Rough size of a document in memory, which is mostly its checkpoints.
+pub fn estimated_doc_size(doc: &Doc) -> usize {
+    let tabs: usize = doc
+        .tabs
+        .iter()
+        .map(|tab| {
+            tab.name.len()
+                + tab.checkpoints.iter().map(String::len).sum::<usize>()
+                + tab
+                    .history
+                    .iter()
+                    .map(|snapshot| snapshot.checkpoint.len())
+                    .sum::<usize>()
+        })
+        .sum();
+    doc.name.len() + doc.tags.iter().map(String::len).sum::<usize>() + tabs
+}
+
+/// Drops the least recently opened documents until at most `max_docs` documents of at
+/// most `max_bytes` are left. `keep`, the current document and documents with unsaved
+/// changes are never dropped, even if that breaks the budget.
+/// Returns the ids of the dropped documents.
+pub fn evict_docs(
+    app_state: &mut AppState,
+    keep: ReflectableUuid,
+    max_docs: usize,
+    max_bytes: usize,
+) -> Vec<ReflectableUuid> {
+    let docs = &app_state.docs;
+    app_state.recent_docs.retain(|id| docs.contains_key(id));
+    // documents put in memory some other way, e.g. new ones, count as the least recent
+    let mut order: Vec<ReflectableUuid> = docs
+        .keys()
+        .filter(|id| !app_state.recent_docs.contains(id))
+        .cloned()
+        .collect();
+    order.sort_by_key(|id| id.0);
+    order.extend(app_state.recent_docs.iter().cloned());
+
+    let mut count = docs.len();
+    let mut bytes: usize = docs.values().map(estimated_doc_size).sum();
+    let mut evicted = vec![];
+    for id in order {
+        if count <= max_docs && bytes <= max_bytes {
+            break;
+        }
+        if id == keep
+            || Some(id) == app_state.current_document
+            || app_state.dirty_docs.contains(&id)
+        {
+            continue;
+        }
+        bytes -= estimated_doc_size(&app_state.docs[&id]);
+        count -= 1;
+        app_state.docs.remove(&id);
+        evicted.push(id);
+    }
+    app_state.recent_docs.retain(|id| !evicted.contains(id));
+    evicted
+}
+
+/// Tags without surrounding whitespace, empty ones and repeats, in their first order.
+pub fn dedup_tags<S: AsRef<str>>(tags: impl IntoIterator<Item = S>) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    for tag in tags {
+        let tag = tag.as_ref().trim();
+        if !tag.is_empty() && !result.iter().any(|t| t == tag) {
+            result.push(tag.to_string());
         }
     }
+    result
+}
+
+/// Tags typed as a comma separated list.
+pub fn parse_tags(text: &str) -> Vec<String> {
+    dedup_tags(text.split(','))
+}
+
+/// Whether a document with `tags` is listed under `filter`, it needs every tag of it.
+pub fn matches_tags(tags: &[String], filter: &BTreeSet<String>) -> bool {
+    filter.iter().all(|tag| tags.contains(tag))
 }
 
 #[derive(Debug, Default)]
 pub struct Config {
     pub github_access_token: Option<String>,
+    pub share_backend: Option<String>,
+    pub gitlab_access_token: Option<String>,
+    pub gitlab_url: Option<String>,
+    pub share_url: Option<String>,
+    pub share_token: Option<String>,
+    pub store: Option<String>,
+    pub store_dir: Option<PathBuf>,
+    pub autosave_idle_secs: Option<f64>,
+    pub trash_retention_days: Option<f64>,
 }
 
 #[cfg(not(target_arch = "wasm32"))]
@@ -77,12 +211,32 @@ pub fn read_config_file() -> Option<Config> {
     let config_file_path = PathBuf::from(&home_dir).join(".velo.toml");
     let config_str = fs::read_to_string(config_file_path).ok()?;
     let config_value: toml::Value = toml::from_str(&config_str).ok()?;
-    let mut config = Config::default();
-    if let Some(token) = config_value.get("github_access_token") {
-        if let Some(token_str) = token.as_str() {
-            config.github_access_token = Some(token_str.to_owned());
-        }
-    }
+    let string = |key: &str| {
+        config_value
+            .get(key)
+            .and_then(|value| value.as_str())
+            .map(str::to_owned)
+    };
+    // whole numbers are fine too, e.g. `autosave_idle_secs = 5`
+    let number = |key: &str| {
+        config_value.get(key).and_then(|value| {
+            value
+                .as_float()
+                .or_else(|| value.as_integer().map(|value| value as f64))
+        })
+    };
+    let config = Config {
+        github_access_token: string("github_access_token"),
+        share_backend: string("share_backend"),
+        gitlab_access_token: string("gitlab_access_token"),
+        gitlab_url: string("gitlab_url"),
+        share_url: string("share_url"),
+        share_token: string("share_token"),
+        store: string("store"),
+        store_dir: string("store_dir").map(PathBuf::from),
+        autosave_idle_secs: number("autosave_idle_secs"),
+        trash_retention_days: number("trash_retention_days"),
+    };
     Some(config)
 }
 
@@ -113,8 +267,8 @@ pub fn bevy_color_to_cosmic(color: bevy::prelude::Color) -> cosmic_text::Color {
     )
 }
 
-pub fn get_theme_key(pkv: &PkvStore) -> String {
-    if let Ok(user_preferences) = pkv.get::<UserPreferences>("user_preferences") {
+pub fn get_theme_key(storage: &Storage) -> String {
+    if let Ok(user_preferences) = storage.preferences() {
         if let Some(theme_name) = user_preferences.theme_name {
             theme_name
         } else {
@@ -127,3 +281,100 @@ pub fn get_theme_key(pkv: &PkvStore) -> String {
 
 pub static DARK_THEME_ICON_CODE: &str = "\u{e51c}";
 pub static LIGHT_THEME_ICON_CODE: &str = "\u{e518}";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn id(id: u128) -> ReflectableUuid {
+        ReflectableUuid(Uuid::from_u128(id))
+    }
+
+    fn doc(n: u128, checkpoint: &str) -> Doc {
+        Doc::test(&format!("doc {}", n), &[checkpoint]).with_id(id(n))
+    }
+
+    #[test]
+    fn test_load_evicts_least_recently_used() {
+        let mut storage = Storage(Box::<MemoryStore>::default());
+        for i in 0..=MAX_SAVED_DOCS_IN_MEMORY as u128 {
+            storage.set_doc(&doc(i, "{}")).unwrap();
+        }
+        let mut app_state = AppState::default();
+        for i in 0..MAX_SAVED_DOCS_IN_MEMORY as u128 {
+            load_doc_to_memory(id(i), &mut app_state, &storage).unwrap();
+        }
+        load_doc_to_memory(id(0), &mut app_state, &storage).unwrap();
+        assert_eq!(app_state.docs.len(), MAX_SAVED_DOCS_IN_MEMORY);
+
+        load_doc_to_memory(
+            id(MAX_SAVED_DOCS_IN_MEMORY as u128),
+            &mut app_state,
+            &storage,
+        )
+        .unwrap();
+        assert_eq!(app_state.docs.len(), MAX_SAVED_DOCS_IN_MEMORY);
+        assert!(app_state.docs.contains_key(&id(0)));
+        assert!(!app_state.docs.contains_key(&id(1)));
+        assert_eq!(app_state.recent_docs.back(), Some(&id(7)));
+    }
+
+    #[test]
+    fn test_current_and_unsaved_docs_are_kept() {
+        let mut app_state = AppState::default();
+        for i in 0..4 {
+            let doc = doc(i, "{}");
+            if i == 3 {
+                app_state.insert_doc(doc);
+            } else {
+                app_state.docs.insert(doc.id, doc);
+            }
+            app_state.recent_docs.push_back(id(i));
+        }
+        app_state.current_document = Some(id(0));
+        app_state.doc_mut(&id(1)).unwrap().name = "renamed".to_string();
+
+        let evicted = evict_docs(&mut app_state, id(0), 0, usize::MAX);
+        assert_eq!(evicted, vec![id(2)]);
+        let mut kept: Vec<_> = app_state.docs.keys().cloned().collect();
+        kept.sort_by_key(|id| id.0);
+        assert_eq!(kept, vec![id(0), id(1), id(3)]);
+        assert_eq!(app_state.recent_docs, [id(0), id(1), id(3)]);
+    }
+
+    #[test]
+    fn test_evict_to_memory_budget() {
+        let mut app_state = AppState::default();
+        let checkpoint = "x".repeat(1000);
+        for i in 0..4 {
+            let doc = doc(i, &checkpoint);
+            app_state.docs.insert(doc.id, doc);
+            app_state.recent_docs.push_back(id(i));
+        }
+        let size = estimated_doc_size(&app_state.docs[&id(0)]);
+        assert!(size > 1000);
+
+        let evicted = evict_docs(&mut app_state, id(3), 10, 2 * size);
+        assert_eq!(evicted, vec![id(0), id(1)]);
+        assert_eq!(evict_docs(&mut app_state, id(3), 10, 2 * size), vec![]);
+        // the document being opened stays even if it alone breaks the budget
+        assert_eq!(evict_docs(&mut app_state, id(3), 10, 0), vec![id(2)]);
+        assert!(app_state.docs.contains_key(&id(3)));
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(
+            parse_tags(" work, ideas,,work ,Ideas "),
+            vec!["work", "ideas", "Ideas"]
+        );
+        let tags = vec!["work".to_string(), "ideas".to_string()];
+        assert!(matches_tags(&tags, &BTreeSet::new()));
+        assert!(matches_tags(&tags, &BTreeSet::from(["work".to_string()])));
+        assert!(!matches_tags(
+            &tags,
+            &BTreeSet::from(["work".to_string(), "home".to_string()])
+        ));
+    }
+}